@@ -1,8 +1,13 @@
 #![feature(duration_constructors)]
 #![feature(type_alias_impl_trait)]
 
-use std::{str::FromStr, sync::Arc};
+use std::{path::Path, str::FromStr, sync::Arc};
 use clap::{Arg, ArgAction};
+use rust_grpc_example::hash_fs::auth::BearerToken;
+use rust_grpc_example::hash_fs::compression::ClientCompression;
+use rust_grpc_example::hash_fs::filesystems::SelectFilesystem;
+use rust_grpc_example::hash_fs::tls::GrpcTlsConfig;
+use tifs::fs::fs_config::MountOption;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -19,7 +24,7 @@ async fn main() -> anyhow::Result<()> {
             Arg::new("device")
                 .value_name("ENDPOINTS")
                 .required(true)
-                .help("all pd endpoints of the tikv cluster, separated by commas (e.g. hash-fs:http://127.0.0.1:50051)")
+                .help("all hash fs server endpoints, separated by commas (e.g. hash-fs:http://10.0.0.1:50051,http://10.0.0.2:50051)")
                 .index(1)
                 .action(ArgAction::Append)
         )
@@ -86,6 +91,7 @@ async fn main() -> anyhow::Result<()> {
     // "hash-fs:http://[::1]:50051"
     let pd_endpoints = endpoints.into_iter()
         .filter_map(|s|s.strip_prefix("hash-fs:").map(String::from))
+        .flat_map(|s|s.split(',').map(String::from).collect::<Vec<_>>())
         .collect::<Vec<_>>();
 
     let options_str = matches.get_many::<String>("options")
@@ -93,28 +99,54 @@ async fn main() -> anyhow::Result<()> {
         v.into_iter().cloned().collect::<Vec<_>>()
     }).unwrap_or(Vec::new());
 
-    let options = tifs::fs::fs_config::MountOption::to_vec(
+    let mut options = tifs::fs::fs_config::MountOption::to_vec(
         options_str.iter().map(|s|s.as_str()));
 
     if pd_endpoints.len() == 0 {
         return Err(anyhow::anyhow!("missing endpoint parameter"));
     }
 
-    let endpoint_str = pd_endpoints.first().unwrap();
-    let grpc_endpoint = tonic::transport::Endpoint::from_str(endpoint_str)
-        .map_err(|err| anyhow::anyhow!("parsing endpoint {} failed: Err: {:?}", endpoint_str, err))?;
-    let mut client = rust_grpc_example::grpc::greeter::greeter_client::GreeterClient::connect(grpc_endpoint.clone()).await?;
+    let tls_config_path = options.iter().find_map(|opt|{
+        if let MountOption::HashFsTls(path) = opt { Some(path) } else { None }
+    });
+    let tls_config = match tls_config_path {
+        Some(path) => Some(GrpcTlsConfig::from_file(Path::new(path))?.client_tls_config()?),
+        None => None,
+    };
+    let mut grpc_endpoints = Vec::new();
+    for endpoint_str in &pd_endpoints {
+        let mut grpc_endpoint = tonic::transport::Endpoint::from_str(endpoint_str)
+            .map_err(|err| anyhow::anyhow!("parsing endpoint {} failed: Err: {:?}", endpoint_str, err))?;
+        if let Some(tls) = &tls_config {
+            grpc_endpoint = grpc_endpoint.tls_config(tls.clone())?;
+        }
+        grpc_endpoints.push(grpc_endpoint);
+    }
+    let token_file_path = options.iter().find_map(|opt|{
+        if let MountOption::HashFsTokenFile(path) = opt { Some(path) } else { None }
+    });
+    let bearer_token = match token_file_path {
+        Some(path) => BearerToken::from_file(Path::new(path))?,
+        None => BearerToken::default(),
+    };
 
-    let request = tonic::Request::new(rust_grpc_example::grpc::greeter::HelloRequest {
-        name: "Tonic".into(),
+    let filesystem_name = options.iter().find_map(|opt|{
+        if let MountOption::HashFsName(name) = opt { Some(name) } else { None }
     });
+    let filesystem = match filesystem_name {
+        Some(name) => SelectFilesystem::new(name)?,
+        None => SelectFilesystem::default(),
+    };
 
-    println!("Sending request to gRPC Server...");
-    let response = client.say_hello(request).await?;
+    let compression = ClientCompression::from_options(&options)?;
 
-    println!("RESPONSE={:?}", response);
+    let hash_fs_client = Arc::new(rust_grpc_example::hash_fs::client::HashFsClient::new(
+        grpc_endpoints, bearer_token, filesystem, compression)?);
 
-    let hash_fs_client = Arc::new(rust_grpc_example::hash_fs::client::HashFsClient::new(grpc_endpoint));
+    let server_info = hash_fs_client.handshake().await?;
+    tracing::info!("connected to hash fs server {} (protocol version {}, features: {:?})",
+        server_info.server_version, server_info.protocol_version, server_info.features);
+    server_info.adapt_mount_options(&mut options)?;
 
     let fs = tifs::fs::tikv_fs::TiFs::construct_hash_fs_client(pd_endpoints, options.clone(), hash_fs_client).await?;
 
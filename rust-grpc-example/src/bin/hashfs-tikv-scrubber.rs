@@ -0,0 +1,95 @@
+use clap::ArgAction;
+use clap::{crate_version, builder::Arg};
+
+use tifs::fs::fs_config::{self};
+use tifs::fs::scrubber::{run_scrubber, ScrubberConfig};
+use tifs::fs::tikv_fs::TiFs;
+
+// Standalone scrubber: verifies all stored blocks against their hashes
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+    tracing_subscriber::fmt()
+                .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+                .try_init()
+                .map_err(|err| anyhow::anyhow!("fail to init tracing subscriber: {}", err))?;
+
+    let matches = clap::builder::Command::new("hashfs-tikv-scrubber")
+        .version(crate_version!())
+        .author("Hexi Lee, Ulrich Hornung")
+        .arg(
+            Arg::new("device")
+                .value_name("ENDPOINTS")
+                .required(true)
+                .help("all pd endpoints of the tikv cluster, separated by commas (e.g. tifs:127.0.0.1:2379)")
+                .index(1)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("options")
+                .value_name("OPTION")
+                .long("option")
+                .short('o')
+                .help("filesystem mount options")
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("rate")
+                .long("rate")
+                .value_name("BLOCKS_PER_SECOND")
+                .value_parser(clap::value_parser!(u64))
+                .help("maximum number of verified blocks per second (0: unlimited)")
+        )
+        .arg(
+            Arg::new("chunk-size")
+                .long("chunk-size")
+                .value_name("KEYS")
+                .value_parser(clap::value_parser!(u32))
+                .help("number of keys scanned per transaction")
+        )
+        .arg(
+            Arg::new("quarantine")
+                .long("quarantine")
+                .action(ArgAction::SetTrue)
+                .help("move blocks that don't match their hash to the quarantine")
+        )
+        .arg(
+            Arg::new("once")
+                .long("once")
+                .action(ArgAction::SetTrue)
+                .help("stop after one complete round over all blocks")
+        )
+        .get_matches();
+
+    let endpoints = matches.get_many::<String>("device").map(|v|{
+        v.into_iter().cloned().collect::<Vec<_>>()
+    }).unwrap_or(Vec::new());
+
+    let pd_endpoints = endpoints.into_iter()
+        .filter_map(|s|s.strip_prefix("tifs:").map(String::from))
+        .collect::<Vec<_>>();
+
+    let options_str = matches.get_many::<String>("options")
+    .map(|v|{
+        v.into_iter().cloned().collect::<Vec<_>>()
+    }).unwrap_or(Vec::new());
+
+    let options = fs_config::MountOption::to_vec(
+        options_str.iter().map(|s|s.as_str()));
+
+    let mut config = ScrubberConfig::default();
+    if let Some(rate) = matches.get_one::<u64>("rate") {
+        config.blocks_per_second = Some(*rate);
+    }
+    if let Some(chunk_size) = matches.get_one::<u32>("chunk-size") {
+        config.chunk_size = *chunk_size;
+    }
+    config.quarantine = matches.get_flag("quarantine");
+    config.single_round = matches.get_flag("once");
+
+    let hash_fs = TiFs::construct_hash_fs_server(pd_endpoints, options).await?;
+    run_scrubber(hash_fs, config).await
+        .map_err(|err| anyhow::anyhow!("scrubber failed: {:?}", err))?;
+
+    Ok(())
+}
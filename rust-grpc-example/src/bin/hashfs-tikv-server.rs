@@ -1,12 +1,21 @@
+use std::path::Path;
+use std::sync::Arc;
+
 use clap::ArgAction;
 use clap::{crate_version, builder::Arg};
 
 use rust_grpc_example::grpc::hash_fs::hash_fs_server::HashFsServer;
+use rust_grpc_example::hash_fs::auth::{AuthInterceptor, Authenticator, BearerTokenAuth, NoAuth};
+use rust_grpc_example::hash_fs::compression::{parse_encodings, DEFAULT_SERVER_COMPRESSION};
+use rust_grpc_example::hash_fs::filesystems::FilesystemsFile;
+use rust_grpc_example::hash_fs::server::HashFsGrpcServer;
+use rust_grpc_example::hash_fs::tls::GrpcTlsConfig;
 use tifs::fs::fs_config::{self};
+use tifs::fs::scrubber::{run_scrubber, ScrubberConfig};
+use tifs::fs::tikv_fs::TiFs;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
 
-use rust_grpc_example::grpc::greeter::greeter_server::GreeterServer;
-
 // Runtime to run our server
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -81,6 +90,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("LOGFILE")
                 .help("log file in server mode (ignored if --foreground is present)")
         )
+        .arg(
+            Arg::new("scrub")
+                .long("scrub")
+                .action(ArgAction::SetTrue)
+                .help("verify all stored blocks of the served filesystems against their hashes in the background")
+        )
+        .arg(
+            Arg::new("scrub-rate")
+                .long("scrub-rate")
+                .value_name("BLOCKS_PER_SECOND")
+                .value_parser(clap::value_parser!(u64))
+                .help("maximum number of blocks verified per second by the scrubber (0: unlimited)")
+        )
+        .arg(
+            Arg::new("scrub-quarantine")
+                .long("scrub-quarantine")
+                .action(ArgAction::SetTrue)
+                .help("move blocks that don't match their hash to the quarantine")
+        )
+        .arg(
+            Arg::new("tls-config")
+                .long("tls-config")
+                .value_name("FILE")
+                .help("toml file with cert_path, key_path and optionally ca_path (enables mTLS) of the gRPC server")
+        )
+        .arg(
+            Arg::new("auth-tokens")
+                .long("auth-tokens")
+                .value_name("FILE")
+                .help("toml file with the bearer tokens of the accepted clients")
+        )
+        .arg(
+            Arg::new("filesystems")
+                .long("filesystems")
+                .value_name("FILE")
+                .help("toml file with additional filesystems served next to the one of the mount options")
+        )
+        .arg(
+            Arg::new("compression")
+                .long("compression")
+                .value_name("ENCODINGS")
+                .default_value(DEFAULT_SERVER_COMPRESSION)
+                .help("accepted and sent gRPC compressions, separated by commas (gzip, zstd or none)")
+        )
         .get_matches();
 
     let endpoints = matches.get_many::<String>("device").map(|v|{
@@ -100,15 +153,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         options_str.iter().map(|s|s.as_str()));
 
     let addr = "[::]:50051".parse()?;
-    let greeter = rust_grpc_example::hash_fs::server::MyGreeter::default();
-    let hash_fs = rust_grpc_example::hash_fs::server::HashFsGrpcServer::new(
-        pd_endpoints, options).await?;
+    let mut filesystems_options = vec![options.clone()];
+    if let Some(path) = matches.get_one::<String>("filesystems") {
+        let file = FilesystemsFile::from_file(Path::new(path))?;
+        filesystems_options.extend(file.filesystems.iter()
+            .map(|entry| entry.mount_options(&options)));
+    }
+    // every served filesystem has its own blocks and scrub progress:
+    if matches.get_flag("scrub") {
+        let mut scrub_config = ScrubberConfig::default();
+        if let Some(rate) = matches.get_one::<u64>("scrub-rate") {
+            scrub_config.blocks_per_second = Some(*rate);
+        }
+        scrub_config.quarantine = matches.get_flag("scrub-quarantine");
+        for (index, fs_options) in filesystems_options.iter().enumerate() {
+            let scrub_fs = TiFs::construct_hash_fs_server(
+                pd_endpoints.clone(), fs_options.clone()).await?;
+            let scrub_config = scrub_config.clone();
+            tokio::spawn(async move {
+                if let Err(err) = run_scrubber(scrub_fs, scrub_config).await {
+                    tracing::error!("scrubber of filesystem {index} stopped: {err:?}");
+                }
+            });
+        }
+    }
+
+    let compression = parse_encodings(
+        matches.get_one::<String>("compression").map(|s| s.as_str()).unwrap_or("none"))?;
+    let hash_fs = HashFsGrpcServer::with_filesystems(
+        pd_endpoints, filesystems_options).await?
+        .with_compression(compression);
+
+    let mut server = Server::builder();
+    if let Some(path) = matches.get_one::<String>("tls-config") {
+        let tls = GrpcTlsConfig::from_file(Path::new(path))?;
+        server = server.tls_config(tls.server_tls_config()?)?;
+    } else {
+        tracing::warn!("no --tls-config given, the gRPC connections are not encrypted");
+    }
+
+    let auth: Arc<dyn Authenticator> = match matches.get_one::<String>("auth-tokens") {
+        Some(path) => Arc::new(BearerTokenAuth::from_file(Path::new(path))?),
+        None => {
+            tracing::warn!("no --auth-tokens given, all clients are accepted");
+            Arc::new(NoAuth)
+        }
+    };
+
+    // standard gRPC health service, used by the clients for failover:
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter.set_serving::<HashFsServer<HashFsGrpcServer>>().await;
 
     tracing::error!("v - Starting gRPC Server...");
     println!("Starting gRPC Server...");
-    Server::builder()
-        .add_service(GreeterServer::new(greeter))
-        .add_service(HashFsServer::new(hash_fs))
+    server
+        .add_service(health_service)
+        .add_service(InterceptedService::new(hash_fs.into_service(), AuthInterceptor::new(auth)))
         .serve(addr)
         .await?;
 
@@ -0,0 +1,199 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use super::id_map::IdMap;
+
+const AUTHORIZATION_HEADER: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Identity of an authenticated client. Added to the request extensions
+/// by the AuthInterceptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub name: String,
+    pub id_map: IdMap,
+    /// Names of the accessible filesystems. Empty allows all.
+    pub filesystems: Vec<String>,
+}
+
+impl ClientIdentity {
+    /// Identity of all clients of a server without authentication.
+    pub fn anonymous() -> Self {
+        Self { name: "anonymous".into(), id_map: IdMap::trusted(), filesystems: Vec::new() }
+    }
+
+    pub fn may_access(&self, filesystem: &str) -> bool {
+        self.filesystems.is_empty() || self.filesystems.iter().any(|name| name == filesystem)
+    }
+}
+
+/// Decides based on the request metadata which client sent a request.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<ClientIdentity, Status>;
+}
+
+/// Accepts all requests. Used when the server has no token file.
+pub struct NoAuth;
+
+impl Authenticator for NoAuth {
+    fn authenticate(&self, _metadata: &MetadataMap) -> Result<ClientIdentity, Status> {
+        Ok(ClientIdentity::anonymous())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenEntry {
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub filesystems: Vec<String>,
+    #[serde(flatten)]
+    pub id_map: IdMap,
+}
+
+/// Content of the token file of the server:
+///
+/// ```toml
+/// [[tokens]]
+/// name = "workstation"
+/// token = "<secret>"
+/// # optional, all filesystems of the server by default:
+/// filesystems = ["projects"]
+/// # optional, see IdMap:
+/// root_squash = true
+/// all_squash = false
+/// anonuid = 65534
+/// anongid = 65534
+/// uids = ["1000-1999"]
+/// gids = ["100"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TokenFile {
+    #[serde(default)]
+    pub tokens: Vec<TokenEntry>,
+}
+
+/// Accepts requests carrying one of the configured tokens
+/// in an "authorization: Bearer <token>" header.
+pub struct BearerTokenAuth {
+    tokens: Vec<TokenEntry>,
+}
+
+impl BearerTokenAuth {
+    pub fn new(file: TokenFile) -> Self {
+        Self { tokens: file.tokens }
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("reading token file {path:?} failed: {err}"))?;
+        let file = toml::from_str::<TokenFile>(&content)?;
+        if file.tokens.is_empty() {
+            anyhow::bail!("token file {path:?} contains no tokens");
+        }
+        Ok(Self::new(file))
+    }
+}
+
+impl Authenticator for BearerTokenAuth {
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<ClientIdentity, Status> {
+        let Some(value) = metadata.get(AUTHORIZATION_HEADER) else {
+            return Err(Status::unauthenticated("bearer token is required"));
+        };
+        let token = value.to_str().ok()
+            .and_then(|v| v.strip_prefix(BEARER_PREFIX))
+            .ok_or_else(|| Status::unauthenticated("malformed authorization header"))?;
+        self.tokens.iter()
+            .find(|entry| constant_time_eq(entry.token.as_bytes(), token.as_bytes()))
+            .map(|entry| ClientIdentity {
+                name: entry.name.clone(),
+                id_map: entry.id_map.clone(),
+                filesystems: entry.filesystems.clone(),
+            })
+            .ok_or_else(|| Status::unauthenticated("unknown bearer token"))
+    }
+}
+
+/// Doesn't leak the length of the matching prefix through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Server side interceptor that rejects requests of unauthenticated clients.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    auth: Arc<dyn Authenticator>,
+}
+
+impl AuthInterceptor {
+    pub fn new(auth: Arc<dyn Authenticator>) -> Self {
+        Self { auth }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let identity = self.auth.authenticate(request.metadata())?;
+        request.extensions_mut().insert(identity);
+        Ok(request)
+    }
+}
+
+/// Client side interceptor that adds the bearer token to all requests.
+#[derive(Clone, Default)]
+pub struct BearerToken {
+    value: Option<MetadataValue<Ascii>>,
+}
+
+impl BearerToken {
+    pub fn new(token: &str) -> anyhow::Result<Self> {
+        let value = format!("{BEARER_PREFIX}{token}").parse()
+            .map_err(|err| anyhow::anyhow!("invalid bearer token: {err}"))?;
+        Ok(Self { value: Some(value) })
+    }
+
+    /// Reads the token from the first line of the file.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("reading token file {path:?} failed: {err}"))?;
+        Self::new(content.lines().next().unwrap_or("").trim())
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.value {
+            request.metadata_mut().insert(AUTHORIZATION_HEADER, value.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_token_auth_accepts_only_known_tokens() {
+        let auth = BearerTokenAuth::new(toml::from_str(r#"
+            [[tokens]]
+            name = "workstation"
+            token = "secret"
+        "#).unwrap());
+
+        let mut request = BearerToken::new("secret").unwrap().call(Request::new(())).unwrap();
+        assert_eq!(auth.authenticate(request.metadata()).unwrap().name, "workstation");
+
+        request.metadata_mut().insert(AUTHORIZATION_HEADER, "Bearer secreT".parse().unwrap());
+        assert!(auth.authenticate(request.metadata()).is_err());
+        assert!(auth.authenticate(&MetadataMap::new()).is_err());
+    }
+}
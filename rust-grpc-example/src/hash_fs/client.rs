@@ -1,21 +1,57 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem;
 use std::ops::{Deref, Range};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::SystemTime;
 
 use bytestring::ByteString;
 use fuser::TimeOrNow;
+use futures::{SinkExt, StreamExt};
 use num_bigint::BigUint;
 
-use crate::grpc::hash_fs::{self as grpc_fs, InitRq, MetaStaticReadRq};
+use crate::grpc::hash_fs::{self as grpc_fs, InitRq, MetaMutableReadRq, MetaStaticReadRq};
+use crate::hash_fs::auth::BearerToken;
+use crate::hash_fs::compression::{encoding_name, feature_name, ClientCompression};
+use crate::hash_fs::endpoints::{backoff, EndpointSet, TrackedChannel};
+use crate::hash_fs::filesystems::SelectFilesystem;
+use crate::hash_fs::handshake::{negotiate_protocol_version, ServerInfo, FEATURES, MIN_PROTOCOL_VERSION, PAGE_CURSOR_VERSION, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER, WRITE_BLOCKS_ROUNDS_VERSION};
 use crate::utils::object_pool::{HandedOutPoolElement, Pool};
-use tifs::fs::hash_fs_interface::{BlockIndex, GotOrMade, HashFsError, HashFsInterface, HashFsResult};
-use tifs::fs::inode::{DirectoryItem, InoAccessTime, InoDescription, InoSize, InoStorageFileAttr, ParentStorageIno, StorageDirItem, StorageDirItemKind, StorageFilePermission, StorageIno, TiFsHash};
-use tifs::fs::meta::MetaStatic;
+use tifs::fs::hash_fs_interface::{BlockDataSource, BlockDataStream, BlockIndex, ChangeEventStream, DirectoryPage, GotOrMade, HashFsError, HashFsInterface, HashFsResult, InoAllAttributes, MissingBlocksProvider, NewBlockStream, RenameMode};
+use tifs::fs::inode::{DirectoryItem, InoAccessTime, InoChangeIterationId, InoDescription, InoSize, InoStorageFileAttr, ParentStorageIno, StorageDirItem, StorageDirItemKind, StorageFilePermission, StorageIno, TiFsHash};
+use tifs::fs::meta::{MetaMutable, MetaStatic};
+use tifs::fs::fs_stats::{FileStats, FsStats};
+use tifs::fs::quota::{QuotaLimits, QuotaReport, QuotaTarget};
 use tokio::time::sleep;
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+
+type RawGrpcClient = grpc_fs::hash_fs_client::HashFsClient<
+    InterceptedService<TrackedChannel, ClientInterceptor>>;
+
+/// Adds the bearer token, the selected filesystem and the negotiated
+/// protocol version to all requests.
+#[derive(Clone)]
+struct ClientInterceptor {
+    bearer_token: BearerToken,
+    filesystem: SelectFilesystem,
+    /// Set by the handshake, shared by the clients of all pools.
+    protocol_version: Arc<OnceLock<u32>>,
+}
+
+impl Interceptor for ClientInterceptor {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        let request = self.bearer_token.call(request)?;
+        let mut request = self.filesystem.call(request)?;
+        if let Some(version) = self.protocol_version.get() {
+            request.metadata_mut().insert(PROTOCOL_VERSION_HEADER, (*version).into());
+        }
+        Ok(request)
+    }
+}
 
-type RawGrpcClient = grpc_fs::hash_fs_client::HashFsClient<tonic::transport::Channel>;
+/// Block messages of inode_write_blocks that may wait for being sent.
+const WRITE_BLOCKS_BUFFER: usize = 16;
 
 fn handle_error(err_opt: &Option<grpc_fs::HashFsError>) -> HashFsResult<()> {
     if let Some(err) = err_opt {
@@ -56,45 +92,163 @@ fn parse_all_attrs(
 }
 
 pub struct HashFsClient {
-    grpc_endpoint: tonic::transport::Endpoint,
-    grpc_client_pool: Pool<RawGrpcClient>,
+    grpc_endpoints: Arc<EndpointSet>,
+    interceptor: ClientInterceptor,
+    /// One pool per endpoint, same order.
+    grpc_client_pools: Vec<Pool<RawGrpcClient>>,
+    compression: ClientCompression,
+    /// Set by the handshake if the server supports the configured compression.
+    negotiated_compression: OnceLock<CompressionEncoding>,
 }
 
 impl HashFsClient {
-    pub fn new(grpc_endpoint: tonic::transport::Endpoint) -> Self {
-        Self {
-            grpc_endpoint: grpc_endpoint.clone(),
-            grpc_client_pool: Pool::new(),
+    /// TLS is configured at the endpoints. Requests are distributed over
+    /// the healthy endpoints, unhealthy ones are only used if no other is left.
+    pub fn new(
+        grpc_endpoints: Vec<tonic::transport::Endpoint>,
+        bearer_token: BearerToken,
+        filesystem: SelectFilesystem,
+        compression: ClientCompression,
+    ) -> anyhow::Result<Self> {
+        let grpc_endpoints = Arc::new(EndpointSet::new(grpc_endpoints)?);
+        grpc_endpoints.spawn_health_checks();
+        Ok(Self {
+            grpc_client_pools: (0..grpc_endpoints.len()).map(|_| Pool::new()).collect(),
+            grpc_endpoints,
+            interceptor: ClientInterceptor {
+                bearer_token,
+                filesystem,
+                protocol_version: Arc::new(OnceLock::new()),
+            },
+            compression,
+            negotiated_compression: OnceLock::new(),
+        })
+    }
+
+    /// Client for calls transferring block data. Only these accept compressed
+    /// responses, the server decides per call whether it compresses them.
+    /// Requests are compressed if their payload reaches the threshold,
+    /// streamed ones (payload_size None) always.
+    fn block_data_client(&self, client: &RawGrpcClient, payload_size: Option<usize>) -> RawGrpcClient {
+        let mut client = client.clone();
+        if let Some(encoding) = self.negotiated_compression.get() {
+            client = client.accept_compressed(*encoding);
+            if payload_size.map_or(true, |size| size >= self.compression.threshold) {
+                client = client.send_compressed(*encoding);
+            }
         }
+        client
     }
 
-    async fn connect_with_retries(grpc_endpoint: tonic::transport::Endpoint) -> core::result::Result<RawGrpcClient, tonic::transport::Error> {
+    /// Tries all endpoints, the healthy ones first, with increasing delays.
+    async fn connect_with_retries(
+        &self,
+    ) -> core::result::Result<(usize, RawGrpcClient), tonic::transport::Error> {
         let start_time = std::time::Instant::now();
+        let mut attempts = 0;
         loop {
-            let hash_fs_client = RawGrpcClient::connect(grpc_endpoint.clone()).await;
-            match hash_fs_client {
-                Ok(client) => return Ok(client),
-                Err(err) => {
-                    let time_passed = start_time.elapsed();
-                    if time_passed > std::time::Duration::from_mins(10) {
-                        tracing::error!("failed to connect to grpc sever. timeout passed");
-                        return  Err(err);
+            let mut last_err = None;
+            for index in self.grpc_endpoints.candidates() {
+                let state = self.grpc_endpoints.get(index);
+                match state.endpoint.connect().await {
+                    Ok(channel) => return Ok((index, RawGrpcClient::with_interceptor(
+                        TrackedChannel::new(channel, state.clone()), self.interceptor.clone()))),
+                    Err(err) => {
+                        tracing::warn!("failed to connect to grpc server {}: {err}", state.endpoint.uri());
+                        state.mark_failed();
+                        last_err = Some(err);
                     }
-                    tracing::warn!("failed to connect to grpc sever. trying again ...");
-                    sleep(std::time::Duration::from_secs(1)).await;
                 }
             }
+            if let Some(err) = last_err {
+                let time_passed = start_time.elapsed();
+                if time_passed > std::time::Duration::from_mins(10) {
+                    tracing::error!("failed to connect to grpc sever. timeout passed");
+                    return Err(err);
+                }
+            }
+            tracing::warn!("failed to connect to any grpc sever. trying again ...");
+            sleep(backoff(attempts)).await;
+            attempts += 1;
         }
     }
 
+    /// Free clients of healthy endpoints are reused round robin.
+    /// New connections are only made if none is free. Without a healthy endpoint
+    /// the free clients of the others are reused, instead of connecting per request.
     async fn lock_grpc<'pool>(&'pool self) -> core::result::Result<HandedOutPoolElement<'pool, RawGrpcClient>, tonic::transport::Error> {
-        let next = self.grpc_client_pool.get_one_free();
-        if let Some(next) = next {
-            return Ok(next);
+        let candidates = self.grpc_endpoints.candidates();
+        // candidates start with the healthy endpoints:
+        let any_healthy = candidates.first()
+            .map(|index| self.grpc_endpoints.get(*index).is_healthy())
+            .unwrap_or(false);
+        for index in candidates {
+            if any_healthy && !self.grpc_endpoints.get(index).is_healthy() {
+                break;
+            }
+            if let Some(next) = self.grpc_client_pools[index].get_one_free() {
+                return Ok(next);
+            }
+        }
+
+        let (index, new_one) = self.connect_with_retries().await?;
+        Ok(self.grpc_client_pools[index].add_new_busy_to_pool(new_one))
+    }
+
+    /// Has to succeed before the filesystem is used.
+    /// Fails if client and server have no protocol version in common.
+    /// Version negotiated by the handshake, the oldest supported one before.
+    fn protocol_version(&self) -> u32 {
+        self.interceptor.protocol_version.get().copied().unwrap_or(MIN_PROTOCOL_VERSION)
+    }
+
+    /// Servers before PAGE_CURSOR_VERSION don't return the cursor of the next
+    /// page, a full page then continues after its last entry.
+    fn next_page_cursor(
+        &self,
+        next_cursor: Option<String>,
+        last_name: Option<&String>,
+        page_len: usize,
+        limit: u32,
+    ) -> Option<ByteString> {
+        if self.protocol_version() >= PAGE_CURSOR_VERSION {
+            return next_cursor.map(Into::into);
         }
+        (page_len >= limit as usize).then(|| last_name.cloned().map(Into::into)).flatten()
+    }
 
-        let new_one = Self::connect_with_retries(self.grpc_endpoint.clone()).await?;
-        Ok(self.grpc_client_pool.add_new_busy_to_pool(new_one))
+    pub async fn handshake(&self) -> anyhow::Result<ServerInfo> {
+        let mut rq = grpc_fs::HandshakeRq::default();
+        rq.protocol_version = PROTOCOL_VERSION;
+        rq.min_protocol_version = MIN_PROTOCOL_VERSION;
+        rq.features = FEATURES.iter().map(|f| f.to_string()).collect();
+        if let Some(encoding) = self.compression.encoding {
+            rq.features.push(feature_name(encoding));
+        }
+        rq.client_version = env!("CARGO_PKG_VERSION").into();
+        let rs = self.lock_grpc().await?.handshake(rq).await?.into_inner();
+        handle_error(&rs.error)
+            .map_err(|err| anyhow::anyhow!("handshake failed: {err:?}"))?;
+        let Some(protocol_version) = negotiate_protocol_version(
+            rs.min_protocol_version, rs.protocol_version) else {
+            anyhow::bail!("hash fs server {} speaks protocol versions {}..={}, the client {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}",
+                rs.server_version, rs.min_protocol_version, rs.protocol_version);
+        };
+        let _ = self.interceptor.protocol_version.set(protocol_version);
+        if let Some(encoding) = self.compression.encoding {
+            if rs.features.contains(&feature_name(encoding)) {
+                let _ = self.negotiated_compression.set(encoding);
+            } else {
+                tracing::warn!("hash fs server {} doesn't support {} compression, sending uncompressed",
+                    rs.server_version, encoding_name(encoding));
+            }
+        }
+        Ok(ServerInfo {
+            protocol_version,
+            server_version: rs.server_version,
+            features: rs.features,
+            meta_static: rs.meta_static.map(Into::into),
+        })
     }
 }
 
@@ -121,6 +275,16 @@ impl HashFsInterface for HashFsClient {
         Ok(v.into())
     }
 
+    async fn meta_mutable_read(&self) -> HashFsResult<MetaMutable> {
+        let rs = self.lock_grpc().await?
+            .meta_mutable_read(MetaMutableReadRq{}).await?.into_inner();
+        handle_error(&rs.error)?;
+        let Some(v) = rs.value else {
+            return Err(HashFsError::GrpcMessageIncomplete);
+        };
+        Ok(v.into())
+    }
+
     async fn directory_read_children(&self, dir_ino: StorageIno) -> HashFsResult<Vec<DirectoryItem>> {
         let rs = self.lock_grpc().await?
         .directory_read_children(grpc_fs::DirectoryReadChildrenRq{
@@ -135,6 +299,64 @@ impl HashFsInterface for HashFsClient {
         }).collect::<Vec<_>>())
     }
 
+    async fn directory_read_children_page(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<DirectoryItem>> {
+        let mut rq = grpc_fs::DirectoryReadChildrenPageRq::default();
+        rq.dir_ino = Some(dir_ino.into());
+        rq.start_after = start_after.map(|n|n.to_string());
+        rq.limit = limit;
+        let rs = self.lock_grpc().await?
+            .directory_read_children_page(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        let children = rs.value.into_iter().filter_map(|item|{
+            Some(DirectoryItem{
+                ino: item.ino.clone()?.into(),
+                typ: item.typ().into(),
+                name: item.name,
+            })
+        }).collect::<Vec<_>>();
+        let next_cursor = self.next_page_cursor(rs.next_cursor,
+            children.last().map(|item| &item.name), children.len(), limit);
+        Ok((children, next_cursor))
+    }
+
+    async fn directory_read_children_with_attributes(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>> {
+        let mut rq = grpc_fs::DirectoryReadChildrenWithAttributesRq::default();
+        rq.dir_ino = Some(dir_ino.into());
+        rq.start_after = start_after.map(|n|n.to_string());
+        rq.limit = limit;
+        let rs = self.lock_grpc().await?
+            .directory_read_children_with_attributes(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        let mut result = Vec::with_capacity(rs.value.len());
+        for entry in rs.value {
+            let Some(item) = entry.item else {
+                return Err(HashFsError::GrpcMessageIncomplete);
+            };
+            let Some(ino) = item.ino.clone() else {
+                return Err(HashFsError::GrpcMessageIncomplete);
+            };
+            let all = entry.all.map(|all| parse_all_attrs(Some(all))).transpose()?;
+            result.push((DirectoryItem{
+                ino: ino.into(),
+                typ: item.typ().into(),
+                name: item.name,
+            }, all));
+        }
+        let next_cursor = self.next_page_cursor(rs.next_cursor,
+            result.last().map(|(item, _)| &item.name), result.len(), limit);
+        Ok((result, next_cursor))
+    }
+
     async fn directory_add_child_checked_existing_inode(
         &self,
         parent: ParentStorageIno,
@@ -223,12 +445,14 @@ impl HashFsInterface for HashFsClient {
         child_name: ByteString,
         new_parent: ParentStorageIno,
         new_child_name: ByteString,
+        mode: RenameMode,
     ) -> HashFsResult<()> {
         let mut rq = grpc_fs::DirectoryRenameChildRq::default();
         rq.parent = Some(parent.into());
         rq.child_name = child_name.to_string();
         rq.new_parent =  Some(new_parent.into());
         rq.new_child_name = new_child_name.to_string();
+        rq.flags = mode.flags();
         let rs = self.lock_grpc().await?
             .directory_rename_child(rq).await?.into_inner();
         handle_error(&rs.error)?;
@@ -284,6 +508,20 @@ impl HashFsInterface for HashFsClient {
         parse_all_attrs(mem::take(&mut rs.all))
     }
 
+    async fn inode_get_change_iteration_ids(
+        &self,
+        inos: &[StorageIno],
+    ) -> HashFsResult<HashMap<StorageIno, InoChangeIterationId>> {
+        let mut rq = grpc_fs::InodeGetChangeIterationIdsRq::default();
+        rq.inos = inos.iter().map(|ino| (*ino).into()).collect();
+        let rs = self.lock_grpc().await?
+            .inode_get_change_iteration_ids(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(rs.ids.into_iter().map(|(ino, id)|{
+            (StorageIno(ino), InoChangeIterationId(id.into()))
+        }).collect())
+    }
+
     async fn inode_set_all_attributes(
         &self,
         ino: StorageIno,
@@ -311,7 +549,7 @@ impl HashFsInterface for HashFsClient {
         rq.crtime = crtime.map(Into::into);
         rq.chgtime = chgtime.map(Into::into);
         rq.bkuptime = bkuptime.map(Into::into);
-        rq.flags = flags;
+        rq.flags = mode.flags();
         let rs = self.lock_grpc().await?
             .inode_set_all_attributes(rq).await?.into_inner();
         handle_error(&rs.error)?;
@@ -404,15 +642,67 @@ impl HashFsInterface for HashFsClient {
         }).collect::<BTreeMap<_, _>>())
     }
 
+    async fn hb_get_reference_counts(
+        &self,
+        hashes: &[TiFsHash],
+        source: &BlockDataSource,
+    ) -> HashFsResult<HashMap<TiFsHash, u64>> {
+        let mut rq = grpc_fs::HbGetReferenceCountsRq::default();
+        rq.hashes = hashes.iter().map(|h| grpc_fs::Hash{data: h.clone()}).collect();
+        rq.ino = Some(source.ino.into());
+        rq.ranges = source.block_ranges.iter().cloned().map(Into::into).collect();
+        let rs = self.lock_grpc().await?.hb_get_reference_counts(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(rs.counts.into_iter().filter_map(|c|{
+            Some((c.hash?.data, c.count))
+        }).collect::<HashMap<_,_>>())
+    }
+
+    async fn inode_remove_block_range(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        collapse: bool,
+    ) -> HashFsResult<()> {
+        let mut rq = grpc_fs::InodeRemoveBlockRangeRq::default();
+        rq.ino = Some(ino.into());
+        rq.block_range = Some(block_range.into());
+        rq.collapse = collapse;
+        let rs = self.lock_grpc().await?
+            .inode_remove_block_range(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(())
+    }
+
+    async fn inode_seek_block_mapping(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        mapped: bool,
+    ) -> HashFsResult<Option<BlockIndex>> {
+        let mut rq = grpc_fs::InodeSeekBlockMappingRq::default();
+        rq.ino = Some(ino.into());
+        rq.block_range = Some(block_range.into());
+        rq.mapped = mapped;
+        let rs = self.lock_grpc().await?
+            .inode_seek_block_mapping(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(rs.found.map(Into::into))
+    }
+
     async fn hb_get_block_data_by_hashes(
         &self,
         hashes: &HashSet<&TiFsHash>,
+        source: &BlockDataSource,
     ) -> HashFsResult<HashMap<TiFsHash, Arc<Vec<u8>>>> {
         let mut rq = grpc_fs::HbGetBlockDataByHashesRq::default();
         rq.hashes = hashes.iter().map(|h|{
             grpc_fs::Hash{ data: h.to_vec() }
         }).collect::<Vec<_>>();
-        let rs = self.lock_grpc().await?
+        rq.ino = Some(source.ino.into());
+        rq.ranges = source.block_ranges.iter().cloned().map(Into::into).collect();
+        let grpc = self.lock_grpc().await?;
+        let rs = self.block_data_client(&grpc, Some(0))
             .hb_get_block_data_by_hashes(rq).await?.into_inner();
         handle_error(&rs.error)?;
         Ok(rs.block_data.into_iter().filter_map(|mut d|{
@@ -424,6 +714,30 @@ impl HashFsInterface for HashFsClient {
         }).collect::<HashMap<_,_>>())
     }
 
+    async fn hb_get_block_data_by_hashes_stream(
+        &self,
+        hashes: Vec<TiFsHash>,
+        source: BlockDataSource,
+    ) -> HashFsResult<BlockDataStream> {
+        let mut rq = grpc_fs::HbGetBlockDataByHashesStreamRq::default();
+        rq.hashes = hashes.into_iter().map(|data|{
+            grpc_fs::Hash{ data }
+        }).collect::<Vec<_>>();
+        rq.ino = Some(source.ino.into());
+        rq.ranges = source.block_ranges.into_iter().map(Into::into).collect();
+        let grpc = self.lock_grpc().await?;
+        let stream = self.block_data_client(&grpc, Some(0))
+            .hb_get_block_data_by_hashes_stream(rq).await?.into_inner();
+        Ok(Box::pin(stream.map(|r| {
+            let rs = r?;
+            handle_error(&rs.error)?;
+            let Some(grpc_fs::HashBlockData{hash: Some(hash), data}) = rs.block_data else {
+                return Err(HashFsError::GrpcMessageIncomplete);
+            };
+            Ok((hash.data, Arc::new(data)))
+        })))
+    }
+
     async fn file_get_hash(&self, ino: StorageIno) -> HashFsResult<Vec<u8>> {
         let mut rq = grpc_fs::FileGetHashRq::default();
         rq.ino = Some(ino.into());
@@ -436,6 +750,18 @@ impl HashFsInterface for HashFsClient {
         Ok(hash.data)
     }
 
+    async fn file_get_merkle_hash(&self, ino: StorageIno) -> HashFsResult<TiFsHash> {
+        let mut rq = grpc_fs::FileGetMerkleHashRq::default();
+        rq.ino = Some(ino.into());
+        let rs = self.lock_grpc().await?
+            .file_get_merkle_hash(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        let Some(hash) = rs.hash else {
+            return Err(HashFsError::GrpcMessageIncomplete);
+        };
+        Ok(hash.data)
+    }
+
     async fn file_read_block_hashes(
         &self,
         ino: StorageIno,
@@ -482,6 +808,7 @@ impl HashFsInterface for HashFsClient {
             return Ok(());
         }
 
+        let payload_size = blocks.iter().map(|(_, data)| data.len()).sum();
         let mut rq = grpc_fs::HbUploadNewBlockRq::default();
         rq.blocks = blocks.iter().map(|(h, data)|{
             grpc_fs::HashBlockData {
@@ -489,12 +816,32 @@ impl HashFsInterface for HashFsClient {
                 data: data.deref().clone()
             }
         }).collect();
-        let rs = self.lock_grpc().await?
+        let grpc = self.lock_grpc().await?;
+        let rs = self.block_data_client(&grpc, Some(payload_size))
             .hb_upload_new_block(rq).await?.into_inner();
         handle_error(&rs.error)?;
         Ok(())
     }
 
+    async fn hb_upload_new_block_stream(
+        &self,
+        blocks: NewBlockStream,
+    ) -> HashFsResult<()> {
+        let rq = blocks.map(|(hash, data)|{
+            grpc_fs::HbUploadNewBlockStreamRq {
+                block: Some(grpc_fs::HashBlockData {
+                    hash: Some(grpc_fs::Hash{data: hash}),
+                    data: Arc::unwrap_or_clone(data),
+                }),
+            }
+        });
+        let grpc = self.lock_grpc().await?;
+        let rs = self.block_data_client(&grpc, None)
+            .hb_upload_new_block_stream(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(())
+    }
+
     async fn inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
         &self,
         ino: StorageIno,
@@ -519,6 +866,76 @@ impl HashFsInterface for HashFsClient {
         Ok(())
     }
 
+    async fn inode_write_blocks(
+        &self,
+        ino: StorageIno,
+        blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+        mut missing_data: MissingBlocksProvider,
+    ) -> HashFsResult<()> {
+        let mut rq = grpc_fs::InodeWriteBlocksRq::default();
+        rq.ino = Some(ino.into());
+        rq.blocks = blocks.iter().map(|(h,l,ids)|{
+            grpc_fs::HashBlockAddresses {
+                hash: Some(grpc_fs::Hash { data: (*h).clone() }),
+                block_actual_length: *l,
+                block_ids: ids.iter().cloned().map(Into::into).collect(),
+            }
+        }).collect();
+        // older servers send the final response right after the first data round:
+        let rounds = self.protocol_version() >= WRITE_BLOCKS_ROUNDS_VERSION;
+        let (mut sender, requests) = futures::channel::mpsc::channel(WRITE_BLOCKS_BUFFER);
+        // fits into the buffer of the channel:
+        let _ = sender.try_send(rq);
+        let grpc = self.lock_grpc().await?;
+        let mut responses = self.block_data_client(&grpc, None)
+            .inode_write_blocks(requests).await?.into_inner();
+
+        let mut data_sent = false;
+        loop {
+            let Some(rs) = responses.message().await? else {
+                return Err(HashFsError::GrpcMessageIncomplete);
+            };
+            handle_error(&rs.error)?;
+            if rs.finished || (!rounds && data_sent) {
+                return Ok(());
+            }
+            let missing = rs.missing.into_iter().map(|h| h.data).collect();
+            let mut data = missing_data(missing);
+            while let Some((hash, block)) = data.next().await {
+                let mut rq = grpc_fs::InodeWriteBlocksRq::default();
+                rq.data = Some(grpc_fs::HashBlockData {
+                    hash: Some(grpc_fs::Hash{data: hash}),
+                    data: Arc::unwrap_or_clone(block),
+                });
+                if sender.send(rq).await.is_err() {
+                    // the server stopped receiving, its response tells why
+                    break;
+                }
+            }
+            // ends the data of this response:
+            let _ = sender.send(grpc_fs::InodeWriteBlocksRq::default()).await;
+            data_sent = true;
+        }
+    }
+
+    async fn inode_set_blocks_from_hashes(
+        &self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+        hashes: &[TiFsHash],
+        truncate: bool,
+    ) -> HashFsResult<()> {
+        let mut rq = grpc_fs::InodeSetBlocksFromHashesRq::default();
+        rq.ino = Some(ino.into());
+        rq.first_block = Some(first_block.into());
+        rq.hashes = hashes.iter().map(|h| grpc_fs::Hash{data: h.clone()}).collect();
+        rq.truncate = truncate;
+        let rs = self.lock_grpc().await?
+            .inode_set_blocks_from_hashes(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(())
+    }
+
     async fn snapshot_create(&self, name: ByteString) -> HashFsResult<GotOrMade<StorageDirItem>> {
         let mut rq = grpc_fs::SnapshotCreateRq::default();
         rq.name = name.to_string();
@@ -535,4 +952,62 @@ impl HashFsInterface for HashFsClient {
         }
 
     }
+
+    async fn quota_set(&self, target: QuotaTarget, limits: QuotaLimits) -> HashFsResult<()> {
+        let mut rq = grpc_fs::QuotaSetRq::default();
+        rq.target = Some(target.into());
+        rq.limits = Some(limits.into());
+        let rs = self.lock_grpc().await?.quota_set(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        Ok(())
+    }
+
+    async fn quota_list(&self) -> HashFsResult<Vec<QuotaReport>> {
+        let rq = grpc_fs::QuotaListRq::default();
+        let rs = self.lock_grpc().await?.quota_list(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        rs.value.into_iter().map(|report| report.try_into()).collect()
+    }
+
+    async fn quota_report(&self, target: QuotaTarget) -> HashFsResult<Option<QuotaReport>> {
+        let mut rq = grpc_fs::QuotaReportRq::default();
+        rq.target = Some(target.into());
+        let rs = self.lock_grpc().await?.quota_report(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        rs.value.map(|report| report.try_into()).transpose()
+    }
+
+    async fn fs_stats(&self) -> HashFsResult<FsStats> {
+        let rq = grpc_fs::FsStatsRq::default();
+        let rs = self.lock_grpc().await?.fs_stats(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        let Some(v) = rs.value else {
+            return Err(HashFsError::GrpcMessageIncomplete);
+        };
+        Ok(v.into())
+    }
+
+    async fn file_stats(&self, ino: StorageIno) -> HashFsResult<FileStats> {
+        let mut rq = grpc_fs::FileStatsRq::default();
+        rq.ino = Some(ino.into());
+        let rs = self.lock_grpc().await?.file_stats(rq).await?.into_inner();
+        handle_error(&rs.error)?;
+        let Some(v) = rs.value else {
+            return Err(HashFsError::GrpcMessageIncomplete);
+        };
+        Ok(v.into())
+    }
+
+    async fn watch_changes(&self) -> HashFsResult<ChangeEventStream> {
+        let rq = grpc_fs::WatchChangesRq::default();
+        let stream = self.lock_grpc().await?.watch_changes(rq).await?.into_inner();
+        Ok(Box::pin(stream.map(|r| {
+            let rs = r?;
+            handle_error(&rs.error)?;
+            let Some(event) = rs.event else {
+                return Err(HashFsError::GrpcMessageIncomplete);
+            };
+            Ok(event.into())
+        })))
+    }
 }
@@ -0,0 +1,99 @@
+use parse_size::parse_size;
+use tifs::fs::fs_config::MountOption;
+use tonic::codec::CompressionEncoding;
+
+/// Uploads with less block data are sent uncompressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4 << 10;
+
+/// Encodings compressed by the server by default, if the client accepts them.
+pub const DEFAULT_SERVER_COMPRESSION: &str = "gzip,zstd";
+
+pub fn encoding_name(encoding: CompressionEncoding) -> &'static str {
+    match encoding {
+        CompressionEncoding::Gzip => "gzip",
+        CompressionEncoding::Zstd => "zstd",
+    }
+}
+
+/// "gzip", "zstd" or "none".
+pub fn parse_encoding(name: &str) -> anyhow::Result<Option<CompressionEncoding>> {
+    match name.trim() {
+        "none" => Ok(None),
+        "gzip" => Ok(Some(CompressionEncoding::Gzip)),
+        "zstd" => Ok(Some(CompressionEncoding::Zstd)),
+        other => anyhow::bail!("unsupported compression {other:?} (gzip|zstd|none)"),
+    }
+}
+
+/// Comma separated list of encodings, like "gzip,zstd".
+pub fn parse_encodings(names: &str) -> anyhow::Result<Vec<CompressionEncoding>> {
+    let mut encodings = Vec::new();
+    for name in names.split(',') {
+        if let Some(encoding) = parse_encoding(name)? {
+            encodings.push(encoding);
+        }
+    }
+    Ok(encodings)
+}
+
+/// Handshake feature of a server that accepts and sends the encoding.
+pub fn feature_name(encoding: CompressionEncoding) -> String {
+    format!("compression_{}", encoding_name(encoding))
+}
+
+/// Compression of the block data calls of a client.
+/// Metadata calls are never compressed.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCompression {
+    pub encoding: Option<CompressionEncoding>,
+    pub threshold: usize,
+}
+
+impl ClientCompression {
+    pub fn from_options(options: &[MountOption]) -> anyhow::Result<Self> {
+        let mut compression = Self::default();
+        for option in options {
+            match option {
+                MountOption::HashFsCompression(name) => {
+                    compression.encoding = parse_encoding(name)?;
+                }
+                MountOption::HashFsCompressionThreshold(size) => {
+                    compression.threshold = parse_size(size)
+                        .map_err(|err| anyhow::anyhow!("failed to parse hashfscompressionthreshold({size}): {err}"))?
+                        as usize;
+                }
+                _ => {}
+            }
+        }
+        Ok(compression)
+    }
+}
+
+impl Default for ClientCompression {
+    fn default() -> Self {
+        Self {
+            encoding: None,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_encoding_lists() {
+        assert_eq!(parse_encodings("gzip, zstd").unwrap(),
+            vec![CompressionEncoding::Gzip, CompressionEncoding::Zstd]);
+        assert_eq!(parse_encodings("none").unwrap(), vec![]);
+        assert!(parse_encodings("brotli").is_err());
+        assert_eq!(feature_name(CompressionEncoding::Zstd), "compression_zstd");
+
+        let options = MountOption::to_vec(
+            ["hashfscompression=zstd", "hashfscompressionthreshold=64k"].iter().copied());
+        let compression = ClientCompression::from_options(&options).unwrap();
+        assert_eq!(compression.encoding, Some(CompressionEncoding::Zstd));
+        assert_eq!(compression.threshold, 64 << 10);
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::time::sleep;
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Service};
+use tonic::server::NamedService;
+use tonic::transport::{Body, Channel, Endpoint};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+use crate::grpc::hash_fs::hash_fs_server::HashFsServer;
+
+use super::server::HashFsGrpcServer;
+
+/// Without it connecting to a dead server takes as long as the tcp timeout.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delay after the given number of consecutive failures.
+pub fn backoff(failures: u32) -> Duration {
+    MIN_BACKOFF.checked_mul(2u32.saturating_pow(failures))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+/// One hash fs server of a client.
+pub struct EndpointState {
+    pub endpoint: Endpoint,
+    healthy: AtomicBool,
+    /// Consecutive failures, for the backoff of the health checks.
+    failures: AtomicU32,
+}
+
+impl EndpointState {
+    fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            healthy: AtomicBool::new(true),
+            failures: AtomicU32::new(0),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// New requests prefer the other endpoints until the next successful health check.
+    pub fn mark_failed(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        if self.healthy.swap(false, Ordering::Relaxed) {
+            tracing::warn!("hash fs server {} failed, using the other endpoints",
+                self.endpoint.uri());
+        }
+    }
+
+    fn mark_healthy(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        if !self.healthy.swap(true, Ordering::Relaxed) {
+            tracing::info!("hash fs server {} is healthy again", self.endpoint.uri());
+        }
+    }
+
+    fn next_check_delay(&self) -> Duration {
+        if self.is_healthy() {
+            HEALTH_CHECK_INTERVAL
+        } else {
+            backoff(self.failures.load(Ordering::Relaxed))
+        }
+    }
+}
+
+/// The endpoints of a client, used round robin.
+/// Unhealthy ones are only used if no healthy one is left.
+pub struct EndpointSet {
+    endpoints: Vec<Arc<EndpointState>>,
+    next: AtomicUsize,
+}
+
+impl EndpointSet {
+    pub fn new(endpoints: Vec<Endpoint>) -> anyhow::Result<Self> {
+        if endpoints.is_empty() {
+            anyhow::bail!("at least one hash fs endpoint is required");
+        }
+        Ok(Self {
+            endpoints: endpoints.into_iter()
+                .map(|e| Arc::new(EndpointState::new(e.connect_timeout(CONNECT_TIMEOUT))))
+                .collect(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn get(&self, index: usize) -> &Arc<EndpointState> {
+        &self.endpoints[index]
+    }
+
+    /// Indices of all endpoints in the order they should be tried:
+    /// the healthy ones first, rotated by one per call.
+    pub fn candidates(&self) -> Vec<usize> {
+        let count = self.endpoints.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let (mut healthy, unhealthy): (Vec<_>, Vec<_>) = (0..count)
+            .map(|i| (start + i) % count)
+            .partition(|i| self.endpoints[*i].is_healthy());
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Checks every endpoint with the standard gRPC health service
+    /// until the endpoint is dropped.
+    pub fn spawn_health_checks(&self) {
+        for state in &self.endpoints {
+            tokio::spawn(health_check_task(Arc::downgrade(state)));
+        }
+    }
+}
+
+async fn health_check_task(state: Weak<EndpointState>) {
+    let Some(channel) = state.upgrade().map(|strong| strong.endpoint.clone()
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .connect_lazy()) else {
+        return;
+    };
+    let mut client = HealthClient::new(channel);
+    loop {
+        let Some(strong) = state.upgrade() else {
+            break;
+        };
+        let rq = HealthCheckRequest {
+            service: <HashFsServer<HashFsGrpcServer> as NamedService>::NAME.into(),
+        };
+        match client.check(rq).await {
+            Ok(rs) if rs.get_ref().status() == ServingStatus::Serving => strong.mark_healthy(),
+            Ok(rs) => {
+                tracing::debug!("{} is not serving: {:?}", strong.endpoint.uri(), rs.get_ref().status());
+                strong.mark_failed();
+            }
+            Err(err) => {
+                tracing::debug!("health check of {} failed: {err:?}", strong.endpoint.uri());
+                strong.mark_failed();
+            }
+        }
+        let delay = strong.next_check_delay();
+        drop(strong);
+        sleep(delay).await;
+    }
+}
+
+/// Channel that reports transport failures to its endpoint,
+/// such that the following requests fail over to the other endpoints.
+/// The channel itself reconnects on the next request.
+#[derive(Clone)]
+pub struct TrackedChannel {
+    channel: Channel,
+    state: Arc<EndpointState>,
+}
+
+impl TrackedChannel {
+    pub fn new(channel: Channel, state: Arc<EndpointState>) -> Self {
+        Self { channel, state }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for TrackedChannel {
+    type Response = http::Response<Body>;
+    type Error = tonic::transport::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let ready = self.channel.poll_ready(cx);
+        if let Poll::Ready(Err(_)) = &ready {
+            self.state.mark_failed();
+        }
+        ready
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let state = self.state.clone();
+        let response = self.channel.call(request);
+        Box::pin(async move {
+            let response = response.await;
+            if response.is_err() {
+                state.mark_failed();
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_prefer_healthy_endpoints() {
+        let endpoints = ["http://a:1", "http://b:1", "http://c:1"].iter()
+            .map(|uri| Endpoint::from_static(*uri)).collect();
+        let set = EndpointSet::new(endpoints).unwrap();
+        assert_eq!(set.candidates(), vec![0, 1, 2]);
+        assert_eq!(set.candidates(), vec![1, 2, 0]);
+
+        set.get(2).mark_failed();
+        assert_eq!(set.candidates(), vec![0, 1, 2]);
+        assert_eq!(set.candidates(), vec![0, 1, 2]);
+        assert_eq!(set.candidates(), vec![1, 0, 2]);
+
+        assert_eq!(backoff(0), MIN_BACKOFF);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use tifs::fs::fs_config::MountOption;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Request metadata selecting one of the filesystems of the server.
+/// Requests without it use the default filesystem.
+pub const FILESYSTEM_HEADER: &str = "hashfs-filesystem";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilesystemEntry {
+    /// Used as key prefix of the filesystem.
+    pub name: String,
+    /// Mount options, added to the options of the server command line.
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+impl FilesystemEntry {
+    pub fn mount_options(&self, base: &[MountOption]) -> Vec<MountOption> {
+        let mut options = base.to_vec();
+        options.extend(MountOption::to_vec(self.options.iter().map(|s| s.as_str())));
+        // the last name option wins:
+        options.push(MountOption::Name(self.name.clone()));
+        options
+    }
+}
+
+/// Additional filesystems served by one server:
+///
+/// ```toml
+/// [[filesystems]]
+/// name = "projects"
+/// options = ["blksize=64k", "maxsize=10T"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FilesystemsFile {
+    #[serde(default)]
+    pub filesystems: Vec<FilesystemEntry>,
+}
+
+impl FilesystemsFile {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("reading filesystems file {path:?} failed: {err}"))?;
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+/// Client side interceptor that selects the filesystem for all requests.
+#[derive(Clone, Default)]
+pub struct SelectFilesystem {
+    value: Option<MetadataValue<Ascii>>,
+}
+
+impl SelectFilesystem {
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        let value = name.parse()
+            .map_err(|err| anyhow::anyhow!("invalid filesystem name {name:?}: {err}"))?;
+        Ok(Self { value: Some(value) })
+    }
+}
+
+impl Interceptor for SelectFilesystem {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.value {
+            request.metadata_mut().insert(FILESYSTEM_HEADER, value.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filesystem_entries_override_the_name() {
+        let file = toml::from_str::<FilesystemsFile>(r#"
+            [[filesystems]]
+            name = "projects"
+            options = ["blksize=64k"]
+
+            [[filesystems]]
+            name = "scratch"
+        "#).unwrap();
+        assert_eq!(file.filesystems.len(), 2);
+
+        let base = MountOption::to_vec(["name=main", "smalltxns"].iter().copied());
+        let options = file.filesystems[0].mount_options(&base);
+        assert_eq!(options.len(), 4);
+        assert_eq!(options.last(), Some(&MountOption::Name("projects".into())));
+        assert!(options.contains(&MountOption::SmallTxns));
+    }
+}
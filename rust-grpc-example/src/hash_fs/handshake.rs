@@ -0,0 +1,127 @@
+use tifs::fs::fs_config::{MountOption, TiFsConfig};
+use tifs::fs::meta::MetaStatic;
+
+/// Version of the hash fs gRPC protocol. Has to be increased with every
+/// change of the messages or of their meaning that older peers can't handle.
+/// 2: block data requests name the inode they read from.
+/// 3: directory pages return the cursor of the next page.
+/// 4: inode_write_blocks requests missing blocks in rounds, until its response is finished.
+///    Reference count requests name the inode they read from.
+pub const PROTOCOL_VERSION: u32 = 4;
+/// Oldest protocol version still supported by this build. Features of later
+/// versions are only used if the peer supports them.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// First version whose block data requests name the inode they read from.
+pub const BLOCK_DATA_SOURCE_VERSION: u32 = 2;
+/// First version whose directory pages return the cursor of the next page.
+pub const PAGE_CURSOR_VERSION: u32 = 3;
+/// First version with several rounds of missing blocks in inode_write_blocks
+/// and with the inode in reference count requests.
+pub const WRITE_BLOCKS_ROUNDS_VERSION: u32 = 4;
+
+/// Request metadata with the protocol version negotiated in the handshake.
+/// Requests without it are served like MIN_PROTOCOL_VERSION.
+pub const PROTOCOL_VERSION_HEADER: &str = "hashfs-protocol-version";
+
+/// hb_get_block_data_by_hashes_stream and hb_upload_new_block_stream
+pub const FEATURE_STREAMING: &str = "streaming";
+/// inode_write_blocks
+pub const FEATURE_COMBINED_WRITE: &str = "combined_write";
+pub const FEATURE_WATCH_CHANGES: &str = "watch_changes";
+pub const FEATURE_SNAPSHOTS: &str = "snapshots";
+pub const FEATURE_QUOTAS: &str = "quotas";
+/// Not supported yet, the filesystem has no extended attributes.
+pub const FEATURE_XATTRS: &str = "xattrs";
+
+/// Features supported by the client and the server of this build.
+pub const FEATURES: &[&str] = &[
+    FEATURE_STREAMING,
+    FEATURE_COMBINED_WRITE,
+    FEATURE_WATCH_CHANGES,
+    FEATURE_SNAPSHOTS,
+    FEATURE_QUOTAS,
+];
+
+/// Features the client of this build can't work without.
+pub const REQUIRED_FEATURES: &[&str] = &[FEATURE_STREAMING];
+
+/// Highest protocol version supported by both sides, None if their ranges don't overlap.
+pub fn negotiate_protocol_version(other_min: u32, other_max: u32) -> Option<u32> {
+    let version = other_max.min(PROTOCOL_VERSION);
+    (version >= other_min.max(MIN_PROTOCOL_VERSION)).then_some(version)
+}
+
+/// What the client learned about the server in the handshake.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    pub protocol_version: u32,
+    pub server_version: String,
+    pub features: Vec<String>,
+    /// None while the filesystem is not initialized.
+    pub meta_static: Option<MetaStatic>,
+}
+
+impl ServerInfo {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Takes over the static config of the filesystem and disables options
+    /// the server doesn't support. Fails if an explicitly given option
+    /// contradicts the server, instead of writing data the server misinterprets.
+    pub fn adapt_mount_options(&self, options: &mut Vec<MountOption>) -> anyhow::Result<()> {
+        if let Some(missing) = REQUIRED_FEATURES.iter().find(|f| !self.supports(f)) {
+            anyhow::bail!("hash fs server {} doesn't support the required feature {missing}",
+                self.server_version);
+        }
+        if options.contains(&MountOption::CombinedWrite) && !self.supports(FEATURE_COMBINED_WRITE) {
+            tracing::warn!("hash fs server {} doesn't support combinedwrite, option ignored",
+                self.server_version);
+            options.retain(|opt| *opt != MountOption::CombinedWrite);
+        }
+
+        let Some(meta) = &self.meta_static else {
+            return Ok(());
+        };
+        let config = TiFsConfig::from_options(options)?;
+
+        if config.block_size != meta.block_size {
+            if options.iter().any(|opt| matches!(opt, MountOption::BlkSize(_))) {
+                anyhow::bail!("blksize {} doesn't match the block size {} of the filesystem",
+                    config.block_size, meta.block_size);
+            }
+            options.push(MountOption::BlkSize(meta.block_size.to_string()));
+        }
+        if config.hash_algorithm.to_string() != meta.hash_algorithm {
+            if options.iter().any(|opt| matches!(opt, MountOption::HashAlgorithm(_))) {
+                anyhow::bail!("hashalgorithm {} doesn't match the hash algorithm {} of the filesystem",
+                    config.hash_algorithm.to_string(), meta.hash_algorithm);
+            }
+            options.push(MountOption::HashAlgorithm(meta.hash_algorithm.clone()));
+        }
+        if config.hashed_blocks != meta.hashed_blocks {
+            if !meta.hashed_blocks {
+                anyhow::bail!("hashedblocks is given but the filesystem doesn't use hashed blocks");
+            }
+            options.push(MountOption::HashedBlocks);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_the_highest_common_version() {
+        assert_eq!(negotiate_protocol_version(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION + 3),
+            Some(PROTOCOL_VERSION));
+        assert_eq!(negotiate_protocol_version(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 3), None);
+        assert_eq!(negotiate_protocol_version(0, MIN_PROTOCOL_VERSION - 1), None);
+        // older peers are still served, with their own version:
+        assert!(MIN_PROTOCOL_VERSION < PROTOCOL_VERSION);
+        assert_eq!(negotiate_protocol_version(MIN_PROTOCOL_VERSION, PAGE_CURSOR_VERSION),
+            Some(PAGE_CURSOR_VERSION));
+    }
+}
@@ -0,0 +1,201 @@
+use serde::Deserialize;
+use tifs::fs::hash_fs_interface::{HashFsError, HashFsResult};
+use tifs::fs::inode::InoStorageFileAttr;
+
+const ROOT_ID: u32 = 0;
+/// "nobody" / "nogroup" on most systems.
+const DEFAULT_ANON_ID: u32 = 65534;
+
+/// Inclusive range of uids or gids, written as "1000-1999" or "100".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IdRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+impl IdRange {
+    pub fn contains(&self, id: u32) -> bool {
+        (self.first..=self.last).contains(&id)
+    }
+}
+
+impl TryFrom<String> for IdRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |id: &str| id.trim().parse::<u32>()
+            .map_err(|err| format!("invalid id {id:?}: {err}"));
+        let (first, last) = match value.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => {
+                let id = parse(&value)?;
+                (id, id)
+            }
+        };
+        if first > last {
+            return Err(format!("empty id range {value:?}"));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// uids and gids a client may use, similar to the options of an NFS export.
+/// Squashed ids are replaced by anonuid / anongid when creating inodes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct IdMap {
+    /// Squashes uid and gid 0 of the client.
+    pub root_squash: bool,
+    /// Squashes all uids and gids of the client.
+    pub all_squash: bool,
+    pub anonuid: u32,
+    pub anongid: u32,
+    /// Allowed ids of the client. Empty allows all ids.
+    pub uids: Vec<IdRange>,
+    pub gids: Vec<IdRange>,
+}
+
+impl Default for IdMap {
+    fn default() -> Self {
+        Self {
+            root_squash: true,
+            all_squash: false,
+            anonuid: DEFAULT_ANON_ID,
+            anongid: DEFAULT_ANON_ID,
+            uids: Vec::new(),
+            gids: Vec::new(),
+        }
+    }
+}
+
+impl IdMap {
+    /// No squashing and no restrictions, for servers without authentication.
+    pub fn trusted() -> Self {
+        Self { root_squash: false, ..Default::default() }
+    }
+
+    /// The client acts like root and bypasses all permission checks.
+    pub fn is_unrestricted(&self) -> bool {
+        !self.root_squash && !self.all_squash && self.uids.is_empty() && self.gids.is_empty()
+    }
+
+    fn squash(&self, id: u32, anon_id: u32) -> u32 {
+        if self.all_squash || (self.root_squash && id == ROOT_ID) {
+            anon_id
+        } else {
+            id
+        }
+    }
+
+    fn allows(&self, ranges: &[IdRange], anon_id: u32, id: u32) -> bool {
+        if id == anon_id && (self.all_squash || self.root_squash) {
+            return true;
+        }
+        if self.squash(id, anon_id) != id {
+            return false;
+        }
+        ranges.is_empty() || ranges.iter().any(|range| range.contains(id))
+    }
+
+    pub fn allows_uid(&self, uid: u32) -> bool {
+        self.allows(&self.uids, self.anonuid, uid)
+    }
+
+    pub fn allows_gid(&self, gid: u32) -> bool {
+        self.allows(&self.gids, self.anongid, gid)
+    }
+
+    /// The uid stored for new inodes of the client.
+    pub fn map_uid(&self, uid: u32) -> HashFsResult<u32> {
+        let uid = self.squash(uid, self.anonuid);
+        self.allows_uid(uid).then_some(uid).ok_or(HashFsError::PermissionDenied)
+    }
+
+    pub fn map_gid(&self, gid: u32) -> HashFsResult<u32> {
+        let gid = self.squash(gid, self.anongid);
+        self.allows_gid(gid).then_some(gid).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Checks the permission bits of the inode like POSIX does: the owner bits
+    /// if the client may act as the owner, otherwise the group bits if it may act
+    /// as a member of the group, otherwise the bits for others.
+    /// mask holds the bits for others, e.g. 0o2 for write access.
+    fn may_access(&self, attr: &InoStorageFileAttr, mask: u16) -> bool {
+        if self.is_unrestricted() {
+            return true;
+        }
+        let mode = attr.perm.0;
+        let class_bits = if self.allows_uid(attr.uid) {
+            mode >> 6
+        } else if self.allows_gid(attr.gid) {
+            mode >> 3
+        } else {
+            mode
+        };
+        class_bits & mask != 0
+    }
+
+    /// Write access according to the permission bits of the inode.
+    pub fn may_write(&self, attr: &InoStorageFileAttr) -> bool {
+        self.may_access(attr, 0o2)
+    }
+
+    /// Read access according to the permission bits of the inode.
+    pub fn may_read(&self, attr: &InoStorageFileAttr) -> bool {
+        self.may_access(attr, 0o4)
+    }
+
+    /// Search (execute) access according to the permission bits of the directory.
+    pub fn may_search(&self, attr: &InoStorageFileAttr) -> bool {
+        self.may_access(attr, 0o1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tifs::fs::inode::StorageFilePermission;
+
+    use super::*;
+
+    #[test]
+    fn squashes_and_restricts_ids() {
+        let map = toml::from_str::<IdMap>(r#"
+            uids = ["1000-1999", "42"]
+        "#).unwrap();
+        assert_eq!(map.map_uid(0).unwrap(), DEFAULT_ANON_ID);
+        assert_eq!(map.map_uid(1500).unwrap(), 1500);
+        assert_eq!(map.map_uid(42).unwrap(), 42);
+        assert!(map.map_uid(2000).is_err());
+        assert_eq!(map.map_gid(0).unwrap(), DEFAULT_ANON_ID);
+        assert!(!map.allows_uid(0));
+
+        let map = IdMap { all_squash: true, anonuid: 7, ..Default::default() };
+        assert_eq!(map.map_uid(1500).unwrap(), 7);
+        assert!(!map.allows_uid(1500));
+
+        assert!(IdMap::trusted().is_unrestricted());
+        assert!(toml::from_str::<IdMap>(r#"uids = ["2-1"]"#).is_err());
+    }
+
+    #[test]
+    fn checks_permission_bits() {
+        let map = toml::from_str::<IdMap>(r#"uids = ["1000"]
+            gids = ["100"]"#).unwrap();
+        let attr = |perm, uid, gid| InoStorageFileAttr {
+            perm: StorageFilePermission(perm), uid, gid, rdev: 0, flags: 0,
+            last_change: std::time::SystemTime::UNIX_EPOCH,
+        };
+        assert!(map.may_read(&attr(0o400, 1000, 0)));
+        assert!(!map.may_write(&attr(0o400, 1000, 0)));
+        assert!(!map.may_read(&attr(0o440, 2000, 0)));
+        assert!(map.may_read(&attr(0o040, 2000, 100)));
+        assert!(map.may_read(&attr(0o004, 2000, 0)));
+        assert!(!map.may_read(&attr(0o700, 2000, 100)));
+        // only the bits of the matching class count:
+        assert!(!map.may_read(&attr(0o044, 1000, 100)));
+        assert!(!map.may_read(&attr(0o404, 2000, 100)));
+        assert!(map.may_search(&attr(0o710, 2000, 100)));
+        assert!(!map.may_search(&attr(0o601, 2000, 100)));
+    }
+}
@@ -1,2 +1,9 @@
+pub mod auth;
 pub mod client;
+pub mod compression;
+pub mod endpoints;
+pub mod filesystems;
+pub mod handshake;
+pub mod id_map;
 pub mod server;
+pub mod tls;
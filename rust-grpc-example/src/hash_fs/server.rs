@@ -1,43 +1,36 @@
 use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+use futures::{SinkExt, Stream, StreamExt};
+use tonic::codec::CompressionEncoding;
+
 use crate::grpc_time_to_system_time;
 use tifs::fs::fs_config::{self};
-use tifs::fs::hash_fs_interface::{BlockIndex, HashFsInterface};
-use tonic::{Request, Response, Status};
+use tifs::fs::hash_fs_interface::{BlockDataSource, BlockIndex, ChangeEvent, HashFsError, HashFsInterface, HashFsResult, MissingBlocksProvider, RenameMode};
+use tifs::fs::inode::{ParentStorageIno, StorageIno, TiFsHash};
+use tifs::fs::key::ROOT_INODE;
 
-use crate::grpc::greeter::greeter_server::Greeter;
-use crate::grpc::greeter::{HelloResponse, HelloRequest};
 use crate::grpc::hash_fs::{self as grpc_fs};
+use crate::grpc::hash_fs::hash_fs_server::HashFsServer;
 
+use super::auth::ClientIdentity;
+use super::compression::feature_name;
+use super::filesystems::FILESYSTEM_HEADER;
+use super::handshake::{negotiate_protocol_version, BLOCK_DATA_SOURCE_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER, WRITE_BLOCKS_ROUNDS_VERSION, FEATURES};
 
-// Implement the service skeleton for the "Greeter" service
-// defined in the proto
-#[derive(Debug, Default)]
-pub struct MyGreeter {}
-
-// Implement the service function(s) defined in the proto
-// for the Greeter service (SayHello...)
-#[tonic::async_trait]
-impl Greeter for MyGreeter {
-    async fn say_hello(
-        &self,
-        request: Request<HelloRequest>,
-    ) -> Result<Response<HelloResponse>, Status> {
-        println!("Received request from: {:?}", request);
-
-        let response = HelloResponse {
-            message: format!("Hello {}!", request.into_inner().name).into(),
-        };
-
-        Ok(Response::new(response))
-    }
-}
+/// Received blocks that may wait for being stored.
+const UPLOAD_STREAM_BUFFER: usize = 32;
 
 pub struct HashFsGrpcServer {
-    fs_impl: Arc<dyn HashFsInterface>,
+    /// By the name (key prefix) of the filesystem.
+    filesystems: HashMap<String, Arc<dyn HashFsInterface>>,
+    default_filesystem: String,
+    /// Accepted and sent, in order of preference.
+    compression: Vec<CompressionEncoding>,
 }
 
 impl HashFsGrpcServer {
@@ -45,26 +38,239 @@ impl HashFsGrpcServer {
         pd_endpoints: Vec<String>,
         options: Vec<fs_config::MountOption>,
     ) -> anyhow::Result<Self> {
+        Self::with_filesystems(pd_endpoints, vec![options]).await
+    }
 
-        let fs_impl = tifs::fs::tikv_fs::TiFs::construct_hash_fs_server(
-            pd_endpoints, options).await?;
+    /// Serves one filesystem per options, named by their name option.
+    /// The first one is used for requests that don't select a filesystem.
+    pub async fn with_filesystems(
+        pd_endpoints: Vec<String>,
+        filesystems_options: Vec<Vec<fs_config::MountOption>>,
+    ) -> anyhow::Result<Self> {
+        let mut filesystems = HashMap::new();
+        let mut default_filesystem = None;
+        for options in filesystems_options {
+            let fs_config = fs_config::TiFsConfig::from_options(&options)?;
+            let name = String::from_utf8(fs_config.key_prefix)?;
+            let fs_impl = tifs::fs::tikv_fs::TiFs::construct_hash_fs_server(
+                pd_endpoints.clone(), options).await?;
+            tracing::info!("serving filesystem {name:?}");
+            default_filesystem.get_or_insert_with(|| name.clone());
+            if filesystems.insert(name.clone(), fs_impl as Arc<dyn HashFsInterface>).is_some() {
+                anyhow::bail!("filesystem {name:?} is configured twice");
+            }
+        }
+        let Some(default_filesystem) = default_filesystem else {
+            anyhow::bail!("no filesystem configured");
+        };
 
         Ok(HashFsGrpcServer {
-            fs_impl,
+            filesystems,
+            default_filesystem,
+            compression: Vec::new(),
+        })
+    }
+
+    pub fn with_compression(mut self, encodings: Vec<CompressionEncoding>) -> Self {
+        self.compression = encodings;
+        self
+    }
+
+    /// Responses are only compressed if the client accepts it for the call.
+    pub fn into_service(self) -> HashFsServer<Self> {
+        let encodings = self.compression.clone();
+        encodings.into_iter().fold(HashFsServer::new(self), |service, encoding| {
+            service.accept_compressed(encoding).send_compressed(encoding)
         })
     }
+
+    /// The identity is set by the AuthInterceptor,
+    /// servers without interceptor trust all clients.
+    fn scope<T>(&self, request: &tonic::Request<T>) -> Result<RequestScope, tonic::Status> {
+        let identity = request.extensions().get::<ClientIdentity>().cloned()
+            .unwrap_or_else(ClientIdentity::anonymous);
+        let name = match request.metadata().get(FILESYSTEM_HEADER) {
+            Some(value) => value.to_str()
+                .map_err(|_| tonic::Status::invalid_argument("malformed filesystem name"))?,
+            None => self.default_filesystem.as_str(),
+        };
+        let Some(fs_impl) = self.filesystems.get(name) else {
+            return Err(tonic::Status::not_found(format!("unknown filesystem {name:?}")));
+        };
+        if !identity.may_access(name) {
+            return Err(tonic::Status::permission_denied(format!(
+                "{} has no access to filesystem {name:?}", identity.name)));
+        }
+        let protocol_version = match request.metadata().get(PROTOCOL_VERSION_HEADER) {
+            Some(value) => value.to_str().ok()
+                .and_then(|version| version.parse::<u32>().ok())
+                .filter(|version| (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(version))
+                .ok_or_else(|| tonic::Status::invalid_argument("unsupported protocol version"))?,
+            None => MIN_PROTOCOL_VERSION,
+        };
+        Ok(RequestScope { identity, fs_impl: fs_impl.clone(), protocol_version })
+    }
+}
+
+/// Client and filesystem of a request.
+struct RequestScope {
+    identity: ClientIdentity,
+    fs_impl: Arc<dyn HashFsInterface>,
+    /// Negotiated with the client in the handshake.
+    protocol_version: u32,
+}
+
+impl RequestScope {
+    /// Write permission on the inode. For directories this covers
+    /// adding, removing and renaming of their entries.
+    async fn check_write_access(&self, ino: StorageIno) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        let (_, attr, _, _) = self.fs_impl.inode_get_all_attributes(ino).await?;
+        self.identity.id_map.may_write(&attr).then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Read permission on the inode. For directories this covers listing their entries.
+    async fn check_read_access(&self, ino: StorageIno) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        let (_, attr, _, _) = self.fs_impl.inode_get_all_attributes(ino).await?;
+        self.identity.id_map.may_read(&attr).then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Search permission on the directory, needed to look up its entries.
+    async fn check_search_access(&self, ino: StorageIno) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        let (_, attr, _, _) = self.fs_impl.inode_get_all_attributes(ino).await?;
+        self.identity.id_map.may_search(&attr).then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Adding, removing and renaming entries of a directory needs
+    /// write and search permission on it.
+    async fn check_entry_change_access(&self, dir_ino: StorageIno) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        let (_, attr, _, _) = self.fs_impl.inode_get_all_attributes(dir_ino).await?;
+        let id_map = &self.identity.id_map;
+        (id_map.may_write(&attr) && id_map.may_search(&attr))
+            .then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Changes are only reported for inodes and directories the client may read.
+    async fn may_see_change(&self, event: &ChangeEvent) -> bool {
+        match event {
+            ChangeEvent::Inode(ino) => self.check_read_access(*ino).await.is_ok(),
+            ChangeEvent::Entry { parent, name: _ } => self.check_read_access(parent.0).await.is_ok(),
+            ChangeEvent::Overflow => true,
+        }
+    }
+
+    /// Block data is addressed by hash only. Restricted clients get it only
+    /// for blocks mapped in the given ranges of an inode they may read.
+    async fn check_block_read_access<'a>(
+        &self,
+        source: &BlockDataSource,
+        hashes: impl IntoIterator<Item = &'a TiFsHash>,
+    ) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        self.check_read_access(source.ino).await?;
+        let mapped = self.fs_impl.inode_read_block_hashes_block_range(
+            source.ino, &source.block_ranges).await?;
+        let mapped = mapped.values().collect::<HashSet<_>>();
+        hashes.into_iter().all(|hash| mapped.contains(hash))
+            .then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Inode and blocks a block data request reads from. Clients of protocol
+    /// versions before `since` don't name them. Their requests are only served
+    /// for unrestricted clients, whose reads aren't checked against the source.
+    fn block_data_source(
+        &self,
+        ino: Option<grpc_fs::StorageIno>,
+        ranges: Vec<grpc_fs::BlockRange>,
+        since: u32,
+    ) -> Result<BlockDataSource, tonic::Status> {
+        match ino {
+            Some(ino) => Ok(BlockDataSource {
+                ino: ino.into(),
+                block_ranges: ranges.into_iter().map(Into::into).collect(),
+            }),
+            None if self.protocol_version >= since => {
+                Err(tonic::Status::invalid_argument("ino parameter is required!"))
+            }
+            None if self.identity.id_map.is_unrestricted() => Ok(BlockDataSource {
+                ino: ROOT_INODE.0,
+                block_ranges: Vec::new(),
+            }),
+            None => Err(tonic::Status::permission_denied(
+                "restricted clients have to name the inode of block data requests")),
+        }
+    }
+
+    /// Only the owner may change the mode and the ownership of an inode.
+    async fn check_owner(&self, ino: StorageIno) -> HashFsResult<()> {
+        if self.identity.id_map.is_unrestricted() {
+            return Ok(());
+        }
+        let (_, attr, _, _) = self.fs_impl.inode_get_all_attributes(ino).await?;
+        self.identity.id_map.allows_uid(attr.uid).then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
+
+    /// Changes of the whole filesystem are reserved for unrestricted clients.
+    fn check_unrestricted(&self) -> HashFsResult<()> {
+        self.identity.id_map.is_unrestricted().then_some(()).ok_or(HashFsError::PermissionDenied)
+    }
 }
 
 // Implement the service function(s) defined in the proto
-// for the Greeter service (SayHello...)
+// for the HashFS service
 #[tonic::async_trait]
 impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
+    async fn handshake(
+        &self,
+        request: tonic::Request<grpc_fs::HandshakeRq>,
+    ) -> std::result::Result<tonic::Response<grpc_fs::HandshakeRs>, tonic::Status> {
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(version) = negotiate_protocol_version(
+            rq.min_protocol_version, rq.protocol_version) else {
+            return Err(tonic::Status::failed_precondition(format!(
+                "client {} speaks protocol versions {}..={}, the server {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}",
+                rq.client_version, rq.min_protocol_version, rq.protocol_version)));
+        };
+        tracing::info!("handshake of {} (client {}, features {:?}): protocol version {version}",
+            scope.identity.name, rq.client_version, rq.features);
+        let mut rsp = grpc_fs::HandshakeRs::default();
+        rsp.protocol_version = PROTOCOL_VERSION;
+        rsp.min_protocol_version = MIN_PROTOCOL_VERSION;
+        rsp.features = FEATURES.iter().map(|f| f.to_string()).collect();
+        rsp.features.extend(self.compression.iter().map(|e| feature_name(*e)));
+        rsp.server_version = env!("CARGO_PKG_VERSION").into();
+        match scope.fs_impl.meta_static_read().await {
+            Err(HashFsError::FsNotInitialized) => {}
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(meta) => rsp.meta_static = Some(meta.into()),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn init(
         &self,
         request: tonic::Request<grpc_fs::InitRq>,
     ) -> std::result::Result<tonic::Response<grpc_fs::InitRs>, tonic::Status> {
-        let r = self.fs_impl.init(
-            request.get_ref().gid, request.get_ref().uid).await;
+        let scope = self.scope(&request)?;
+        let rq = request.get_ref();
+        let r = async move {
+            let gid = scope.identity.id_map.map_gid(rq.gid)?;
+            let uid = scope.identity.id_map.map_uid(rq.uid)?;
+            scope.fs_impl.init(gid, uid).await
+        }.await;
         let mut rsp = grpc_fs::InitRs::default();
         match r {
             Err(error) => rsp.error = Some(error.into()),
@@ -74,12 +280,13 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
     }
     async fn meta_static_read(
         &self,
-        _request: tonic::Request<grpc_fs::MetaStaticReadRq>,
+        request: tonic::Request<grpc_fs::MetaStaticReadRq>,
     ) -> std::result::Result<
         tonic::Response<grpc_fs::MetaStaticReadRs>,
         tonic::Status,
     >{
-        let rs = self.fs_impl
+        let scope = self.scope(&request)?;
+        let rs = scope.fs_impl
             .meta_static_read().await;
         let mut rsp = grpc_fs::MetaStaticReadRs::default();
         match rs {
@@ -88,6 +295,23 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn meta_mutable_read(
+        &self,
+        request: tonic::Request<grpc_fs::MetaMutableReadRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::MetaMutableReadRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rs = scope.fs_impl
+            .meta_mutable_read().await;
+        let mut rsp = grpc_fs::MetaMutableReadRs::default();
+        match rs {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(val) => rsp.value = Some(val.into()),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn directory_read_children(
         &self,
         request: tonic::Request<grpc_fs::DirectoryReadChildrenRq>,
@@ -95,11 +319,16 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryReadChildrenRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(dir_ino) = rq.dir_ino.clone() else {
             return Err(tonic::Status::invalid_argument("dir_ino parameter is required!"));
         };
-        let r = self.fs_impl.directory_read_children(dir_ino.into()).await;
+        let r = async move {
+            let dir_ino: StorageIno = dir_ino.into();
+            scope.check_read_access(dir_ino).await?;
+            scope.fs_impl.directory_read_children(dir_ino).await
+        }.await;
         let mut rsp = grpc_fs::DirectoryReadChildrenRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -114,6 +343,87 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn directory_read_children_page(
+        &self,
+        request: tonic::Request<grpc_fs::DirectoryReadChildrenPageRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::DirectoryReadChildrenPageRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(dir_ino) = rq.dir_ino.clone() else {
+            return Err(tonic::Status::invalid_argument("dir_ino parameter is required!"));
+        };
+        let r = async move {
+            let dir_ino: StorageIno = dir_ino.into();
+            scope.check_read_access(dir_ino).await?;
+            scope.fs_impl.directory_read_children_page(
+                dir_ino, rq.start_after.map(Into::into), rq.limit).await
+        }.await;
+        let mut rsp = grpc_fs::DirectoryReadChildrenPageRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok((val, next_cursor)) => {
+                rsp.value = val.into_iter().map(|i|{
+                    let mut o = grpc_fs::DirectoryItem::default();
+                    o.ino = Some(i.ino.into());
+                    o.name = i.name;
+                    o.set_typ(i.typ.into());
+                    o
+                }).collect::<Vec<_>>();
+                rsp.next_cursor = next_cursor.map(|n| n.to_string());
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+    async fn directory_read_children_with_attributes(
+        &self,
+        request: tonic::Request<grpc_fs::DirectoryReadChildrenWithAttributesRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::DirectoryReadChildrenWithAttributesRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(dir_ino) = rq.dir_ino.clone() else {
+            return Err(tonic::Status::invalid_argument("dir_ino parameter is required!"));
+        };
+        let r = async move {
+            let dir_ino: StorageIno = dir_ino.into();
+            scope.check_read_access(dir_ino).await?;
+            // attributes of the children are a lookup of each entry:
+            scope.check_search_access(dir_ino).await?;
+            scope.fs_impl.directory_read_children_with_attributes(
+                dir_ino, rq.start_after.map(Into::into), rq.limit).await
+        }.await;
+        let mut rsp = grpc_fs::DirectoryReadChildrenWithAttributesRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok((val, next_cursor)) => {
+                rsp.next_cursor = next_cursor.map(|n| n.to_string());
+                rsp.value = val.into_iter().map(|(i, all)|{
+                    let mut item = grpc_fs::DirectoryItem::default();
+                    item.ino = Some(i.ino.into());
+                    item.name = i.name;
+                    item.set_typ(i.typ.into());
+                    let mut o = grpc_fs::DirectoryItemWithAttributes::default();
+                    o.item = Some(item);
+                    // missing attributes are left out:
+                    o.all = all.map(|(desc, attr, size, atime)| {
+                        let mut all = grpc_fs::InoAllAttributes::default();
+                        all.desc = Some(desc.deref().clone().into());
+                        all.attrs = Some(attr.deref().clone().into());
+                        all.size = Some(size.deref().clone().into());
+                        all.atime = Some(atime.into());
+                        all
+                    });
+                    o
+                }).collect::<Vec<_>>();
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn directory_add_child_checked_new_inode(
         &self,
         request: tonic::Request<grpc_fs::DirectoryAddChildCheckedNewInodeRq>,
@@ -121,6 +431,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryAddChildCheckedNewInodeRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent parameter is required!"));
@@ -128,17 +439,21 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         let Some(perm) = rq.perm.clone() else {
             return Err(tonic::Status::invalid_argument("perm parameter is required!"));
         };
-        let r = self.fs_impl
-            .directory_add_child_checked_new_inode(
-                parent.into(),
-                rq.name.clone().into(),
-                rq.typ().into(),
-                perm.into(),
-                rq.gid,
-                rq.uid,
-                rq.rdev,
-                (rq.inline_data.len() > 0).then(||rq.inline_data),
-            ).await;
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            scope.fs_impl
+                .directory_add_child_checked_new_inode(
+                    parent,
+                    rq.name.clone().into(),
+                    rq.typ().into(),
+                    perm.into(),
+                    scope.identity.id_map.map_gid(rq.gid)?,
+                    scope.identity.id_map.map_uid(rq.uid)?,
+                    rq.rdev,
+                    (rq.inline_data.len() > 0).then(||rq.inline_data),
+                ).await
+        }.await;
         let mut rsp = grpc_fs::DirectoryAddChildCheckedNewInodeRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -156,16 +471,21 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryAddChildCheckedExistingInodeRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(parent) = rq.parent else {
+        let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let Some(ino) = rq.ino else {
+        let Some(ino) = rq.ino.clone() else {
             return Err(tonic::Status::invalid_argument("ino is required!"));
         };
-        let r = self.fs_impl
-            .directory_add_child_checked_existing_inode(
-            parent.into(), rq.name.into(), ino.into()).await;
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            scope.fs_impl
+                .directory_add_child_checked_existing_inode(
+                parent, rq.name.into(), ino.into()).await
+        }.await;
         let mut rs = grpc_fs::DirectoryAddChildCheckedExistingInodeRs::default();
         if let Err(err) = r {
             rs.error = Some(err.into());
@@ -179,12 +499,17 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryRemoveChildFileRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(parent) = rq.parent else {
+        let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let r = self.fs_impl
-            .directory_remove_child_file(parent.into(), rq.name.into()).await;
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            scope.fs_impl
+                .directory_remove_child_file(parent, rq.name.into()).await
+        }.await;
         let mut rs = grpc_fs::DirectoryRemoveChildFileRs::default();
         if let Err(err) = r {
             rs.error = Some(err.into());
@@ -198,12 +523,17 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryRemoveChildDirectoryRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(parent) = rq.parent else {
+        let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let r = self.fs_impl
-            .directory_remove_child_directory(parent.into(), rq.name.into()).await;
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            scope.fs_impl
+                .directory_remove_child_directory(parent, rq.name.into()).await
+        }.await;
         let mut rs = grpc_fs::DirectoryRemoveChildDirectoryRs::default();
         if let Err(err) = r {
             rs.error = Some(err.into());
@@ -218,19 +548,32 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryRenameChildRs>,
         tonic::Status,
     > {
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(parent) = rq.parent else {
+        let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let Some(new_parent) = rq.new_parent else {
+        let Some(new_parent) = rq.new_parent.clone() else {
             return Err(tonic::Status::invalid_argument("new_parent is required!"));
         };
-        let r = self.fs_impl.directory_rename_child(
-            parent.into(),
-            rq.child_name.into(),
-            new_parent.into(),
-            rq.new_child_name.into()
-        ).await;
+        let Some(mode) = RenameMode::from_flags(rq.flags) else {
+            return Err(tonic::Status::invalid_argument("invalid rename flags!"));
+        };
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            let new_parent: ParentStorageIno = new_parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            if new_parent != parent {
+                scope.check_entry_change_access(new_parent.0).await?;
+            }
+            scope.fs_impl.directory_rename_child(
+                parent,
+                rq.child_name.into(),
+                new_parent,
+                rq.new_child_name.into(),
+                mode,
+            ).await
+        }.await;
         let mut rs = grpc_fs::DirectoryRenameChildRs::default();
         if let Err(err) = r {
             rs.error = Some(err.into());
@@ -244,13 +587,16 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryChildGetAllAttributesRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(parent) = rq.parent else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let r = self.fs_impl
-            .directory_child_get_all_attributes(
-                parent.into(), rq.name.into()).await;
+        let parent: ParentStorageIno = parent.into();
+        let r = async {
+            scope.check_search_access(parent.0).await?;
+            scope.fs_impl.directory_child_get_all_attributes(parent, rq.name.into()).await
+        }.await;
         let mut rsp = grpc_fs::DirectoryChildGetAllAttributesRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -272,14 +618,19 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::DirectoryAddNewSymlinkRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(parent) = rq.parent else {
+        let Some(parent) = rq.parent.clone() else {
             return Err(tonic::Status::invalid_argument("parent is required!"));
         };
-        let r = self.fs_impl
-            .directory_add_new_symlink(
-                rq.gid, rq.uid, parent.into(),
-                rq.name.into(), rq.link.into()).await;
+        let r = async move {
+            let parent: ParentStorageIno = parent.into();
+            scope.check_entry_change_access(parent.0).await?;
+            scope.fs_impl
+                .directory_add_new_symlink(
+                    scope.identity.id_map.map_gid(rq.gid)?, scope.identity.id_map.map_uid(rq.uid)?,
+                    parent, rq.name.into(), rq.link.into()).await
+        }.await;
         let mut rsp = grpc_fs::DirectoryAddNewSymlinkRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -296,11 +647,12 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeGetAllAttributesRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl
+        let r = scope.fs_impl
             .inode_get_all_attributes(ino.into()).await;
         let mut rsp = grpc_fs::InodeGetAllAttributesRs::default();
         match r {
@@ -316,6 +668,27 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn inode_get_change_iteration_ids(
+        &self,
+        request: tonic::Request<grpc_fs::InodeGetChangeIterationIdsRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::InodeGetChangeIterationIdsRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let inos = rq.inos.into_iter().map(|ino| ino.into()).collect::<Vec<_>>();
+        let r = scope.fs_impl.inode_get_change_iteration_ids(&inos).await;
+        let mut rsp = grpc_fs::InodeGetChangeIterationIdsRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(ids) => {
+                rsp.ids = ids.into_iter().map(|(ino, id)| (ino.0, id.0.into())).collect();
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
     async fn inode_set_all_attributes(
         &self,
         request: tonic::Request<grpc_fs::InodeSetAllAttributesRq>,
@@ -323,23 +696,39 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeSetAllAttributesRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(ino) = rq.ino else {
+        let Some(ino) = rq.ino.clone() else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl.inode_set_all_attributes(
-            ino.into(),
-            rq.mode.map(Into::into),
-            rq.uid,
-            rq.gid,
-            rq.size,
-            rq.atime.map(Into::into),
-            rq.mtime.map(Into::into),
-            rq.ctime.map(grpc_time_to_system_time),
-            rq.crtime.map(grpc_time_to_system_time),
-            rq.chgtime.map(grpc_time_to_system_time),
-            rq.bkuptime.map(grpc_time_to_system_time),
-            rq.flags).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            if rq.mode.is_some() || rq.uid.is_some() || rq.gid.is_some() || rq.flags.is_some() {
+                scope.check_owner(ino).await?;
+            }
+            // a client may only give away inodes to ids it may use itself:
+            if rq.uid.is_some_and(|uid| !scope.identity.id_map.allows_uid(uid))
+                || rq.gid.is_some_and(|gid| !scope.identity.id_map.allows_gid(gid)) {
+                return Err(HashFsError::PermissionDenied);
+            }
+            if rq.size.is_some() || rq.atime.is_some() || rq.mtime.is_some() || rq.ctime.is_some()
+                || rq.crtime.is_some() || rq.chgtime.is_some() || rq.bkuptime.is_some() {
+                scope.check_write_access(ino).await?;
+            }
+            scope.fs_impl.inode_set_all_attributes(
+                ino,
+                rq.mode.map(Into::into),
+                rq.uid,
+                rq.gid,
+                rq.size,
+                rq.atime.map(Into::into),
+                rq.mtime.map(Into::into),
+                rq.ctime.map(grpc_time_to_system_time),
+                rq.crtime.map(grpc_time_to_system_time),
+                rq.chgtime.map(grpc_time_to_system_time),
+                rq.bkuptime.map(grpc_time_to_system_time),
+                rq.flags).await
+        }.await;
         let mut rs = grpc_fs::InodeSetAllAttributesRs::default();
         if let Err(err) = r {
             rs.error = Some(err.into());
@@ -350,11 +739,12 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         &self,
         request: tonic::Request<grpc_fs::InodeOpenRq>,
     ) -> std::result::Result<tonic::Response<grpc_fs::InodeOpenRs>, tonic::Status>{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl.inode_open(ino.into()).await;
+        let r = scope.fs_impl.inode_open(ino.into()).await;
         let mut rsp = grpc_fs::InodeOpenRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -368,6 +758,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         &self,
         request: tonic::Request<grpc_fs::InodeCloseRq>,
     ) -> std::result::Result<tonic::Response<grpc_fs::InodeCloseRs>, tonic::Status>{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
@@ -375,7 +766,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         let Some(use_id) = rq.use_id else {
             return Err(tonic::Status::invalid_argument("use_id parameter is required!"));
         };
-        let r = self.fs_impl.inode_close(
+        let r = scope.fs_impl.inode_close(
             ino.into(), use_id.into()).await;
         let mut rsp = grpc_fs::InodeCloseRs::default();
         match r {
@@ -391,12 +782,17 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeAllocateSizeRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(ino) = rq.ino else {
+        let Some(ino) = rq.ino.clone() else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl
-            .inode_allocate_size(ino.into(), rq.offset, rq.length).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_write_access(ino).await?;
+            scope.fs_impl
+                .inode_allocate_size(ino, rq.offset, rq.length).await
+        }.await;
         let mut rsp = grpc_fs::InodeAllocateSizeRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -411,12 +807,16 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeReadInlineDataRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl
-            .inode_read_inline_data(ino.into()).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.inode_read_inline_data(ino).await
+        }.await;
         let mut rsp = grpc_fs::InodeReadInlineDataRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -433,13 +833,17 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeReadBlockHashesDataRangeRs>,
         tonic::Status,
     > {
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let Some(ino) = rq.ino else {
+        let Some(ino) = rq.ino.clone() else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl
-            .inode_read_block_hashes_data_range(
-                ino.into(), rq.start, rq.read_size).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.inode_read_block_hashes_data_range(
+                ino, rq.start, rq.read_size).await
+        }.await;
         let mut rsp = grpc_fs::InodeReadBlockHashesDataRangeRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -458,6 +862,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::InodeReadBlockHashesBlockRangeRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
@@ -467,9 +872,11 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         };
         let block_ranges = rq.ranges.iter().map(|br|br.clone().into())
             .collect::<Vec<std::ops::Range<BlockIndex> >>();
-        let r = self.fs_impl
-            .inode_read_block_hashes_block_range(
-                ino.into(), &block_ranges).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.inode_read_block_hashes_block_range(ino, &block_ranges).await
+        }.await;
         let mut rsp = grpc_fs::InodeReadBlockHashesBlockRangeRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -481,6 +888,86 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn hb_get_reference_counts(
+        &self,
+        request: tonic::Request<grpc_fs::HbGetReferenceCountsRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::HbGetReferenceCountsRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let source = scope.block_data_source(rq.ino, rq.ranges, WRITE_BLOCKS_ROUNDS_VERSION)?;
+        let hashes = rq.hashes.into_iter().map(|h| h.data).collect::<Vec<_>>();
+        let r = async {
+            scope.check_block_read_access(&source, hashes.iter()).await?;
+            scope.fs_impl.hb_get_reference_counts(&hashes, &source).await
+        }.await;
+        let mut rsp = grpc_fs::HbGetReferenceCountsRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(counts) => {
+                rsp.counts = counts.into_iter().map(|(hash, count)|{
+                    grpc_fs::HashReferenceCount{ hash: Some(grpc_fs::Hash{data: hash}), count }
+                }).collect();
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    async fn inode_remove_block_range(
+        &self,
+        request: tonic::Request<grpc_fs::InodeRemoveBlockRangeRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::InodeRemoveBlockRangeRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(ino) = rq.ino.clone() else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let Some(block_range) = rq.block_range.clone() else {
+            return Err(tonic::Status::invalid_argument("block_range parameter is required!"));
+        };
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_write_access(ino).await?;
+            scope.fs_impl
+                .inode_remove_block_range(ino, block_range.into(), rq.collapse).await
+        }.await;
+        let mut rsp = grpc_fs::InodeRemoveBlockRangeRs::default();
+        if let Err(err) = r {
+            rsp.error = Some(err.into());
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+    async fn inode_seek_block_mapping(
+        &self,
+        request: tonic::Request<grpc_fs::InodeSeekBlockMappingRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::InodeSeekBlockMappingRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(ino) = rq.ino else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let Some(block_range) = rq.block_range else {
+            return Err(tonic::Status::invalid_argument("block_range parameter is required!"));
+        };
+        let r = scope.fs_impl
+            .inode_seek_block_mapping(ino.into(), block_range.into(), rq.mapped).await;
+        let mut rsp = grpc_fs::InodeSeekBlockMappingRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(found) => {
+                rsp.found = found.map(Into::into);
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn hb_get_block_data_by_hashes(
         &self,
         request: tonic::Request<grpc_fs::HbGetBlockDataByHashesRq>,
@@ -488,12 +975,16 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::HbGetBlockDataByHashesRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
+        let source = scope.block_data_source(rq.ino, rq.ranges, BLOCK_DATA_SOURCE_VERSION)?;
         let hashes = rq.hashes.iter().map(|h|{
             &h.data
         }).collect::<HashSet<_>>();
-        let r = self.fs_impl
-            .hb_get_block_data_by_hashes(&hashes).await;
+        let r = async move {
+            scope.check_block_read_access(&source, hashes.iter().copied()).await?;
+            scope.fs_impl.hb_get_block_data_by_hashes(&hashes, &source).await
+        }.await;
         let mut rsp = grpc_fs::HbGetBlockDataByHashesRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -508,16 +999,64 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+
+    type hb_get_block_data_by_hashes_streamStream = Pin<Box<dyn Stream<Item = Result<grpc_fs::HbGetBlockDataByHashesStreamRs, tonic::Status>> + Send>>;
+
+    async fn hb_get_block_data_by_hashes_stream(
+        &self,
+        request: tonic::Request<grpc_fs::HbGetBlockDataByHashesStreamRq>,
+    ) -> std::result::Result<
+        tonic::Response<Self::hb_get_block_data_by_hashes_streamStream>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let source = scope.block_data_source(rq.ino, rq.ranges, BLOCK_DATA_SOURCE_VERSION)?;
+        let hashes = rq.hashes.into_iter().map(|h|{
+            h.data
+        }).collect::<Vec<_>>();
+        let r = async move {
+            scope.check_block_read_access(&source, &hashes).await?;
+            scope.fs_impl.hb_get_block_data_by_hashes_stream(hashes, source).await
+        }.await;
+        let blocks = match r {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                let mut rsp = grpc_fs::HbGetBlockDataByHashesStreamRs::default();
+                rsp.error = Some(err.into());
+                return Ok(tonic::Response::new(Box::pin(futures::stream::once(
+                    async move { Ok(rsp) }))));
+            }
+        };
+        let stream = blocks.map(|r| {
+            let mut rsp = grpc_fs::HbGetBlockDataByHashesStreamRs::default();
+            match r {
+                Err(err) => rsp.error = Some(err.into()),
+                Ok((hash, data)) => {
+                    rsp.block_data = Some(grpc_fs::HashBlockData{
+                        hash: Some(grpc_fs::Hash{data: hash}),
+                        data: Arc::unwrap_or_clone(data),
+                    });
+                }
+            }
+            Ok(rsp)
+        });
+        Ok(tonic::Response::new(Box::pin(stream)))
+    }
     async fn file_get_hash(
         &self,
         request: tonic::Request<grpc_fs::FileGetHashRq>,
     ) -> std::result::Result<tonic::Response<grpc_fs::FileGetHashRs>, tonic::Status>{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
         };
-        let r = self.fs_impl
-            .file_get_hash(ino.into()).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.file_get_hash(ino).await
+        }.await;
         let mut rsp = grpc_fs::FileGetHashRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -527,6 +1066,29 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn file_get_merkle_hash(
+        &self,
+        request: tonic::Request<grpc_fs::FileGetMerkleHashRq>,
+    ) -> std::result::Result<tonic::Response<grpc_fs::FileGetMerkleHashRs>, tonic::Status>{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(ino) = rq.ino else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.file_get_merkle_hash(ino).await
+        }.await;
+        let mut rsp = grpc_fs::FileGetMerkleHashRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(data) => {
+                rsp.hash = Some(grpc_fs::Hash{ data });
+            }
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn file_read_block_hashes(
         &self,
         request: tonic::Request<grpc_fs::FileReadBlockHashesRq>,
@@ -534,6 +1096,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::FileReadBlockHashesRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
@@ -541,8 +1104,11 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         let Some(block_range) = rq.block_range else {
             return Err(tonic::Status::invalid_argument("block_range parameter is required!"));
         };
-        let r = self.fs_impl
-            .file_read_block_hashes(ino.into(), block_range.into()).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_read_access(ino).await?;
+            scope.fs_impl.file_read_block_hashes(ino, block_range.into()).await
+        }.await;
         let mut rsp = grpc_fs::FileReadBlockHashesRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -559,6 +1125,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::HbIncrementReferenceCountRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
         if rq.increments.len() == 0 {
             return Err(tonic::Status::invalid_argument("increments parameter is required!"));
@@ -567,8 +1134,12 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
             Some((e.hash?.data, e.inc))
         }).collect::<Vec<_>>();
         let list_ref = list.iter().map(|(h,c)|(h,*c)).collect::<Vec<_>>();
-        let r = self.fs_impl
-            .hb_increment_reference_count(&list_ref).await;
+        // references can't be attributed to an inode here, restricted
+        // clients write with inode_write_blocks:
+        let r = async {
+            scope.check_unrestricted()?;
+            scope.fs_impl.hb_increment_reference_count(&list_ref).await
+        }.await;
         let mut rsp = grpc_fs::HbIncrementReferenceCountRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -591,6 +1162,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::HbUploadNewBlockRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let mut rq = request.into_inner();
         if rq.blocks.len() == 0 {
             return Err(tonic::Status::invalid_argument("blocks parameter is required!"));
@@ -599,7 +1171,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
             let hash = &d.hash.as_ref()?.data;
             Some((hash, Arc::new(mem::take(&mut d.data))))
         }).collect::<Vec<_>>();
-        let r = self.fs_impl
+        let r = scope.fs_impl
             .hb_upload_new_block(&blocks).await;
         let mut rsp = grpc_fs::HbUploadNewBlockRs::default();
         match r {
@@ -608,6 +1180,39 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+    async fn hb_upload_new_block_stream(
+        &self,
+        request: tonic::Request<tonic::Streaming<grpc_fs::HbUploadNewBlockStreamRq>>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::HbUploadNewBlockStreamRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let mut incoming = request.into_inner();
+        let (mut sender, blocks) = futures::channel::mpsc::channel(UPLOAD_STREAM_BUFFER);
+        // forwards the received blocks while the previous ones are stored:
+        let receive = async move {
+            while let Some(rq) = incoming.message().await? {
+                let Some(grpc_fs::HashBlockData{hash: Some(hash), data}) = rq.block else {
+                    return Err(tonic::Status::invalid_argument("block is incomplete!"));
+                };
+                if sender.send((hash.data, Arc::new(data))).await.is_err() {
+                    // storing failed, the error is reported below
+                    break;
+                }
+            }
+            Ok(())
+        };
+        let (received, r) = futures::join!(
+            receive, scope.fs_impl.hb_upload_new_block_stream(Box::pin(blocks)));
+        received?;
+        let mut rsp = grpc_fs::HbUploadNewBlockStreamRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(()) => {}
+        }
+        Ok(tonic::Response::new(rsp))
+    }
     async fn inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
         &self,
         request: tonic::Request<
@@ -619,6 +1224,7 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         >,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let mut rq = request.into_inner();
         let Some(ino) = rq.ino else {
             return Err(tonic::Status::invalid_argument("ino parameter is required!"));
@@ -633,11 +1239,15 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
                 mem::take(&mut d.block_ids).into_iter().map(|id|id.into()).collect::<Vec<BlockIndex>>()
             ))
         }).collect::<Vec<_>>();
-        let r = self.fs_impl
-            .inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
-                ino.into(),
-                &blocks,
-            ).await;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_write_access(ino).await?;
+            scope.fs_impl
+                .inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
+                    ino,
+                    &blocks,
+                ).await
+        }.await;
         let mut rsp = grpc_fs::InodeWriteHashBlockToAddressesUpdateInoSizeAndCleaningPreviousBlockHashesRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -646,6 +1256,112 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         Ok(tonic::Response::new(rsp))
     }
 
+    type inode_write_blocksStream = Pin<Box<dyn Stream<Item = Result<grpc_fs::InodeWriteBlocksRs, tonic::Status>> + Send>>;
+
+    async fn inode_write_blocks(
+        &self,
+        request: tonic::Request<tonic::Streaming<grpc_fs::InodeWriteBlocksRq>>,
+    ) -> std::result::Result<
+        tonic::Response<Self::inode_write_blocksStream>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let mut incoming = request.into_inner();
+        let Some(rq) = incoming.message().await? else {
+            return Err(tonic::Status::invalid_argument("blocks message is required!"));
+        };
+        let Some(ino) = rq.ino else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let ino: StorageIno = ino.into();
+        if let Err(err) = scope.check_write_access(ino).await {
+            let mut rsp = grpc_fs::InodeWriteBlocksRs::default();
+            rsp.error = Some(err.into());
+            return Ok(tonic::Response::new(Box::pin(futures::stream::once(
+                async move { Ok(rsp) }))));
+        }
+        let blocks = rq.blocks.into_iter().filter_map(|d|{
+            Some((
+                d.hash?.data,
+                d.block_actual_length,
+                d.block_ids.into_iter().map(|id|id.into()).collect::<Vec<BlockIndex>>()
+            ))
+        }).collect::<Vec<_>>();
+
+        let fs_impl = scope.fs_impl.clone();
+        let (mut responses, rsp_stream) = futures::channel::mpsc::channel(1);
+        let mut missing_responses = responses.clone();
+        let incoming = Arc::new(tokio::sync::Mutex::new(incoming));
+        // older clients expect exactly one response with missing blocks before the final one:
+        let rounds = scope.protocol_version >= WRITE_BLOCKS_ROUNDS_VERSION;
+        let asked = Arc::new(AtomicBool::new(false));
+        let asked_before = asked.clone();
+        let missing_data: MissingBlocksProvider = Box::new(move |missing| {
+            if asked.swap(true, Ordering::Relaxed) && !rounds {
+                // no more data follows, the write fails with UnknownBlockHash:
+                return Box::pin(futures::stream::empty());
+            }
+            let mut rsp = grpc_fs::InodeWriteBlocksRs::default();
+            rsp.missing = missing.into_iter().map(|data| grpc_fs::Hash{data}).collect();
+            // the client read the previous response before it sent the data,
+            // so this fits into the buffer of the channel:
+            let _ = missing_responses.try_send(Ok(rsp));
+            Box::pin(futures::stream::unfold(incoming.clone(), |incoming| async move {
+                let rq = incoming.lock().await.message().await.ok()??;
+                let Some(grpc_fs::HashBlockData{hash: Some(hash), data}) = rq.data else {
+                    return None;
+                };
+                Some(((hash.data, Arc::new(data)), incoming))
+            }))
+        });
+        tokio::spawn(async move {
+            let blocks = blocks.iter().map(|(h, l, ids)| (h, *l, ids.clone())).collect::<Vec<_>>();
+            let r = fs_impl.inode_write_blocks(ino, &blocks, missing_data).await;
+            if !rounds && !asked_before.load(Ordering::Relaxed) {
+                let _ = responses.send(Ok(grpc_fs::InodeWriteBlocksRs::default())).await;
+            }
+            let mut rsp = grpc_fs::InodeWriteBlocksRs::default();
+            rsp.finished = true;
+            match r {
+                Err(err) => rsp.error = Some(err.into()),
+                Ok(()) => {}
+            }
+            let _ = responses.send(Ok(rsp)).await;
+        });
+        Ok(tonic::Response::new(Box::pin(rsp_stream)))
+    }
+
+    async fn inode_set_blocks_from_hashes(
+        &self,
+        request: tonic::Request<grpc_fs::InodeSetBlocksFromHashesRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::InodeSetBlocksFromHashesRs>,
+        tonic::Status,
+    > {
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(ino) = rq.ino else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let Some(first_block) = rq.first_block else {
+            return Err(tonic::Status::invalid_argument("first_block parameter is required!"));
+        };
+        let hashes = rq.hashes.into_iter().map(|h| h.data).collect::<Vec<_>>();
+        let truncate = rq.truncate;
+        let r = async move {
+            let ino: StorageIno = ino.into();
+            scope.check_write_access(ino).await?;
+            scope.fs_impl.inode_set_blocks_from_hashes(
+                ino, first_block.into(), &hashes, truncate).await
+        }.await;
+        let mut rsp = grpc_fs::InodeSetBlocksFromHashesRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(()) => {}
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
     async fn snapshot_create(
         &self,
         request: tonic::Request<grpc_fs::SnapshotCreateRq>,
@@ -653,8 +1369,12 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         tonic::Response<grpc_fs::SnapshotCreateRs>,
         tonic::Status,
     >{
+        let scope = self.scope(&request)?;
         let rq = request.into_inner();
-        let r = self.fs_impl.snapshot_create(rq.name.into()).await;
+        let r = match scope.check_unrestricted() {
+            Ok(()) => scope.fs_impl.snapshot_create(rq.name.into()).await,
+            Err(err) => Err(err),
+        };
         let mut rsp = grpc_fs::SnapshotCreateRs::default();
         match r {
             Err(err) => rsp.error = Some(err.into()),
@@ -665,4 +1385,139 @@ impl grpc_fs::hash_fs_server::HashFs for HashFsGrpcServer {
         }
         Ok(tonic::Response::new(rsp))
     }
+
+    async fn quota_set(
+        &self,
+        request: tonic::Request<grpc_fs::QuotaSetRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::QuotaSetRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(target) = rq.target else {
+            return Err(tonic::Status::invalid_argument("target parameter is required!"));
+        };
+        let limits = rq.limits.map(|l|l.into()).unwrap_or_default();
+        let r = match scope.check_unrestricted() {
+            Ok(()) => scope.fs_impl.quota_set(target.into(), limits).await,
+            Err(err) => Err(err),
+        };
+        let mut rsp = grpc_fs::QuotaSetRs::default();
+        if let Err(err) = r {
+            rsp.error = Some(err.into());
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    async fn quota_list(
+        &self,
+        request: tonic::Request<grpc_fs::QuotaListRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::QuotaListRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let r = scope.fs_impl.quota_list().await;
+        let mut rsp = grpc_fs::QuotaListRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(reports) => rsp.value = reports.into_iter().map(|r|r.into()).collect(),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    async fn quota_report(
+        &self,
+        request: tonic::Request<grpc_fs::QuotaReportRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::QuotaReportRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(target) = rq.target else {
+            return Err(tonic::Status::invalid_argument("target parameter is required!"));
+        };
+        let r = scope.fs_impl.quota_report(target.into()).await;
+        let mut rsp = grpc_fs::QuotaReportRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(report) => rsp.value = report.map(|r|r.into()),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    async fn fs_stats(
+        &self,
+        request: tonic::Request<grpc_fs::FsStatsRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::FsStatsRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let r = scope.fs_impl.fs_stats().await;
+        let mut rsp = grpc_fs::FsStatsRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(val) => rsp.value = Some(val.into()),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    async fn file_stats(
+        &self,
+        request: tonic::Request<grpc_fs::FileStatsRq>,
+    ) -> std::result::Result<
+        tonic::Response<grpc_fs::FileStatsRs>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let rq = request.into_inner();
+        let Some(ino) = rq.ino else {
+            return Err(tonic::Status::invalid_argument("ino parameter is required!"));
+        };
+        let r = scope.fs_impl.file_stats(ino.into()).await;
+        let mut rsp = grpc_fs::FileStatsRs::default();
+        match r {
+            Err(err) => rsp.error = Some(err.into()),
+            Ok(val) => rsp.value = Some(val.into()),
+        }
+        Ok(tonic::Response::new(rsp))
+    }
+
+    type watch_changesStream = Pin<Box<dyn Stream<Item = Result<grpc_fs::WatchChangesRs, tonic::Status>> + Send>>;
+
+    async fn watch_changes(
+        &self,
+        request: tonic::Request<grpc_fs::WatchChangesRq>,
+    ) -> std::result::Result<
+        tonic::Response<Self::watch_changesStream>,
+        tonic::Status,
+    >{
+        let scope = self.scope(&request)?;
+        let events = match scope.fs_impl.watch_changes().await {
+            Ok(events) => events,
+            Err(err) => {
+                let mut rsp = grpc_fs::WatchChangesRs::default();
+                rsp.error = Some(err.into());
+                return Ok(tonic::Response::new(Box::pin(futures::stream::once(
+                    async move { Ok(rsp) }))));
+            }
+        };
+        let scope = Arc::new(scope);
+        let stream = events.filter_map(move |r| {
+            let scope = scope.clone();
+            async move {
+                let mut rsp = grpc_fs::WatchChangesRs::default();
+                match r {
+                    Err(err) => rsp.error = Some(err.into()),
+                    Ok(event) if scope.may_see_change(&event).await => rsp.event = Some(event.into()),
+                    Ok(_) => return None,
+                }
+                Some(Ok(rsp))
+            }
+        });
+        Ok(tonic::Response::new(Box::pin(stream)))
+    }
 }
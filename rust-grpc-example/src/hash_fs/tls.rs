@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+/// Certificates of the hash fs gRPC connection, read from a toml file
+/// in the style of the TiKV tls config. All files are PEM encoded.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GrpcTlsConfig {
+    /// Server: CA of the client certificates, enables mTLS.
+    /// Client: CA of the server certificate, system roots if missing.
+    pub ca_path: Option<PathBuf>,
+    /// Own certificate. Required on the server, used for mTLS on the client.
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    /// Client: expected name in the server certificate, the endpoint host by default.
+    pub domain: Option<String>,
+}
+
+impl GrpcTlsConfig {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("reading tls config {path:?} failed: {err}"))?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    fn identity(&self) -> anyhow::Result<Option<Identity>> {
+        match (&self.cert_path, &self.key_path) {
+            (Some(cert), Some(key)) => Ok(Some(Identity::from_pem(
+                std::fs::read(cert)?, std::fs::read(key)?))),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("cert_path and key_path have to be given together"),
+        }
+    }
+
+    fn ca(&self) -> anyhow::Result<Option<Certificate>> {
+        let Some(ca) = &self.ca_path else {
+            return Ok(None);
+        };
+        Ok(Some(Certificate::from_pem(std::fs::read(ca)?)))
+    }
+
+    pub fn server_tls_config(&self) -> anyhow::Result<ServerTlsConfig> {
+        let identity = self.identity()?.ok_or_else(|| {
+            anyhow::anyhow!("the server requires cert_path and key_path")
+        })?;
+        let mut config = ServerTlsConfig::new().identity(identity);
+        if let Some(ca) = self.ca()? {
+            config = config.client_ca_root(ca);
+        }
+        Ok(config)
+    }
+
+    pub fn client_tls_config(&self) -> anyhow::Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca) = self.ca()? {
+            config = config.ca_certificate(ca);
+        }
+        if let Some(identity) = self.identity()? {
+            config = config.identity(identity);
+        }
+        if let Some(domain) = &self.domain {
+            config = config.domain_name(domain.clone());
+        }
+        Ok(config)
+    }
+}
@@ -5,8 +5,10 @@ use std::{convert::TryInto, time::SystemTime};
 
 use fuser::TimeOrNow;
 use num_bigint::BigUint;
-use tifs::fs::hash_fs_interface::BlockIndex;
-use tifs::fs::{hash_fs_interface::HashFsError, key::PARENT_OF_ROOT_INODE, meta::MetaStatic};
+use tifs::fs::hash_fs_interface::{BlockIndex, ChangeEvent};
+use tifs::fs::{hash_fs_interface::HashFsError, key::PARENT_OF_ROOT_INODE, meta::{MetaMutable, MetaStatic}};
+use tifs::fs::fs_stats::{FileStats, FsStats};
+use tifs::fs::quota::{QuotaLimits, QuotaReport, QuotaState, QuotaTarget, QuotaUsage};
 use tifs::fs::inode::{InoAccessTime, InoDescription, InoSize, InoStorageFileAttr, ParentStorageIno, StorageDirItem, StorageDirItemKind, StorageFilePermission, StorageIno};
 use uuid::Uuid;
 
@@ -57,6 +59,14 @@ impl From<&grpc::hash_fs::HashFsError> for HashFsError {
             gId::GrpcMessageIncomplete => nId::GrpcMessageIncomplete,
             gId::RawTonicTransportError => nId::RawTonicTransportError(val.msg.clone()),
             gId::FsDataIsMissing => nId::FsHasMissingData(Some(val.msg.clone())),
+            gId::DirNotEmpty => nId::DirNotEmpty,
+            gId::NotADirectory => nId::NotADirectory,
+            gId::IsADirectory => nId::IsADirectory,
+            gId::NoSpaceLeft => nId::NoSpaceLeft,
+            gId::QuotaExceeded => nId::QuotaExceeded,
+            gId::UnknownBlockHash => nId::UnknownBlockHash,
+            gId::PermissionDenied => nId::PermissionDenied,
+            gId::CrossQuotaRename => nId::CrossQuotaRename,
         }
     }
 }
@@ -97,6 +107,14 @@ impl From<&HashFsError> for grpc::hash_fs::HashFsError {
                 msg_out = msg.clone().unwrap_or(format!(""));
                 nId::FsDataIsMissing
             }
+            gId::DirNotEmpty => nId::DirNotEmpty,
+            gId::NotADirectory => nId::NotADirectory,
+            gId::IsADirectory => nId::IsADirectory,
+            gId::NoSpaceLeft => nId::NoSpaceLeft,
+            gId::QuotaExceeded => nId::QuotaExceeded,
+            gId::UnknownBlockHash => nId::UnknownBlockHash,
+            gId::PermissionDenied => nId::PermissionDenied,
+            gId::CrossQuotaRename => nId::CrossQuotaRename,
         };
         let mut o = grpc::hash_fs::HashFsError::default();
         o.set_id(id);
@@ -119,6 +137,10 @@ impl From<&grpc::hash_fs::StorageDirItemKind> for StorageDirItemKind {
             gId::File => nId::File,
             gId::Directory => nId::Directory,
             gId::Symlink => nId::Symlink,
+            gId::CharDevice => nId::CharDevice,
+            gId::BlockDevice => nId::BlockDevice,
+            gId::NamedPipe => nId::NamedPipe,
+            gId::Socket => nId::Socket,
         }
     }
 }
@@ -137,6 +159,10 @@ impl From<StorageDirItemKind> for grpc::hash_fs::StorageDirItemKind {
             gId::File => nId::File,
             gId::Directory => nId::Directory,
             gId::Symlink => nId::Symlink,
+            gId::CharDevice => nId::CharDevice,
+            gId::BlockDevice => nId::BlockDevice,
+            gId::NamedPipe => nId::NamedPipe,
+            gId::Socket => nId::Socket,
         }
     }
 }
@@ -180,6 +206,151 @@ impl From<MetaStatic> for grpc::hash_fs::MetaStatic {
     }
 }
 
+impl From<grpc::hash_fs::MetaMutable> for MetaMutable {
+    fn from(val: grpc::hash_fs::MetaMutable) -> Self {
+        MetaMutable{
+            inode_next: val.inode_next,
+            last_stat: None,
+            used_logical_bytes: val.used_logical_bytes,
+            used_physical_blocks: val.used_physical_blocks,
+            inode_count: val.inode_count,
+        }
+    }
+}
+
+impl From<MetaMutable> for grpc::hash_fs::MetaMutable {
+    fn from(val: MetaMutable) -> Self {
+        let mut o = Self::default();
+        o.inode_next = val.inode_next;
+        o.used_logical_bytes = val.used_logical_bytes;
+        o.used_physical_blocks = val.used_physical_blocks;
+        o.inode_count = val.inode_count;
+        o
+    }
+}
+
+impl From<grpc::hash_fs::QuotaTarget> for QuotaTarget {
+    fn from(val: grpc::hash_fs::QuotaTarget) -> Self {
+        use grpc::hash_fs::QuotaTargetKind as gId;
+        match val.kind() {
+            gId::User => QuotaTarget::User(val.id as u32),
+            gId::Group => QuotaTarget::Group(val.id as u32),
+            gId::Directory => QuotaTarget::Directory(StorageIno(val.id)),
+        }
+    }
+}
+
+impl From<QuotaTarget> for grpc::hash_fs::QuotaTarget {
+    fn from(val: QuotaTarget) -> Self {
+        use grpc::hash_fs::QuotaTargetKind as nId;
+        let (kind, id) = match val {
+            QuotaTarget::User(uid) => (nId::User, uid as u64),
+            QuotaTarget::Group(gid) => (nId::Group, gid as u64),
+            QuotaTarget::Directory(ino) => (nId::Directory, ino.0),
+        };
+        let mut o = Self::default();
+        o.set_kind(kind);
+        o.id = id;
+        o
+    }
+}
+
+impl From<grpc::hash_fs::QuotaLimits> for QuotaLimits {
+    fn from(val: grpc::hash_fs::QuotaLimits) -> Self {
+        QuotaLimits{
+            max_bytes: val.max_bytes,
+            max_inodes: val.max_inodes,
+        }
+    }
+}
+
+impl From<QuotaLimits> for grpc::hash_fs::QuotaLimits {
+    fn from(val: QuotaLimits) -> Self {
+        let mut o = Self::default();
+        o.max_bytes = val.max_bytes;
+        o.max_inodes = val.max_inodes;
+        o
+    }
+}
+
+impl TryFrom<grpc::hash_fs::QuotaReport> for QuotaReport {
+    type Error = HashFsError;
+    fn try_from(val: grpc::hash_fs::QuotaReport) -> Result<Self, Self::Error> {
+        let Some(target) = val.target else {
+            return Err(HashFsError::GrpcMessageIncomplete);
+        };
+        Ok(QuotaReport{
+            target: target.into(),
+            state: QuotaState {
+                limits: val.limits.map(|l|l.into()).unwrap_or_default(),
+                usage: QuotaUsage {
+                    used_bytes: val.used_bytes,
+                    used_inodes: val.used_inodes,
+                },
+            },
+        })
+    }
+}
+
+impl From<QuotaReport> for grpc::hash_fs::QuotaReport {
+    fn from(val: QuotaReport) -> Self {
+        let mut o = Self::default();
+        o.target = Some(val.target.into());
+        o.limits = Some(val.state.limits.into());
+        o.used_bytes = val.state.usage.used_bytes;
+        o.used_inodes = val.state.usage.used_inodes;
+        o
+    }
+}
+
+impl From<grpc::hash_fs::FsStats> for FsStats {
+    fn from(val: grpc::hash_fs::FsStats) -> Self {
+        FsStats{
+            logical_bytes: val.logical_bytes,
+            inode_count: val.inode_count,
+            unique_blocks: val.unique_blocks,
+            referenced_blocks: val.referenced_blocks,
+            unique_block_capacity_bytes: val.unique_block_capacity_bytes,
+            refcount_histogram: val.refcount_histogram.into_iter().collect(),
+        }
+    }
+}
+
+impl From<FsStats> for grpc::hash_fs::FsStats {
+    fn from(val: FsStats) -> Self {
+        let mut o = Self::default();
+        o.logical_bytes = val.logical_bytes;
+        o.inode_count = val.inode_count;
+        o.unique_blocks = val.unique_blocks;
+        o.referenced_blocks = val.referenced_blocks;
+        o.unique_block_capacity_bytes = val.unique_block_capacity_bytes;
+        o.refcount_histogram = val.refcount_histogram.into_iter().collect();
+        o
+    }
+}
+
+impl From<grpc::hash_fs::FileStats> for FileStats {
+    fn from(val: grpc::hash_fs::FileStats) -> Self {
+        FileStats{
+            size: val.size,
+            mapped_blocks: val.mapped_blocks,
+            shared_blocks: val.shared_blocks,
+            exclusive_blocks: val.exclusive_blocks,
+        }
+    }
+}
+
+impl From<FileStats> for grpc::hash_fs::FileStats {
+    fn from(val: FileStats) -> Self {
+        let mut o = Self::default();
+        o.size = val.size;
+        o.mapped_blocks = val.mapped_blocks;
+        o.shared_blocks = val.shared_blocks;
+        o.exclusive_blocks = val.exclusive_blocks;
+        o
+    }
+}
+
 pub fn grpc_time_to_system_time(timestamp: prost_types::Timestamp
 ) -> SystemTime {
     timestamp.try_into().ok().unwrap_or(SystemTime::UNIX_EPOCH)
@@ -278,6 +449,7 @@ impl From<grpc::hash_fs::InoSize> for InoSize {
             blocks: val.blocks,
             last_change: grpc_time_opt_to_system_time(
                 &val.last_change),
+            mapped_blocks: val.mapped_blocks,
         }
     }
 }
@@ -288,6 +460,7 @@ impl From<InoSize> for grpc::hash_fs::InoSize {
         o.size = val.size();
         o.blocks = val.blocks();
         o.last_change = Some(val.last_change.into());
+        o.mapped_blocks = val.mapped_blocks;
         o
     }
 }
@@ -376,3 +549,38 @@ impl From<BigUint> for grpc::hash_fs::BigUint {
         }
     }
 }
+
+impl From<grpc::hash_fs::ChangeEvent> for ChangeEvent {
+    fn from(val: grpc::hash_fs::ChangeEvent) -> Self {
+        use grpc::hash_fs::ChangeEventKind as gId;
+        let ino = val.ino.map(StorageIno::from).unwrap_or(StorageIno(0));
+        match val.kind() {
+            gId::Inode => ChangeEvent::Inode(ino),
+            gId::Entry => ChangeEvent::Entry {
+                parent: ParentStorageIno(ino),
+                name: val.name.into(),
+            },
+            gId::Overflow => ChangeEvent::Overflow,
+        }
+    }
+}
+
+impl From<ChangeEvent> for grpc::hash_fs::ChangeEvent {
+    fn from(val: ChangeEvent) -> Self {
+        use grpc::hash_fs::ChangeEventKind as nId;
+        let mut o = Self::default();
+        match val {
+            ChangeEvent::Inode(ino) => {
+                o.set_kind(nId::Inode);
+                o.ino = Some(ino.into());
+            }
+            ChangeEvent::Entry { parent, name } => {
+                o.set_kind(nId::Entry);
+                o.ino = Some(parent.0.into());
+                o.name = name.to_string();
+            }
+            ChangeEvent::Overflow => o.set_kind(nId::Overflow),
+        }
+        o
+    }
+}
@@ -4,6 +4,8 @@ pub mod async_fs;
 pub mod block;
 pub mod client;
 pub mod dir;
+pub mod dir_handler;
+pub mod disk_block_cache;
 pub mod error;
 pub mod file_handler;
 pub mod hashed_block;
@@ -11,6 +13,11 @@ pub mod index;
 pub mod inode;
 pub mod key;
 pub mod meta;
+pub mod meta_cache;
+pub mod quota;
+pub mod usage;
+pub mod scrubber;
+pub mod change_log;
 pub mod mode;
 pub mod reply;
 pub mod serialize;
@@ -19,6 +26,7 @@ pub mod fuse_to_hashfs;
 pub mod transaction_client_mux;
 pub mod parsers;
 pub mod fs_config;
+pub mod fs_stats;
 pub mod tikv_fs_trait_impl;
 pub mod szymanskis_critical_section;
 pub mod flexible_transaction;
@@ -23,6 +23,10 @@ pub trait AsyncFileSystem: Send + Sync {
     /// Called on filesystem exit.
     async fn destroy(&self) {}
 
+    /// Called once before the session starts, with the handle that allows
+    /// the filesystem to invalidate cached entries of the kernel.
+    fn set_kernel_notifier(&self, _notifier: fuser::Notifier) {}
+
     /// Look up a directory entry by name and get its attributes.
     async fn lookup(&self, _parent: u64, _name: ByteString) -> Result<Entry> {
         Err(FsError::unimplemented())
@@ -400,12 +404,11 @@ pub trait AsyncFileSystem: Send + Sync {
     ) -> Result<()> {
         Err(FsError::unimplemented())
     }
-/*
+
     /// Reposition read/write file offset
-    async fn lseek(&self, _ino: u64, _fh: u64, _offset: i64, _whence: i32) -> Result<Lseek> {
+    async fn lseek(&self, _ino: u64, _fh: u64, _offset: i64, _whence: i32) -> Result<Sleek> {
         Err(FsError::unimplemented())
     }
-*/
 
     /// Copy the specified range from the source inode to the destination inode
     async fn copy_file_range(
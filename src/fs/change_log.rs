@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::hash_fs_interface::ChangeEvent;
+use super::inode::{ParentStorageIno, StorageIno};
+
+/// Interval in which watchers look for the changes of other instances.
+pub const CHANGE_LOG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Entries older than this get removed. Watchers that didn't scan
+/// for that long get an overflow.
+pub const CHANGE_LOG_RETENTION: Duration = Duration::from_secs(300);
+/// Entries may become visible later than the time in their key suggests,
+/// due to clock differences of the writers and the commit duration.
+/// Watchers scan this window again once per window.
+pub const CHANGE_LOG_GRACE: Duration = Duration::from_secs(10);
+/// Entries written per transaction.
+pub const CHANGE_LOG_BATCH_SIZE: usize = 256;
+/// Duration of the lease of the watchers. They renew it after a third of it,
+/// such that it tolerates clock differences of up to two thirds.
+pub const CHANGE_LOG_WATCH_LEASE: Duration = Duration::from_secs(30);
+
+pub fn time_us(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
+}
+
+/// Change event stored in TiKV, such that the watchers
+/// of other hash fs instances get it as well.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChangeLogEntry {
+    /// The watchers of this instance got the event directly.
+    pub instance: Uuid,
+    pub ino: StorageIno,
+    /// Name of the changed entry, ino is its directory then.
+    pub name: Option<String>,
+}
+
+impl ChangeLogEntry {
+    /// None for events that aren't logged.
+    pub fn new(instance: Uuid, event: &ChangeEvent) -> Option<Self> {
+        match event {
+            ChangeEvent::Inode(ino) => Some(Self { instance, ino: *ino, name: None }),
+            ChangeEvent::Entry { parent, name } => Some(Self {
+                instance, ino: parent.0, name: Some(name.to_string()),
+            }),
+            ChangeEvent::Overflow => None,
+        }
+    }
+
+    pub fn into_event(self) -> ChangeEvent {
+        match self.name {
+            Some(name) => ChangeEvent::Entry { parent: ParentStorageIno(self.ino), name: name.into() },
+            None => ChangeEvent::Inode(self.ino),
+        }
+    }
+}
+
+/// Entries are only written while a watcher holds this lease,
+/// without watchers nobody would read them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeLogWatchLease {
+    pub until_us: u64,
+}
+
+impl ChangeLogWatchLease {
+    pub fn is_active(&self, now_us: u64) -> bool {
+        now_us < self.until_us
+    }
+}
+
+/// Start of a change log scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeLogScan {
+    pub from_us: u64,
+    /// Continues after this entry.
+    pub after: Option<(u64, Uuid)>,
+    /// Scans the grace window again for entries that became visible late.
+    pub window: bool,
+}
+
+/// Position of a watcher in the change log. Polls continue after the last
+/// reported entry. Once per grace window, the window is scanned again for
+/// entries that became visible late. The ids of its entries make sure
+/// each one is reported once.
+#[derive(Debug)]
+pub struct ChangeLogCursor {
+    start_us: u64,
+    last: Option<(u64, Uuid)>,
+    scanned_until_us: u64,
+    window_scanned_us: u64,
+    reported: HashMap<Uuid, u64>,
+}
+
+impl ChangeLogCursor {
+    pub fn new(now_us: u64) -> Self {
+        Self {
+            start_us: now_us,
+            last: None,
+            scanned_until_us: now_us,
+            window_scanned_us: now_us,
+            reported: HashMap::new(),
+        }
+    }
+
+    fn window_start_us(&self) -> u64 {
+        self.scanned_until_us.saturating_sub(CHANGE_LOG_GRACE.as_micros() as u64)
+    }
+
+    /// Where the scan starting at now_us begins.
+    pub fn next_scan(&self, now_us: u64) -> ChangeLogScan {
+        let grace_us = CHANGE_LOG_GRACE.as_micros() as u64;
+        if now_us.saturating_sub(self.window_scanned_us) >= grace_us {
+            return ChangeLogScan { from_us: self.window_start_us(), after: None, window: true };
+        }
+        let from_us = self.last.map(|(time_us, _id)| time_us).unwrap_or(self.start_us);
+        ChangeLogScan { from_us, after: self.last, window: false }
+    }
+
+    /// True if the entry wasn't reported yet.
+    pub fn report(&mut self, time_us: u64, id: Uuid) -> bool {
+        self.last = self.last.max(Some((time_us, id)));
+        self.reported.insert(id, time_us).is_none()
+    }
+
+    /// Called after a complete scan, scan_start_us is the time before the scan started.
+    pub fn finish_scan(&mut self, scan: ChangeLogScan, scan_start_us: u64) {
+        self.scanned_until_us = self.scanned_until_us.max(scan_start_us);
+        if scan.window {
+            self.window_scanned_us = scan_start_us;
+        }
+        // ids before the next grace window aren't scanned again:
+        let window_start_us = self.window_start_us()
+            .min(self.window_scanned_us.saturating_sub(CHANGE_LOG_GRACE.as_micros() as u64));
+        self.reported.retain(|_id, time_us| *time_us >= window_start_us);
+    }
+
+    /// Entries after the last scan may already be removed.
+    pub fn is_stale(&self, now_us: u64) -> bool {
+        let limit = (CHANGE_LOG_RETENTION - CHANGE_LOG_GRACE).as_micros() as u64;
+        now_us.saturating_sub(self.scanned_until_us) > limit
+    }
+
+    /// Continues from now after an overflow was reported.
+    pub fn reset(&mut self, now_us: u64) {
+        *self = Self::new(now_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continues_after_reported_entries_and_rescans_the_window_once_per_window() {
+        let grace = CHANGE_LOG_GRACE.as_micros() as u64;
+        let start = 100 * grace;
+        let mut cursor = ChangeLogCursor::new(start);
+        let scan = cursor.next_scan(start + 1);
+        assert_eq!(scan, ChangeLogScan { from_us: start, after: None, window: false });
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(cursor.report(start + 1, a));
+        cursor.finish_scan(scan, start + 2);
+        // the next poll doesn't read the reported entry again:
+        let scan = cursor.next_scan(start + 3);
+        assert_eq!(scan, ChangeLogScan { from_us: start + 1, after: Some((start + 1, a)), window: false });
+        cursor.finish_scan(scan, start + 3);
+
+        // b became visible late, the window scan finds it, but not a again:
+        let scan = cursor.next_scan(start + grace);
+        assert!(scan.window);
+        assert_eq!(scan.from_us, start + 3 - grace);
+        assert!(!cursor.report(start + 1, a));
+        assert!(cursor.report(start, b));
+        cursor.finish_scan(scan, start + grace);
+        assert!(!cursor.next_scan(start + grace + 1).window);
+
+        cursor.finish_scan(cursor.next_scan(start + 3 * grace), start + 3 * grace);
+        assert!(cursor.reported.is_empty());
+
+        assert!(!cursor.is_stale(start + 3 * grace));
+        assert!(cursor.is_stale(start + CHANGE_LOG_RETENTION.as_micros() as u64 + 3 * grace));
+    }
+
+    #[test]
+    fn converts_events() {
+        let instance = Uuid::new_v4();
+        let event = ChangeEvent::Entry { parent: ParentStorageIno(StorageIno(5)), name: "a".into() };
+        let entry = ChangeLogEntry::new(instance, &event).unwrap();
+        assert_eq!(entry.into_event(), event);
+        assert!(ChangeLogEntry::new(instance, &ChangeEvent::Overflow).is_none());
+    }
+
+    #[test]
+    fn lease_expires() {
+        let lease = ChangeLogWatchLease { until_us: 10 };
+        assert!(lease.is_active(9));
+        assert!(!lease.is_active(10));
+        assert!(!ChangeLogWatchLease::default().is_active(0));
+    }
+}
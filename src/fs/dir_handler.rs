@@ -0,0 +1,105 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bytestring::ByteString;
+use tokio::sync::RwLock;
+
+use super::inode::StorageIno;
+
+/// Last fetched page of entries, of the type the caller fetched.
+struct CachedPage {
+    start: usize,
+    items: Box<dyn Any + Send + Sync>,
+    /// No entries follow this page.
+    last: bool,
+}
+
+impl fmt::Debug for CachedPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedPage").field("start", &self.start).field("last", &self.last).finish()
+    }
+}
+
+/// State of an opened directory.
+/// Remembers at which readdir offset each fetched page of children started
+/// and the name to continue the listing after. This keeps the offsets that
+/// were handed out to the kernel valid without holding the listing in memory.
+/// Only the last fetched page is kept, for the following readdir calls on it.
+#[derive(Debug)]
+pub struct DirHandler {
+    pub ino: StorageIno,
+    page_starts: RwLock<BTreeMap<usize, Option<ByteString>>>,
+    last_page: RwLock<Option<CachedPage>>,
+}
+
+impl DirHandler {
+    pub fn new(ino: StorageIno) -> Self {
+        Self {
+            ino,
+            page_starts: RwLock::new(BTreeMap::from([(0, None)])),
+            last_page: RwLock::new(None),
+        }
+    }
+
+    /// Returns the start offset of the nearest known page at or before offset
+    /// and the cursor needed to fetch it.
+    pub async fn page_start_for(&self, offset: usize) -> (usize, Option<ByteString>) {
+        let starts = self.page_starts.read().await;
+        starts.range(..=offset).next_back()
+            .map(|(start, cursor)| (*start, cursor.clone()))
+            .unwrap_or((0, None))
+    }
+
+    pub async fn register_page_start(&self, offset: usize, cursor: Option<ByteString>) {
+        self.page_starts.write().await.insert(offset, cursor);
+    }
+
+    /// Entries of the cached page from offset on. None if the page doesn't
+    /// cover the offset or holds entries of another type.
+    pub async fn cached_items_from<T: Clone + 'static>(&self, offset: usize) -> Option<Vec<T>> {
+        let page = self.last_page.read().await;
+        let page = page.as_ref()?;
+        let items = page.items.downcast_ref::<Vec<T>>()?;
+        let end = page.start + items.len();
+        if offset < page.start || offset > end || (offset == end && !page.last) {
+            return None;
+        }
+        Some(items[offset - page.start..].to_vec())
+    }
+
+    pub async fn cache_page<T: Send + Sync + 'static>(&self, start: usize, items: Vec<T>, last: bool) {
+        *self.last_page.write().await = Some(CachedPage { start, items: Box::new(items), last });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn page_start_for_returns_nearest_page_before_offset() {
+        let handler = DirHandler::new(StorageIno(1));
+        handler.register_page_start(10, Some(ByteString::from("j"))).await;
+        handler.register_page_start(20, Some(ByteString::from("t"))).await;
+
+        assert_eq!(handler.page_start_for(0).await, (0, None));
+        assert_eq!(handler.page_start_for(9).await, (0, None));
+        assert_eq!(handler.page_start_for(10).await, (10, Some(ByteString::from("j"))));
+        assert_eq!(handler.page_start_for(25).await, (20, Some(ByteString::from("t"))));
+    }
+
+    #[tokio::test]
+    async fn cached_page_covers_its_offsets() {
+        let handler = DirHandler::new(StorageIno(1));
+        handler.cache_page(10, vec![1u8, 2, 3], false).await;
+        assert_eq!(handler.cached_items_from::<u8>(11).await, Some(vec![2, 3]));
+        assert_eq!(handler.cached_items_from::<u8>(9).await, None);
+        // the next page isn't cached:
+        assert_eq!(handler.cached_items_from::<u8>(13).await, None);
+        assert_eq!(handler.cached_items_from::<u16>(11).await, None);
+
+        handler.cache_page(10, vec![1u8, 2, 3], true).await;
+        assert_eq!(handler.cached_items_from::<u8>(13).await, Some(vec![]));
+    }
+}
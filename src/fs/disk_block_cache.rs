@@ -0,0 +1,228 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::error::TiFsResult;
+use super::inode::TiFsHash;
+use super::utils::hash_algorithm::HashAlgorithm;
+
+/// Persistent cache of block data in a local directory.
+/// Blocks are immutable and stored under their hash, so the directory can be shared
+/// by multiple mounts and survives restarts. Entries are verified against their
+/// hash when loaded. The least recently used entries are evicted when the size limit
+/// is exceeded. Errors of the cache are logged and handled as cache misses.
+pub struct DiskBlockCache {
+    dir: PathBuf,
+    max_size: u64,
+    algorithm: HashAlgorithm,
+    used: Arc<AtomicU64>,
+    eviction: Arc<Mutex<()>>,
+}
+
+impl DiskBlockCache {
+    pub fn open(dir: PathBuf, max_size: u64, algorithm: HashAlgorithm) -> TiFsResult<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let used = Self::scan(&dir)?.iter().map(|(_, size, _)| size).sum();
+        tracing::info!("opened disk block cache {dir:?} with {used} of {max_size} bytes used");
+        Ok(Self {
+            dir,
+            max_size,
+            algorithm,
+            used: Arc::new(AtomicU64::new(used)),
+            eviction: Arc::new(Mutex::new(())),
+        })
+    }
+
+    fn path_of(&self, hash: &TiFsHash) -> PathBuf {
+        let name = hash.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        self.dir.join(&name[..2]).join(name)
+    }
+
+    pub async fn get(&self, hash: &TiFsHash) -> Option<Vec<u8>> {
+        let path = self.path_of(hash);
+        let data = tokio::fs::read(&path).await.ok()?;
+        if self.algorithm.calculate_hash(&data) != *hash {
+            tracing::warn!("disk block cache: removing corrupted entry {path:?}");
+            if tokio::fs::remove_file(&path).await.is_ok() {
+                self.used.fetch_sub((data.len() as u64).min(self.used.load(Ordering::Relaxed)), Ordering::Relaxed);
+            }
+            return None;
+        }
+        // the modification time is the last use for the eviction:
+        tokio::task::spawn_blocking(move || {
+            let _ = std::fs::File::options().write(true).open(&path)
+                .and_then(|f| f.set_modified(SystemTime::now()));
+        });
+        Some(data)
+    }
+
+    pub async fn insert(&self, hash: &TiFsHash, data: &[u8]) {
+        let path = self.path_of(hash);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return;
+        }
+        // write to a temporary file first, such that readers never see partial entries:
+        let tmp_path = path.with_extension(format!("tmp{}", Uuid::new_v4().simple()));
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&tmp_path, data).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        }.await;
+        if let Err(err) = result {
+            tracing::warn!("disk block cache: failed to store {path:?}: {err:?}");
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return;
+        }
+        self.used.fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.evict_if_needed();
+    }
+
+    /// Stores the blocks in a background task, such that reads and writes don't wait for the disk.
+    pub fn insert_in_background(self: &Arc<Self>, blocks: Vec<(TiFsHash, Arc<Vec<u8>>)>) {
+        if blocks.is_empty() {
+            return;
+        }
+        let me = self.clone();
+        tokio::spawn(async move {
+            for (hash, data) in blocks {
+                me.insert(&hash, &data).await;
+            }
+        });
+    }
+
+    /// Starts the eviction in the background, such that inserts don't wait for it.
+    fn evict_if_needed(&self) {
+        if self.used.load(Ordering::Relaxed) <= self.max_size {
+            return;
+        }
+        // another task is evicting already:
+        let Ok(guard) = self.eviction.clone().try_lock_owned() else {
+            return;
+        };
+        // evict a bit more than needed, to not run this for every insert:
+        let target = self.max_size / 10 * 9;
+        let dir = self.dir.clone();
+        let used = self.used.clone();
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            match Self::evict_oldest(&dir, target) {
+                Ok(remaining) => used.store(remaining, Ordering::Relaxed),
+                Err(err) => tracing::warn!("disk block cache: eviction failed: {err:?}"),
+            }
+        });
+    }
+
+    /// Rescans the directory, as other mounts might share it. Returns the remaining size.
+    fn evict_oldest(dir: &Path, target: u64) -> io::Result<u64> {
+        let mut entries = Self::scan(dir)?;
+        let mut total = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, path) in entries {
+            if total <= target {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Entries removed concurrently, e.g. by the eviction of another mount
+    /// or renamed temporary files, are skipped.
+    fn scan(dir: &Path) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+        fn skip_not_found<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+            match result {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                other => other.map(Some),
+            }
+        }
+        let mut entries = Vec::new();
+        for sub_dir in std::fs::read_dir(dir)? {
+            let sub_dir = sub_dir?;
+            if !sub_dir.file_type()?.is_dir() {
+                continue;
+            }
+            let Some(sub_entries) = skip_not_found(std::fs::read_dir(sub_dir.path()))? else {
+                continue;
+            };
+            for entry in sub_entries {
+                let entry = entry?;
+                let Some(meta) = skip_not_found(entry.metadata())? else {
+                    continue;
+                };
+                if meta.is_file() {
+                    entries.push((meta.modified()?, meta.len(), entry.path()));
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tifs-disk-block-cache-{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn corrupted_entries_are_rejected() {
+        let dir = temp_cache_dir();
+        let algo = HashAlgorithm::Blake3;
+        let cache = DiskBlockCache::open(dir.clone(), 1000, algo).unwrap();
+
+        let data = b"12345678".to_vec();
+        let hash = algo.calculate_hash(&data);
+        cache.insert(&hash, &data).await;
+        assert_eq!(cache.get(&hash).await, Some(data.clone()));
+
+        std::fs::write(cache.path_of(&hash), b"87654321").unwrap();
+        assert_eq!(cache.get(&hash).await, None);
+        assert!(!cache.path_of(&hash).exists());
+        assert_eq!(cache.used.load(Ordering::Relaxed), 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted() {
+        let dir = temp_cache_dir();
+        let algo = HashAlgorithm::Blake3;
+        // large enough that the inserts don't start the background eviction:
+        let cache = DiskBlockCache::open(dir.clone(), 1000, algo).unwrap();
+
+        let blocks = [b"aaaaaaaa", b"bbbbbbbb", b"cccccccc"].map(|data| {
+            (algo.calculate_hash(data), data.to_vec())
+        });
+        for (hash, data) in &blocks {
+            cache.insert(hash, data).await;
+        }
+        // last uses: the second block is the least recently used one, then the first:
+        let now = SystemTime::now();
+        for (index, age) in [(0, 20), (1, 30), (2, 10)] {
+            std::fs::File::options().write(true).open(cache.path_of(&blocks[index].0)).unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age)).unwrap();
+        }
+
+        assert_eq!(DiskBlockCache::evict_oldest(&dir, 16).unwrap(), 16);
+        assert!(cache.path_of(&blocks[0].0).exists());
+        assert!(!cache.path_of(&blocks[1].0).exists());
+        assert!(cache.path_of(&blocks[2].0).exists());
+
+        assert_eq!(DiskBlockCache::evict_oldest(&dir, 8).unwrap(), 8);
+        assert!(!cache.path_of(&blocks[0].0).exists());
+        assert!(cache.path_of(&blocks[2].0).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
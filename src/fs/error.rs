@@ -41,12 +41,27 @@ pub enum FsError {
     #[error("unknown whence({whence})")]
     UnknownWhence { whence: i32 },
 
+    #[error("no data or hole at or after offset({offset}) of ino({ino})")]
+    NoDataOrHoleAfterOffset { ino: u64, offset: i64 },
+
+    #[error("unsupported fallocate mode({mode:#x})")]
+    UnsupportedFallocateMode { mode: i32 },
+
     #[error("cannot find block(<{inode}>[{block}])")]
     BlockNotFound { inode: StorageIno, block: u64 },
 
     #[error("dir({dir}) not empty")]
     DirNotEmpty { dir: String },
 
+    #[error("({file}) is not a directory")]
+    NotDirectory { file: String },
+
+    #[error("({file}) is a directory")]
+    IsDirectory { file: String },
+
+    #[error("invalid rename flags({flags:#x})")]
+    InvalidRenameFlags { flags: u32 },
+
     #[error("invalid string")]
     InvalidStr,
 
@@ -77,6 +92,18 @@ pub enum FsError {
     #[error("no space left: MaxSize({0})")]
     NoSpaceLeft(u64),
 
+    #[error("quota of {target} exceeded")]
+    QuotaExceeded { target: String },
+
+    #[error("directory with children can't be moved to another quota")]
+    CrossQuotaRename,
+
+    #[error("unknown block hash: {hash:?}")]
+    UnknownBlockHash { hash: TiFsHash },
+
+    #[error("permission denied")]
+    PermissionDenied,
+
     #[error("Read checksum mismatch: hash: {hash:?} vs. actual: {actual_hash:?}")]
     ChecksumMismatch{hash: TiFsHash, actual_hash: TiFsHash},
 
@@ -158,15 +185,25 @@ impl From<FsError> for libc::c_int {
             FhNotFound { fh: _ } => libc::EBADF,
             InvalidOffset { ino: _, offset: _ } => libc::EINVAL,
             UnknownWhence { whence: _ } => libc::EINVAL,
+            NoDataOrHoleAfterOffset { ino: _, offset: _ } => libc::ENXIO,
+            UnsupportedFallocateMode { mode: _ } => libc::EOPNOTSUPP,
             BlockNotFound { inode: _, block: _ } => libc::EINVAL,
             DirNotEmpty { dir: _ } => libc::ENOTEMPTY,
+            NotDirectory { file: _ } => libc::ENOTDIR,
+            IsDirectory { file: _ } => libc::EISDIR,
+            InvalidRenameFlags { flags: _ } => libc::EINVAL,
             UnknownFileType => libc::EINVAL,
             KeyError(_) => libc::EAGAIN,
             RetryTimesExcess(_) => libc::EAGAIN,
             InvalidStr => libc::EINVAL,
             BlockSizeConflict { origin: _, new: _ } => libc::EINVAL,
             NoSpaceLeft(_) => libc::ENOSPC,
+            QuotaExceeded { target: _ } => libc::EDQUOT,
+            CrossQuotaRename => libc::EXDEV,
+            UnknownBlockHash { hash: _ } => libc::EINVAL,
+            PermissionDenied => libc::EPERM,
             ChecksumMismatch { hash: _, actual_hash: _ } => libc::ERANGE,
+            InoKindNotSupported(_) => libc::EINVAL,
             _ => libc::EFAULT,
         }
     }
@@ -57,6 +57,8 @@ pub struct FileHandler {
     pub write_accumulator: RwLock<WriteTaskDataList>,
     pub read_ahead: RwLock<AsyncParallelPipeStage<BoxFuture<'static, TiFsResult<()>>>>,
     pub read_ahead_map: RwLock<ReadAhead>,
+    /// Text of the stats virtual file, generated on the first read of this handle.
+    pub stats_text: RwLock<Option<Bytes>>,
 }
 
 impl FileHandler {
@@ -74,7 +76,8 @@ impl FileHandler {
             write_cache: RwLock::new(AsyncParallelPipeStage::new(fs_config.write_in_progress_limit)),
             write_accumulator: RwLock::new(WriteTaskDataList(vec![])),
             read_ahead: RwLock::new(AsyncParallelPipeStage::new(2)),
-            read_ahead_map: RwLock::new(ReadAhead { cached_ranges: RangeSet2::empty() })
+            read_ahead_map: RwLock::new(ReadAhead { cached_ranges: RangeSet2::empty() }),
+            stats_text: RwLock::new(None),
         }
     }
 
@@ -1,11 +1,13 @@
 
 
+use std::time::Duration;
+
 use fuser::MountOption as FuseMountOption;
 use paste::paste;
 use tracing::error;
 use parse_size::parse_size;
 
-use super::{error::{FsError, TiFsResult}, inode::TiFsHash, key::{KeyParser, ScopedKeyBuilder}, utils::hash_algorithm::{HashAlgorithm, ALGO_HASH_LEN_MAP, ALGO_NAME_MAP}};
+use super::{error::{FsError, TiFsResult}, inode::TiFsHash, key::{KeyParser, ScopedKeyBuilder}, utils::{full_hash::FullHashMode, hash_algorithm::{HashAlgorithm, ALGO_HASH_LEN_MAP, ALGO_NAME_MAP}}};
 
 
 macro_rules! define_options {
@@ -280,8 +282,25 @@ define_options! { MountOption (FuseMountOption) {
     define InlineDataLimit(String),
     define SmallTxns,
     define WriteAccumulatorFlushThreshold(String),
+    define FullHash(String),
+    define BlockCacheDir(String),
+    define BlockCacheSize(String),
+    define AttrTtl(String),
+    define EntryTtl(String),
+    define CombinedWrite,
+    define HashFsTls(String), // tls config of the hash fs gRPC connection
+    define HashFsTokenFile(String),
+    define HashFsName(String), // filesystem of a multi filesystem hash fs server
+    define HashFsCompression(String), // gzip, zstd or none
+    define HashFsCompressionThreshold(String), // smallest compressed block upload
 }}
 
+/// Parses seconds with optional fraction, e.g. "0.5".
+fn parse_duration_secs(value: &str) -> Result<Duration, String> {
+    let secs = value.parse::<f64>().map_err(|err| err.to_string())?;
+    Duration::try_from_secs_f64(secs).map_err(|err| err.to_string())
+}
+
 #[derive(Clone)]
 pub struct TiFsConfig {
     pub key_prefix: Vec<u8>,
@@ -291,10 +310,18 @@ pub struct TiFsConfig {
     pub enable_mtime: bool,
     pub direct_io: bool,
     pub inode_cache_size: u64,
+    /// Validity of attributes in the kernel and in the metadata cache.
+    pub attr_ttl: Duration,
+    /// Validity of directory entries (name -> inode) in the kernel and in the metadata cache.
+    pub entry_ttl: Duration,
     pub hashed_blocks: bool,
     pub hash_algorithm: HashAlgorithm,
     pub hash_len: usize,
+    pub full_hash_mode: FullHashMode,
     pub hashed_blocks_cache_size: u64,
+    /// Directory of the persistent block cache. None disables it.
+    pub block_cache_dir: Option<std::path::PathBuf>,
+    pub block_cache_size: u64,
     pub max_size: Option<u64>,
     pub validate_writes: bool,
     pub validate_read_hashes: bool,
@@ -309,6 +336,8 @@ pub struct TiFsConfig {
     pub read_ahead_in_progress_limit: usize,
     pub small_transactions: bool,
     pub chunked_block_upload: bool,
+    /// Writes the blocks with a single inode_write_blocks call.
+    pub combined_write: bool,
 }
 
 impl TiFsConfig {
@@ -328,6 +357,7 @@ impl TiFsConfig {
         let mut parallel_jobs_delete = 0;
         let chunked_block_upload = true;
         let mut write_accumulator_flush_threshold = 2 << 20;
+        let mut full_hash_mode = FullHashMode::Flat;
 
         // iterate over options and overwrite defaults
         for option in options {
@@ -384,6 +414,13 @@ impl TiFsConfig {
                                 msg: format!("fail to parse WriteAccumulatorFlushThreshold({}): {}", value, err) }
                         })?;
                 }
+                MountOption::FullHash(value) => {
+                    full_hash_mode = FullHashMode::from_name(value).ok_or(
+                        FsError::ConfigParsingFailed {
+                            msg: format!("full hash mode unsupported: {} (flat|merkle)", value)
+                        }
+                    )?;
+                }
                 _ => {}
             }
         }
@@ -413,11 +450,26 @@ impl TiFsConfig {
                     }).ok()
                 } else { None }
             }).unwrap_or(10 << 20),
+            attr_ttl: options.iter().find_map(|opt|{
+                if let MountOption::AttrTtl(value) = &opt {
+                    parse_duration_secs(value).map_err(|err|{
+                        error!("fail to parse AttrTtl({}): {}", value, err);
+                    }).ok()
+                } else { None }
+            }).unwrap_or(Duration::from_secs(1)),
+            entry_ttl: options.iter().find_map(|opt|{
+                if let MountOption::EntryTtl(value) = &opt {
+                    parse_duration_secs(value).map_err(|err|{
+                        error!("fail to parse EntryTtl({}): {}", value, err);
+                    }).ok()
+                } else { None }
+            }).unwrap_or(Duration::from_secs(1)),
             hashed_blocks: options.iter().find_map(|opt|{
                 (MountOption::HashedBlocks == *opt).then_some(true)
             }).unwrap_or(false),
             hash_algorithm: hash_algo,
             hash_len: hash_len,
+            full_hash_mode,
             hashed_blocks_cache_size: options.iter().find_map(|opt|{
                 if let MountOption::HashedBlocksCacheSize(value) = &opt {
                     parse_size(value).map_err(|err|{
@@ -425,6 +477,18 @@ impl TiFsConfig {
                     }).ok()
                 } else { None }
             }).unwrap_or(200 << 20),
+            block_cache_dir: options.iter().find_map(|opt|{
+                if let MountOption::BlockCacheDir(value) = &opt {
+                    Some(std::path::PathBuf::from(value))
+                } else { None }
+            }),
+            block_cache_size: options.iter().find_map(|opt|{
+                if let MountOption::BlockCacheSize(value) = &opt {
+                    parse_size(value).map_err(|err|{
+                        error!("fail to parse BlockCacheSize({}): {}", value, err);
+                    }).ok()
+                } else { None }
+            }).unwrap_or(1 << 30),
             validate_writes: options.iter().find_map(|opt|{
                 (MountOption::ValidateWrites == *opt).then_some(true)
             }).unwrap_or(false),
@@ -469,6 +533,9 @@ impl TiFsConfig {
             }).unwrap_or(10),
             small_transactions,
             chunked_block_upload,
+            combined_write: options.iter().find_map(|opt|{
+                (MountOption::CombinedWrite == *opt).then_some(true)
+            }).unwrap_or(false),
             write_accumulator_flush_threshold,
         };
         Ok(cfg)
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::TiFsResult;
+use super::hash_fs_interface::BlockIndex;
+use super::index::serialize_json;
+use super::inode::TiFsHash;
+
+/// Filesystem wide deduplication statistics.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FsStats {
+    /// Sum of the sizes of all inodes.
+    pub logical_bytes: u64,
+    pub inode_count: u64,
+    /// Number of distinct stored blocks.
+    pub unique_blocks: u64,
+    /// Number of block references of all inodes. Equals unique_blocks without deduplication.
+    pub referenced_blocks: u64,
+    /// unique_blocks * block_size. This is an upper bound of the stored bytes,
+    /// as the last block of a file may be shorter.
+    pub unique_block_capacity_bytes: u64,
+    /// Number of blocks per reference count. Counts are rounded up to the next power of two.
+    pub refcount_histogram: BTreeMap<u64, u64>,
+}
+
+impl FsStats {
+    /// Sets the reference statistics from the sum of all BlockStatsDelta shards.
+    pub fn set_block_stats(&mut self, sum: &BlockStatsDelta) {
+        self.referenced_blocks = sum.referenced_blocks.max(0) as u64;
+        self.refcount_histogram = sum.refcount_histogram.iter()
+            .filter(|(_bucket, blocks)| **blocks > 0)
+            .map(|(bucket, blocks)| (*bucket, *blocks as u64))
+            .collect();
+    }
+
+    /// Block references per stored block.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_blocks == 0 {
+            return 1.0;
+        }
+        self.referenced_blocks as f64 / self.unique_blocks as f64
+    }
+
+    /// Content of the .@stats virtual file.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "logical_bytes: {}", self.logical_bytes);
+        let _ = writeln!(out, "inode_count: {}", self.inode_count);
+        let _ = writeln!(out, "unique_blocks: {}", self.unique_blocks);
+        let _ = writeln!(out, "referenced_blocks: {}", self.referenced_blocks);
+        let _ = writeln!(out, "unique_block_capacity_bytes: {}", self.unique_block_capacity_bytes);
+        let _ = writeln!(out, "dedup_ratio: {:.3}", self.dedup_ratio());
+        let _ = writeln!(out, "refcount_histogram:");
+        for (count, blocks) in self.refcount_histogram.iter() {
+            let _ = writeln!(out, "  <={}: {}", count, blocks);
+        }
+        out
+    }
+}
+
+/// Change of the block reference statistics. Written like UsageDelta to a random
+/// shard by the transactions that change reference counters, such that reading
+/// the statistics doesn't need to scan all blocks. Blocks that were stored before
+/// the statistics were tracked are only counted once their counter changes.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BlockStatsDelta {
+    pub referenced_blocks: i64,
+    /// Change of the number of blocks per histogram bucket, see FsStats.
+    pub refcount_histogram: BTreeMap<u64, i64>,
+}
+
+impl BlockStatsDelta {
+    /// Records the change of the reference counter of one block.
+    pub fn add_count_change(&mut self, prev: u64, new: u64) {
+        if prev == new {
+            return;
+        }
+        self.referenced_blocks = self.referenced_blocks
+            .saturating_add(new as i64).saturating_sub(prev as i64);
+        if prev > 0 {
+            *self.refcount_histogram.entry(prev.next_power_of_two()).or_default() -= 1;
+        }
+        if new > 0 {
+            *self.refcount_histogram.entry(new.next_power_of_two()).or_default() += 1;
+        }
+        self.refcount_histogram.retain(|_bucket, blocks| *blocks != 0);
+    }
+
+    pub fn add(&mut self, other: &BlockStatsDelta) {
+        self.referenced_blocks = self.referenced_blocks.saturating_add(other.referenced_blocks);
+        for (bucket, blocks) in other.refcount_histogram.iter() {
+            *self.refcount_histogram.entry(*bucket).or_default() += blocks;
+        }
+        self.refcount_histogram.retain(|_bucket, blocks| *blocks != 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.referenced_blocks == 0 && self.refcount_histogram.is_empty()
+    }
+}
+
+/// Deduplication statistics of a single file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub size: u64,
+    pub mapped_blocks: u64,
+    /// Blocks whose data is also referenced elsewhere (by this or other files).
+    pub shared_blocks: u64,
+    /// Blocks whose data is only referenced once.
+    pub exclusive_blocks: u64,
+}
+
+impl FileStats {
+    /// Content of the .<name>.@stats virtual file.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "size: {}", self.size);
+        let _ = writeln!(out, "mapped_blocks: {}", self.mapped_blocks);
+        let _ = writeln!(out, "shared_blocks: {}", self.shared_blocks);
+        let _ = writeln!(out, "exclusive_blocks: {}", self.exclusive_blocks);
+        out
+    }
+}
+
+/// One line of the .<name>.@blocks virtual file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BlockInfo {
+    pub index: u64,
+    /// Hex encoded hash. None for holes.
+    pub hash: Option<String>,
+    /// Length of the block within the file.
+    pub len: u64,
+    /// Global reference count of the block data.
+    pub refcount: u64,
+}
+
+impl BlockInfo {
+    pub fn new(index: BlockIndex, hash: Option<&TiFsHash>, len: u64, refcount: u64) -> Self {
+        Self {
+            index: index.0,
+            hash: hash.map(|h| h.iter().map(|b| format!("{b:02x}")).collect()),
+            len,
+            refcount,
+        }
+    }
+
+    /// All lines have the same length, such that a read at any offset
+    /// only needs to fetch the blocks that are covered by the read.
+    pub const fn line_len(hash_len: usize) -> usize {
+        const MAX_U64_DIGITS: usize = 20;
+        const SYNTAX: usize = r#"{"index":,"hash":"","len":,"refcount":}"#.len() + 1; // + newline
+        SYNTAX + 3 * MAX_U64_DIGITS + 2 * hash_len
+    }
+
+    /// JSON line padded with spaces to line_len(hash_len).
+    pub fn to_fixed_width_line(&self, hash_len: usize) -> TiFsResult<Vec<u8>> {
+        let line_len = Self::line_len(hash_len);
+        let mut line = serialize_json(self)?;
+        line.resize(line_len - 1, b' ');
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_info_lines_have_fixed_width() {
+        let hash_len = 32;
+        let hash = vec![0xab; hash_len];
+        for info in [
+            BlockInfo::new(BlockIndex(u64::MAX), Some(&hash), u64::MAX, u64::MAX),
+            BlockInfo::new(BlockIndex(0), None, 0, 0),
+        ] {
+            let line = info.to_fixed_width_line(hash_len).unwrap();
+            assert_eq!(line.len(), BlockInfo::line_len(hash_len));
+            assert_eq!(line.last(), Some(&b'\n'));
+            let parsed: serde_json::Value = serde_json::from_slice(&line).unwrap();
+            assert_eq!(parsed["index"], info.index);
+        }
+    }
+
+    #[test]
+    fn count_changes_move_blocks_between_histogram_buckets() {
+        let mut shard_a = BlockStatsDelta::default();
+        for (prev, new) in [(0, 1), (0, 1), (0, 3), (3, 4), (0, 5)] {
+            shard_a.add_count_change(prev, new);
+        }
+        let mut shard_b = BlockStatsDelta::default();
+        for (prev, new) in [(1, 2), (5, 0), (0, 0)] {
+            shard_b.add_count_change(prev, new);
+        }
+        let mut sum = BlockStatsDelta::default();
+        sum.add(&shard_a);
+        sum.add(&shard_b);
+
+        let mut stats = FsStats { unique_blocks: 3, ..Default::default() };
+        stats.set_block_stats(&sum);
+        assert_eq!(stats.referenced_blocks, 7);
+        assert_eq!(stats.refcount_histogram, BTreeMap::from([(1, 1), (2, 1), (4, 1)]));
+        assert!((stats.dedup_ratio() - 7.0 / 3.0).abs() < f64::EPSILON);
+    }
+}
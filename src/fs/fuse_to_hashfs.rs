@@ -1,13 +1,15 @@
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::iter::FromIterator;
 use std::mem;
 use std::ops::{DerefMut, Range};
 use std::sync::{Arc, Weak};
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use bytes::Bytes;
 use bytestring::ByteString;
 use fuser::TimeOrNow;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
 use multimap::MultiMap;
 use num_bigint::BigUint;
 use num_format::{Buffer, Locale, ToFormattedString};
@@ -23,15 +25,15 @@ use crate::fs::meta::MetaStatic;
 use crate::fs::utils::stop_watch::AutoStopWatch;
 use crate::utils::async_parallel_pipe_stage::AsyncParallelPipeStage;
 
-use super::dir::StorageDirectory;
 use super::error::{FsError, TiFsResult};
 use super::file_handler::{FileHandler, WriteTaskDataList};
 use super::fs_config::TiFsConfig;
+use super::fs_stats::BlockInfo;
 use super::hash_block::block_splitter::{BlockSplitterRead, BlockSplitterWrite};
 use super::hash_block::helpers::UpdateIrregularBlock;
-use super::hash_fs_interface::{BlockIndex, GotOrMade, HashFsInterface};
-use super::inode::{InoAccessTime, InoDescription, InoSize, ParentStorageIno, StorageDirItem, InoStorageFileAttr, StorageFilePermission, TiFsHash};
-use super::key::SNAPSHOT_PARENT_INODE;
+use super::hash_fs_interface::{BlockDataSource, BlockIndex, DirectoryPage, GotOrMade, HashFsInterface, InoAllAttributes, RenameMode};
+use super::inode::{DirectoryItem, InoAccessTime, InoDescription, InoSize, ParentStorageIno, StorageDirItem, InoStorageFileAttr, StorageFilePermission, TiFsHash};
+use super::key::{ROOT_INODE, SNAPSHOT_PARENT_INODE};
 use super::mode::as_file_perm;
 use super::parsers;
 use super::reply::StatFs;
@@ -41,6 +43,13 @@ pub const DEFAULT_REGION_BACKOFF: Backoff = Backoff::no_jitter_backoff(300, 1000
 pub const OPTIMISTIC_BACKOFF: Backoff = Backoff::no_jitter_backoff(30, 500, 1000);
 pub const PESSIMISTIC_BACKOFF: Backoff = Backoff::no_backoff();
 pub const MAX_TIKV_SCAN_LIMIT: u32 = 10240;
+/// Free space reported by statfs without a configured max_size.
+/// Kept far below u64::MAX such that byte counts of tools don't overflow.
+pub const UNLIMITED_FREE_BYTES: u64 = 1 << 50;
+/// Free inodes reported by statfs.
+pub const UNLIMITED_FREE_INODES: u64 = 1 << 32;
+/// Blocks that are hashed before they are handed over to the upload.
+pub const WRITE_PIPELINE_BATCH_SIZE: usize = 64;
 
 pub fn make_chunks_hash_map<K,V>(input: HashMap<K,V>, max_chunk_size: usize) -> VecDeque<HashMap<K,V>>
 where
@@ -182,24 +191,90 @@ impl Txn {
        Ok(self.hash_fs.inode_close(ino, use_id).await?)
     }
 
-    #[instrument(skip(self))]
-    pub async fn read_hash_of_file(self: TxnArc, ino: StorageIno, offset: u64, size: u64) -> TiFsResult<Vec<u8>> {
-
+    fn slice_of_hash(&self, offset: u64, size: u64) -> Option<std::ops::Range<usize>> {
         let hash_size = self.fs_config.hash_len as u64;
         let offset = offset.clamp(0, hash_size);
         let remaining = hash_size - offset;
         let size = size.clamp(0, remaining);
+        (size > 0).then_some(offset as usize..(offset+size) as usize)
+    }
 
-        if size == 0 {
+    #[instrument(skip(self))]
+    pub async fn read_hash_of_file(self: TxnArc, ino: StorageIno, offset: u64, size: u64) -> TiFsResult<Vec<u8>> {
+        let Some(range) = self.slice_of_hash(offset, size) else {
             return Ok(vec![]);
-        }
+        };
 
         let the_hash = self.hash_fs.file_get_hash(ino).await?;
 
         trace!("precalculated_hash: {the_hash:x?}");
-        let mut result = Vec::with_capacity(self.fs_config.hash_len);
-        result.extend_from_slice(&the_hash[offset as usize..(offset+size) as usize]);
-        Ok(result)
+        Ok(the_hash[range].to_vec())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn read_merkle_hash_of_file(self: TxnArc, ino: StorageIno, offset: u64, size: u64) -> TiFsResult<Vec<u8>> {
+        let Some(range) = self.slice_of_hash(offset, size) else {
+            return Ok(vec![]);
+        };
+
+        let the_hash = self.hash_fs.file_get_merkle_hash(ino).await?;
+        Ok(the_hash[range].to_vec())
+    }
+
+    /// Text of the stats virtual file. The root inode reports the filesystem wide stats.
+    pub async fn stats_text_of_ino(self: TxnArc, ino: StorageIno) -> TiFsResult<String> {
+        Ok(if ino == ROOT_INODE.0 {
+            self.hash_fs.fs_stats().await?.to_text()
+        } else {
+            self.hash_fs.file_stats(ino).await?.to_text()
+        })
+    }
+
+    /// JSON lines of the blocks virtual file. Every line has the same length,
+    /// so only the blocks covered by the requested range are fetched.
+    pub async fn read_block_infos_of_file(self: TxnArc, ino: StorageIno, offset: u64, size: u64) -> TiFsResult<Vec<u8>> {
+        let hash_len = self.fs_config.hash_len;
+        let line_len = BlockInfo::line_len(hash_len) as u64;
+        let (_desc, _attr, ino_size, _atime) = self.hash_fs.inode_get_all_attributes(ino).await?;
+        let first_block = (offset / line_len).min(ino_size.blocks());
+        let end_block = (offset + size).div_ceil(line_len).min(ino_size.blocks());
+        if first_block >= end_block {
+            return Ok(vec![]);
+        }
+
+        let block_range = BlockIndex(first_block)..BlockIndex(end_block);
+        let hashes = self.hash_fs.inode_read_block_hashes_block_range(
+            ino, &[block_range.clone()]).await?;
+        let unique_hashes = hashes.values().cloned().collect::<HashSet<_>>()
+            .into_iter().collect::<Vec<_>>();
+        let source = BlockDataSource { ino, block_ranges: vec![block_range.clone()] };
+        let ref_counts = self.hash_fs.hb_get_reference_counts(&unique_hashes, &source).await?;
+
+        let mut data = Vec::with_capacity(((end_block - first_block) * line_len) as usize);
+        for index in block_range {
+            let block_start = index.0 * self.block_size;
+            let len = ino_size.size().saturating_sub(block_start).min(self.block_size);
+            let hash = hashes.get(&index);
+            let ref_count = hash.and_then(|h| ref_counts.get(h)).cloned().unwrap_or(0);
+            data.extend(BlockInfo::new(index, hash, len, ref_count).to_fixed_width_line(hash_len)?);
+        }
+
+        let skip = (offset - first_block * line_len) as usize;
+        let end = (skip + size as usize).min(data.len());
+        Ok(data[skip.min(end)..end].to_vec())
+    }
+
+    /// Inverse of reading the hashes virtual file: maps already stored blocks to the file.
+    pub async fn set_blocks_from_hashes(
+        self: TxnArc,
+        ino: StorageIno,
+        first_block: BlockIndex,
+        hashes: Vec<TiFsHash>,
+        truncate: bool,
+    ) -> TiFsResult<()> {
+        self.hash_fs.inode_set_blocks_from_hashes(ino, first_block, &hashes, truncate).await?;
+        self.caches.meta.invalidate_ino(ino).await;
+        Ok(())
     }
 
     pub async fn read(self: Arc<Self>, ino: StorageIno, start: u64, size: u32) -> TiFsResult<Vec<u8>> {
@@ -217,6 +292,7 @@ impl Txn {
             name.clone(),
             *ino,
         ).await?;
+        self.caches.meta.invalidate_entry(parent, name).await;
         Ok(())
     }
 
@@ -231,13 +307,17 @@ impl Txn {
         rdev: u32,
         inline_data: Option<Vec<u8>>,
     ) -> TiFsResult<GotOrMade<StorageDirItem>> {
-        self.hash_fs.directory_add_child_checked_new_inode(
-            parent, name, typ, perm, gid, uid, rdev, inline_data).await.map_err(|e|e.into())
+        let result = self.hash_fs.directory_add_child_checked_new_inode(
+            parent, name.clone(), typ, perm, gid, uid, rdev, inline_data).await?;
+        self.caches.meta.invalidate_entry(parent, &name).await;
+        Ok(result)
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn directory_remove_child(self: TxnArc, parent: ParentStorageIno, name: ByteString) -> TiFsResult<()> {
-        Ok(self.hash_fs.directory_remove_child_file(parent, name).await?)
+        self.hash_fs.directory_remove_child_file(parent, name.clone()).await?;
+        self.caches.meta.invalidate_entry(parent, &name).await;
+        Ok(())
     }
 
     pub async fn read_static_meta(self: TxnArc) -> TiFsResult<Option<MetaStatic>> {
@@ -252,9 +332,16 @@ impl Txn {
         //tracing::debug!("block_hashes(count: {}): {:?}", block_hashes.len(), block_hashes);
         let block_hashes_set = HashSet::from_iter(block_hashes.values().cloned());
         let bs = BlockSplitterRead::new(self.fs_config.block_size, start, size);
-        let blocks_data = self.clone().hb_get_block_data_by_hashes_cached(&block_hashes_set).await?;
+        let source = BlockDataSource {
+            ino,
+            block_ranges: vec![BlockIndex(start / self.fs_config.block_size)
+                ..BlockIndex((start + size).div_ceil(self.fs_config.block_size))],
+        };
+        let blocks_data = self.clone().hb_get_block_data_by_hashes_cached(&block_hashes_set, source).await?;
 
-        let result = parsers::hb_read_from_blocks(&bs, &block_hashes, &blocks_data)?;
+        let (_desc, _attr, ino_size, _atime) = self.clone().get_all_ino_data(ino).await?;
+        let result = parsers::hb_read_from_blocks(
+            &bs, &block_hashes, &blocks_data, ino_size.size())?;
 
         if tracing::enabled!(Level::DEBUG) {
             let mut buf_start = Buffer::default();
@@ -388,7 +475,10 @@ impl Txn {
         Ok(())
     }
 */
-    pub async fn hb_get_block_data_by_hashes_cached(self: TxnArc, hash_list: &HashSet<TiFsHash>
+    pub async fn hb_get_block_data_by_hashes_cached(
+        self: TxnArc,
+        hash_list: &HashSet<TiFsHash>,
+        source: BlockDataSource,
     ) -> TiFsResult<HashMap<TiFsHash, Arc<Vec<u8>>>> {
         let mut watch = AutoStopWatch::start("get_hash_blocks_data");
 
@@ -404,12 +494,33 @@ impl Txn {
 
         watch.sync("cached");
 
+        if let Some(disk_cache) = &self.caches.disk_block {
+            let mut still_uncached = HashSet::new();
+            for hash in uncached_block_hashes {
+                if let Some(data) = disk_cache.get(hash).await {
+                    let data = Arc::new(data);
+                    self.caches.block.insert(hash.clone(), data.clone()).await;
+                    result.insert(hash.clone(), data);
+                } else {
+                    still_uncached.insert(hash);
+                }
+            }
+            uncached_block_hashes = still_uncached;
+
+            watch.sync("disk_cached");
+        }
+
         if uncached_block_hashes.len() == 0 {
             return Ok(result);
         }
 
-        let uncached_blocks = self.hash_fs
-            .hb_get_block_data_by_hashes(&uncached_block_hashes).await?;
+        let mut fetched = self.hash_fs.hb_get_block_data_by_hashes_stream(
+            uncached_block_hashes.into_iter().cloned().collect(), source).await?;
+        let mut uncached_blocks = HashMap::new();
+        while let Some(block) = fetched.next().await {
+            let (hash, value) = block?;
+            uncached_blocks.insert(hash, value);
+        }
 
         for (hash, value) in &uncached_blocks {
             self.caches.block.insert(hash.clone(), value.clone()).await;
@@ -428,6 +539,11 @@ impl Txn {
             watch.sync("validate");
         }
 
+        if let Some(disk_cache) = &self.caches.disk_block {
+            disk_cache.insert_in_background(uncached_blocks.iter()
+                .map(|(hash, value)| (hash.clone(), value.clone())).collect());
+        }
+
         result.extend(uncached_blocks.into_iter());
 
         watch.sync("done");
@@ -482,9 +598,17 @@ impl Txn {
         let block_ranges = bs_list.iter()
             .map(|bs|bs.get_range()).collect::<Vec<_>>();
 
-        // 1. Remote interaction: Read existing block hashes for ranges:
-        let hash_list_prev = self.hash_fs.inode_read_block_hashes_block_range(
-            ino, &block_ranges).await?;
+        // 1. Remote interaction: Read existing block hashes for ranges.
+        // With combined_write only needed for partially written blocks:
+        let full_blocks_only = bs_list.iter().all(|bs|{
+            bs.first_data.data.is_empty() && bs.last_data.data.is_empty()
+        });
+        let hash_list_prev = if self.fs_config.combined_write && full_blocks_only {
+            BTreeMap::new()
+        } else {
+            self.hash_fs.inode_read_block_hashes_block_range(
+                ino, &block_ranges).await?
+        };
 
         let input_block_hashes = hash_list_prev.len();
         watch.sync("hp");
@@ -512,7 +636,10 @@ impl Txn {
 
         // 2. Remote interaction: Read data of first and last block (only if needed)
         let pre_data = self.clone()
-            .hb_get_block_data_by_hashes_cached(&pre_data_hash_request).await?;
+            .hb_get_block_data_by_hashes_cached(&pre_data_hash_request, BlockDataSource {
+                ino,
+                block_ranges: block_ranges.clone(),
+            }).await?;
         watch.sync("pd");
 
         let mut new_blocks = HashMap::new();
@@ -522,81 +649,145 @@ impl Txn {
             first_data_handler.get_and_modify_block_and_publish_hash(&pre_data, &mut new_blocks, &mut new_block_index_hash);
             last_data_handler.get_and_modify_block_and_publish_hash(&pre_data, &mut new_blocks, &mut new_block_index_hash);
         }
+        let irregular_blocks = new_block_index_hash.into_iter().map(|(index, hash)|{
+            let data = new_blocks.get(&hash).unwrap().clone();
+            (index, hash, data)
+        }).collect::<Vec<_>>();
 
-        for bs in &bs_list {
-            for (index, chunk) in bs.mid_data.data.chunks(self.block_size as usize).enumerate() {
-                let hash = self.fs_config.calculate_hash(chunk);
-                new_blocks.insert(hash.clone(), Arc::new(chunk.to_vec()));
-                new_block_index_hash.insert(BlockIndex(bs.mid_data.block_index.0 + index as u64), hash);
-            }
-        }
-        watch.sync("hash");
-
-        for (hash, new_block) in &new_blocks {
-            self.caches.block.insert(hash.clone(), new_block.clone()).await;
-        }
-        watch.sync("add_cache");
+        let mid_chunks = bs_list.iter().flat_map(|bs|{
+            bs.mid_data.data.chunks(self.block_size as usize).enumerate().map(|(index, chunk)|{
+                (BlockIndex(bs.mid_data.block_index.0 + index as u64), chunk)
+            })
+        }).collect::<Vec<_>>();
+        // The mid blocks are hashed batch-wise, such that the upload of
+        // the first batches overlaps with the hashing of the following ones.
+        let batches = std::iter::once(irregular_blocks).chain(
+            mid_chunks.chunks(WRITE_PIPELINE_BATCH_SIZE).map(|batch|{
+                batch.iter().map(|(index, chunk)|{
+                    let hash = self.fs_config.calculate_hash(chunk);
+                    (*index, hash, Arc::new(chunk.to_vec()))
+                }).collect::<Vec<_>>()
+            }));
 
-        // filter out unchanged blocks:
         let mut skipped_new_block_hashes = 0;
-        for (block_index, prev_block_hash) in hash_list_prev.iter() {
-            if let Some(new_block_hash) = new_block_index_hash.get(block_index) {
-                if prev_block_hash == new_block_hash {
-                    new_block_index_hash.remove(block_index);
-                    skipped_new_block_hashes += 1;
-                }
-            }
-        }
-
-        let new_block_hashes_len = new_block_index_hash.len();
-        let mm = new_block_index_hash.into_iter()
-            .map(|(k,v)| (v,k)).collect::<MultiMap<_,_>>();
-
+        let mut new_block_hashes_len = 0;
+        // chunked_block_upload: addresses are registered after all uploads finished.
+        let mut addresses = MultiMap::new();
+        let mut block_lens = HashMap::new();
+        // combined_write: data of all blocks, only the missing ones are transferred.
+        let mut combined_data = HashMap::new();
         let mut parallel_executor = AsyncParallelPipeStage::new(self.fs_config.parallel_jobs);
+        let (upload_sender, upload_receiver) = mpsc::channel(self.fs_config.parallel_jobs);
 
-        if self.fs_config.chunked_block_upload {
-            let increments = mm.iter_all().map(|(h,ids)|{
-                (h, ids.len() as u64)
-            }).collect::<Vec<_>>();
+        let upload = async {
+            if self.fs_config.chunked_block_upload && !self.fs_config.combined_write {
+                self.hash_fs.hb_upload_new_block_stream(Box::pin(upload_receiver)).await
+            } else {
+                Ok(())
+            }
+        };
+        let produce = async {
+            // dropped on return, this ends the upload stream:
+            let mut upload_sender = upload_sender;
+            for batch in batches {
+                for (_, hash, new_block) in &batch {
+                    self.caches.block.insert(hash.clone(), new_block.clone()).await;
+                }
+                if let Some(disk_cache) = &self.caches.disk_block {
+                    disk_cache.insert_in_background(batch.iter()
+                        .map(|(_, hash, new_block)| (hash.clone(), new_block.clone())).collect());
+                }
 
-            let prev_ref_counts = self.hash_fs
-                .hb_increment_reference_count(&increments).await?;
+                // filter out unchanged blocks:
+                let mut mm = MultiMap::new();
+                let mut batch_blocks = HashMap::new();
+                for (block_index, hash, new_block) in batch {
+                    if hash_list_prev.get(&block_index) == Some(&hash) {
+                        skipped_new_block_hashes += 1;
+                        continue;
+                    }
+                    new_block_hashes_len += 1;
+                    mm.insert(hash.clone(), block_index);
+                    batch_blocks.insert(hash, new_block);
+                }
 
-            let mut blocks_to_upload = Vec::new();
-            for (h, _c) in increments {
-                let prev_ref_cnt = prev_ref_counts.get(h)
-                    .cloned().unwrap_or(BigUint::ZERO);
-                if prev_ref_cnt == BigUint::ZERO {
-                    blocks_to_upload.push((h, new_blocks.get(h).unwrap().clone()));
+                if self.fs_config.combined_write {
+                    for (hash, block_ids) in mm {
+                        let data = batch_blocks.remove(&hash).unwrap();
+                        block_lens.insert(hash.clone(), data.len() as u64);
+                        combined_data.insert(hash.clone(), data);
+                        addresses.insert_many(hash, block_ids);
+                    }
+                } else if self.fs_config.chunked_block_upload {
+                    let increments = mm.iter_all().map(|(h,ids)|{
+                        (h, ids.len() as u64)
+                    }).collect::<Vec<_>>();
+
+                    let prev_ref_counts = self.hash_fs
+                        .hb_increment_reference_count(&increments).await?;
+
+                    for (h, _c) in increments {
+                        let prev_ref_cnt = prev_ref_counts.get(h)
+                            .cloned().unwrap_or(BigUint::ZERO);
+                        if prev_ref_cnt == BigUint::ZERO {
+                            let data = batch_blocks.get(h).unwrap().clone();
+                            if upload_sender.send((h.clone(), data)).await.is_err() {
+                                // the upload failed and reports the error
+                                return Ok(());
+                            }
+                        }
+                    }
+
+                    for (hash, block_ids) in mm {
+                        block_lens.insert(hash.clone(), batch_blocks.get(&hash).unwrap().len() as u64);
+                        addresses.insert_many(hash, block_ids);
+                    }
+                } else {
+                    for (hash, block_ids) in mm {
+                        let data = batch_blocks.get(&hash).unwrap().clone();
+                        let fut = self.clone()
+                            .inode_write_upload_block_reference_counting_and_write_addresses(
+                            ino,
+                            hash,
+                            block_ids.len() as u64,
+                            data,
+                            block_ids,
+                        );
+
+                        parallel_executor.push(fut).await;
+                    }
                 }
             }
+            TiFsResult::Ok(())
+        };
 
-            self.hash_fs.hb_upload_new_block(&blocks_to_upload).await?;
+        let (uploaded, produced) = futures::join!(upload, produce);
+        produced?;
+        uploaded?;
+        watch.sync("upload");
 
+        if self.fs_config.combined_write {
+            let blocks = addresses.iter_all().map(|(h, ids)|{
+                (h, *block_lens.get(h).unwrap(), ids.to_vec())
+            }).collect::<Vec<_>>();
+            if blocks.len() > 0 {
+                let combined_data = Arc::new(combined_data);
+                self.hash_fs.inode_write_blocks(ino, &blocks, Box::new(move |missing|{
+                    let combined_data = combined_data.clone();
+                    Box::pin(futures::stream::iter(missing.into_iter().filter_map(move |hash|{
+                        let data = combined_data.get(&hash)?.clone();
+                        Some((hash, data))
+                    })))
+                })).await?;
+            }
+        } else if self.fs_config.chunked_block_upload {
             self.hash_fs
                 .inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
                     ino,
-                    &mm.iter_all().map(|(h, ids)|{
-                        let data = new_blocks.get(h).unwrap().clone();
-                        (h, data.len() as u64, ids.to_vec())
+                    &addresses.iter_all().map(|(h, ids)|{
+                        (h, *block_lens.get(h).unwrap(), ids.to_vec())
                     }).collect::<Vec<_>>()
                 ).await?;
-
-        } else {
-            for (hash, block_ids) in mm {
-
-                let data = new_blocks.get(&hash).unwrap().clone();
-                let fut = self.clone()
-                    .inode_write_upload_block_reference_counting_and_write_addresses(
-                    ino,
-                    hash,
-                    block_ids.len() as u64,
-                    data,
-                    block_ids,
-                );
-
-                parallel_executor.push(fut).await;
-            }
         }
 
         parallel_executor.wait_finish_all().await;
@@ -617,6 +808,9 @@ impl Txn {
         tracing::debug!("hb_write_data(ino:{},start+len:{})-bl_len:{},jobs:{total_jobs}({skipped_new_block_hashes}/{input_block_hashes} skipped)", ino, starts_str, self.fs_config.block_size);
 
         let was_modified = new_block_hashes_len > 0;
+        if was_modified {
+            self.caches.meta.invalidate_ino(ino).await;
+        }
         Ok(was_modified)
     }
 
@@ -655,8 +849,10 @@ impl Txn {
         further_parent: ParentStorageIno,
         new_name: ByteString,
     ) -> TiFsResult<()> {
-        Ok(self.hash_fs.directory_add_child_checked_existing_inode(
-            further_parent, new_name, ino).await?)
+        self.hash_fs.directory_add_child_checked_existing_inode(
+            further_parent, new_name.clone(), ino).await?;
+        self.caches.meta.invalidate_entry(further_parent, &new_name).await;
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
@@ -670,13 +866,73 @@ impl Txn {
         Ok(())
     }
 
+    pub async fn rename(
+        self: TxnArc,
+        parent: ParentStorageIno,
+        name: ByteString,
+        new_parent: ParentStorageIno,
+        new_name: ByteString,
+        mode: RenameMode,
+    ) -> TiFsResult<()> {
+        self.hash_fs.directory_rename_child(
+            parent, name.clone(), new_parent, new_name.clone(), mode).await?;
+        self.caches.meta.invalidate_entry(parent, &name).await;
+        self.caches.meta.invalidate_entry(new_parent, &new_name).await;
+        Ok(())
+    }
+
+    pub async fn symlink(
+        self: TxnArc,
+        gid: u32,
+        uid: u32,
+        parent: ParentStorageIno,
+        name: ByteString,
+        link: ByteString,
+    ) -> TiFsResult<StorageDirItem> {
+        let item = self.hash_fs.directory_add_new_symlink(
+            gid, uid, parent, name.clone(), link).await?;
+        self.caches.meta.invalidate_entry(parent, &name).await;
+        Ok(item)
+    }
+
     pub async fn rmdir(self: TxnArc, parent: ParentStorageIno, name: ByteString) -> TiFsResult<()> {
-        Ok(self.hash_fs.directory_remove_child_directory(parent, name).await?)
+        self.hash_fs.directory_remove_child_directory(parent, name.clone()).await?;
+        self.caches.meta.invalidate_entry(parent, &name).await;
+        Ok(())
     }
 
     pub async fn get_all_ino_data(self: TxnArc, ino: StorageIno,
     ) -> TiFsResult<(Arc<InoDescription>, Arc<InoStorageFileAttr>, Arc<InoSize>, InoAccessTime)> {
-        Ok(self.hash_fs.inode_get_all_attributes(ino).await?)
+        if let Some(attrs) = self.caches.meta.get_attrs(ino).await {
+            return Ok(attrs);
+        }
+        if self.fs_config.attr_ttl.is_zero() {
+            return Ok(self.hash_fs.inode_get_all_attributes(ino).await?);
+        }
+        // the id is read first, such that changes during the read fail the revalidation:
+        let fetch_start = Instant::now();
+        let change_id = self.hash_fs.inode_get_change_iteration_ids(&[ino]).await?.remove(&ino);
+        let attrs = self.hash_fs.inode_get_all_attributes(ino).await?;
+        self.caches.meta.insert_attrs(ino, change_id, attrs.clone(), fetch_start).await;
+        Ok(attrs)
+    }
+
+    /// Attributes of the named child. Uses the cached directory entry if available.
+    pub async fn lookup_child_all_attributes(self: TxnArc, parent: ParentStorageIno, name: ByteString,
+    ) -> TiFsResult<(Arc<InoDescription>, Arc<InoStorageFileAttr>, Arc<InoSize>, InoAccessTime)> {
+        if let Some(ino) = self.caches.meta.get_entry(parent, &name).await {
+            return self.get_all_ino_data(ino).await;
+        }
+        if self.fs_config.entry_ttl.is_zero() {
+            return Ok(self.hash_fs.directory_child_get_all_attributes(parent, name).await?);
+        }
+        let fetch_start = Instant::now();
+        let parent_change_id = self.hash_fs.inode_get_change_iteration_ids(&[parent.0]).await?
+            .remove(&parent.0);
+        let attrs = self.hash_fs.directory_child_get_all_attributes(parent, name.clone()).await?;
+        self.caches.meta.insert_entry(
+            parent, name, attrs.0.storage_ino(), parent_change_id, fetch_start).await;
+        Ok(attrs)
     }
 
     pub async fn set_attributes(
@@ -697,19 +953,145 @@ impl Txn {
     ) -> TiFsResult<()> {
         let mode_conv = mode.map(|m| StorageFilePermission(
             as_file_perm(m)));
-        Ok(self.hash_fs.inode_set_all_attributes(
+        self.hash_fs.inode_set_all_attributes(
             ino, mode_conv, uid, gid, size, atime, mtime, ctime,
-            crtime, chgtime, bkuptime, flags).await?)
+            crtime, chgtime, bkuptime, flags).await?;
+        self.caches.meta.invalidate_ino(ino).await;
+        Ok(())
     }
 
     pub async fn f_allocate(
         self: TxnArc,
-        _fh: Arc<FileHandler>,
+        fh: Arc<FileHandler>,
         ino: StorageIno,
         offset: i64,
-        length: i64
+        length: i64,
+        mode: i32,
     ) -> TiFsResult<()> {
-        Ok(self.hash_fs.inode_allocate_size(ino, offset, length).await?)
+        const SUPPORTED_MODES: i32 = libc::FALLOC_FL_KEEP_SIZE
+            | libc::FALLOC_FL_PUNCH_HOLE
+            | libc::FALLOC_FL_ZERO_RANGE
+            | libc::FALLOC_FL_COLLAPSE_RANGE;
+
+        if offset < 0 || length <= 0 {
+            return Err(FsError::InvalidOffset { ino: ino.0, offset });
+        }
+        if mode & !SUPPORTED_MODES != 0 {
+            return Err(FsError::UnsupportedFallocateMode { mode });
+        }
+        self.caches.meta.invalidate_ino(ino).await;
+        let keep_size = mode & libc::FALLOC_FL_KEEP_SIZE != 0;
+        let punch_hole = mode & libc::FALLOC_FL_PUNCH_HOLE != 0;
+        let zero_range = mode & libc::FALLOC_FL_ZERO_RANGE != 0;
+        let collapse = mode & libc::FALLOC_FL_COLLAPSE_RANGE != 0;
+
+        if punch_hole && (!keep_size || zero_range || collapse) {
+            // punch hole must be combined with keep size (see fallocate(2))
+            return Err(FsError::UnsupportedFallocateMode { mode });
+        }
+        if collapse && mode != libc::FALLOC_FL_COLLAPSE_RANGE {
+            return Err(FsError::InvalidOffset { ino: ino.0, offset });
+        }
+
+        if !(punch_hole || zero_range || collapse) {
+            if keep_size {
+                // blocks are content addressed. There is nothing to pre-allocate.
+                return Ok(());
+            }
+            return Ok(self.hash_fs.inode_allocate_size(ino, offset, length).await?);
+        }
+
+        let (_desc, _attr, ino_size, _atime) = self.hash_fs.inode_get_all_attributes(ino).await?;
+        let size = ino_size.size();
+        let start = offset as u64;
+        let end = start + length as u64;
+
+        if collapse {
+            if (start % self.block_size != 0) || (end % self.block_size != 0) || (end >= size) {
+                return Err(FsError::InvalidOffset { ino: ino.0, offset });
+            }
+            let block_range = BlockIndex(start / self.block_size)..BlockIndex(end / self.block_size);
+            return Ok(self.hash_fs.inode_remove_block_range(ino, block_range, true).await?);
+        }
+
+        // punch hole or zero range. Only the range within the current file size needs clearing:
+        let clear_end = end.min(size);
+        if start < clear_end {
+            let first_full_block = start.div_ceil(self.block_size);
+            // a partial block at the end of the file can be removed completely
+            let end_full_block = if clear_end == size {
+                size.div_ceil(self.block_size)
+            } else {
+                clear_end / self.block_size
+            };
+
+            let mut zero_ranges = Vec::new();
+            if first_full_block < end_full_block {
+                self.hash_fs.inode_remove_block_range(
+                    ino,
+                    BlockIndex(first_full_block)..BlockIndex(end_full_block),
+                    false
+                ).await?;
+                let full_start = first_full_block * self.block_size;
+                let full_end = (end_full_block * self.block_size).min(clear_end);
+                if start < full_start {
+                    zero_ranges.push(start..full_start);
+                }
+                if full_end < clear_end {
+                    zero_ranges.push(full_end..clear_end);
+                }
+            } else {
+                zero_ranges.push(start..clear_end);
+            }
+
+            // partial blocks are rewritten with zeros:
+            let jobs = zero_ranges.into_iter().map(|r|{
+                WriteTaskData {
+                    data: Bytes::from(vec![0u8; (r.end - r.start) as usize]),
+                    start: r.start,
+                }
+            }).collect::<Vec<_>>();
+            if jobs.len() > 0 {
+                self.clone().hb_write_data(fh, WriteTaskDataList(jobs)).await?;
+            }
+        }
+
+        if zero_range && !keep_size && end > size {
+            self.hash_fs.inode_allocate_size(ino, offset, length).await?;
+        }
+        Ok(())
+    }
+
+    /// Implements SEEK_DATA (seek_data == true) and SEEK_HOLE (seek_data == false).
+    /// Block indices without a block hash are holes. The end of the file counts as hole.
+    pub async fn seek_data_or_hole(
+        self: TxnArc,
+        ino: StorageIno,
+        offset: i64,
+        seek_data: bool,
+    ) -> TiFsResult<i64> {
+        if offset < 0 {
+            return Err(FsError::InvalidOffset { ino: ino.0, offset });
+        }
+        let (_desc, _attr, ino_size, _atime) = self.hash_fs.inode_get_all_attributes(ino).await?;
+        let size = ino_size.size();
+        let offset_u = offset as u64;
+        if offset_u >= size {
+            return Err(FsError::NoDataOrHoleAfterOffset { ino: ino.0, offset });
+        }
+
+        let block_range = BlockIndex(offset_u / self.block_size)
+                        ..BlockIndex(size.div_ceil(self.block_size));
+        let found = self.hash_fs.inode_seek_block_mapping(
+            ino, block_range, seek_data).await?;
+        let target = match found {
+            Some(block) => (block.0 * self.block_size).max(offset_u).min(size),
+            None if seek_data => {
+                return Err(FsError::NoDataOrHoleAfterOffset { ino: ino.0, offset });
+            }
+            None => size,
+        };
+        Ok(target as i64)
     }
 
     pub async fn mkdir(
@@ -723,6 +1105,7 @@ impl Txn {
 
         if parent == SNAPSHOT_PARENT_INODE {
             let got_or_made = self.hash_fs.snapshot_create(name.clone()).await?;
+            self.caches.meta.invalidate_entry(parent, &name).await;
             let GotOrMade::NewlyCreated(created) = got_or_made else {
                 return Err(FsError::FileExist { file: name.to_string() });
             };
@@ -740,8 +1123,22 @@ impl Txn {
         Ok(result.value())
     }
 
-    pub async fn read_dir(self: Arc<Self>, ino: StorageIno) -> TiFsResult<StorageDirectory> {
-        Ok(self.hash_fs.directory_read_children(ino).await?)
+    pub async fn read_dir(
+        self: Arc<Self>,
+        ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> TiFsResult<DirectoryPage<DirectoryItem>> {
+        Ok(self.hash_fs.directory_read_children_page(ino, start_after, limit).await?)
+    }
+
+    pub async fn read_dir_with_attributes(
+        self: Arc<Self>,
+        ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> TiFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>> {
+        Ok(self.hash_fs.directory_read_children_with_attributes(ino, start_after, limit).await?)
     }
 
     pub async fn statfs(self: TxnArc) -> TiFsResult<StatFs> {
@@ -791,13 +1188,28 @@ impl Txn {
         Ok(stat)
         */
 
+        let meta = self.hash_fs.meta_mutable_read().await?;
+        let bsize = self.fs_config.block_size;
+        let used_blocks = meta.used_physical_blocks;
+        let max_blocks = self.fs_config.max_size.map(|max_size| max_size / bsize);
+        // total = used + free for blocks and inodes:
+        let (blocks, bfree) = match max_blocks {
+            Some(max_blocks) => (max_blocks.max(used_blocks), max_blocks.saturating_sub(used_blocks)),
+            None => {
+                let bfree = UNLIMITED_FREE_BYTES / bsize;
+                (used_blocks.saturating_add(bfree), bfree)
+            }
+        };
+        let ffree = UNLIMITED_FREE_INODES;
+        let files = meta.inode_count.saturating_add(ffree);
+
         let stat = StatFs::new(
-            0,
-            u64::MAX,
-            u64::MAX,
-            0,
-            u64::MAX,
-            self.fs_config.block_size as u32,
+            blocks,
+            bfree,
+            bfree,
+            files,
+            ffree,
+            bsize as u32,
             MAX_NAME_LEN,
             0,
         );
@@ -6,8 +6,10 @@ use fuser::TimeOrNow;
 use num_bigint::BigUint;
 use uuid::Uuid;
 
-use super::{inode::{ParentStorageIno, StorageDirItem}, meta::MetaStatic};
-use super::inode::{InoAccessTime, DirectoryItem, InoDescription, InoSize, StorageDirItemKind, InoStorageFileAttr, StorageFilePermission, StorageIno, TiFsHash};
+use super::{inode::{ParentStorageIno, StorageDirItem}, meta::{MetaMutable, MetaStatic}};
+use super::quota::{QuotaLimits, QuotaReport, QuotaTarget};
+use super::fs_stats::{FileStats, FsStats};
+use super::inode::{InoAccessTime, InoChangeIterationId, DirectoryItem, InoDescription, InoSize, StorageDirItemKind, InoStorageFileAttr, StorageFilePermission, StorageIno, TiFsHash};
 
 #[derive(Debug)]
 pub enum HashFsError {
@@ -21,6 +23,14 @@ pub enum HashFsError {
     InodeHasNoInlineData,
     GrpcMessageIncomplete,
     RawTonicTransportError(String),
+    DirNotEmpty,
+    NotADirectory,
+    IsADirectory,
+    NoSpaceLeft,
+    QuotaExceeded,
+    CrossQuotaRename,
+    UnknownBlockHash,
+    PermissionDenied,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
@@ -44,6 +54,73 @@ impl std::iter::Step for BlockIndex {
 
 pub type HashFsResult<V> = Result<V, HashFsError>;
 
+pub type InoAllAttributes = (Arc<InoDescription>, Arc<InoStorageFileAttr>, Arc<InoSize>, InoAccessTime);
+
+/// Children of a directory page and the cursor of the next page.
+pub type DirectoryPage<T> = (Vec<T>, Option<ByteString>);
+
+/// Modification reported by watch_changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// Content or attributes of the inode changed.
+    Inode(StorageIno),
+    /// The named entry of the directory was added, removed or replaced.
+    Entry { parent: ParentStorageIno, name: ByteString },
+    /// Events were dropped. All cached metadata has to be considered stale.
+    Overflow,
+}
+
+pub type ChangeEventStream = futures::stream::BoxStream<'static, HashFsResult<ChangeEvent>>;
+
+/// Blocks handed to hb_upload_new_block_stream as they become available.
+pub type NewBlockStream = futures::stream::BoxStream<'static, (TiFsHash, Arc<Vec<u8>>)>;
+
+pub type BlockDataStream = futures::stream::BoxStream<'static, HashFsResult<(TiFsHash, Arc<Vec<u8>>)>>;
+
+/// Inode and blocks the requested block data is read from. The hashes alone
+/// carry no owner, servers check the read permission of restricted clients with it.
+#[derive(Debug, Clone)]
+pub struct BlockDataSource {
+    pub ino: StorageIno,
+    pub block_ranges: Vec<Range<BlockIndex>>,
+}
+
+/// How directory_rename_child treats an existing target entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameMode {
+    /// An existing target is replaced (no flags).
+    Replace,
+    /// Fails if the target exists (RENAME_NOREPLACE).
+    NoReplace,
+    /// Swaps source and the existing target (RENAME_EXCHANGE).
+    Exchange,
+}
+
+impl RenameMode {
+    /// None for unknown flags and for RENAME_NOREPLACE together with RENAME_EXCHANGE.
+    pub fn from_flags(flags: u32) -> Option<Self> {
+        match flags {
+            0 => Some(Self::Replace),
+            libc::RENAME_NOREPLACE => Some(Self::NoReplace),
+            libc::RENAME_EXCHANGE => Some(Self::Exchange),
+            _ => None,
+        }
+    }
+
+    pub fn flags(self) -> u32 {
+        match self {
+            Self::Replace => 0,
+            Self::NoReplace => libc::RENAME_NOREPLACE,
+            Self::Exchange => libc::RENAME_EXCHANGE,
+        }
+    }
+}
+
+/// Called by inode_write_blocks with the hashes of the blocks that are not
+/// stored yet. Returns the data of these blocks. Called again for blocks
+/// that got deleted after they were reported as stored.
+pub type MissingBlocksProvider = Box<dyn FnMut(Vec<TiFsHash>) -> NewBlockStream + Send>;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GotOrMadePure {
     ExistedAlready,
@@ -87,7 +164,26 @@ impl<R> GotOrMade<R> {
 pub trait HashFsInterface: Send + Sync  {
     async fn init(&self, gid: u32, uid: u32) -> HashFsResult<StorageDirItem>;
     async fn meta_static_read(&self) -> HashFsResult<MetaStatic>;
+    // Returns the mutable meta data including the usage counters of the filesystem.
+    async fn meta_mutable_read(&self) -> HashFsResult<MetaMutable>;
     async fn directory_read_children(&self, dir_ino: StorageIno) -> HashFsResult<Vec<DirectoryItem>>;
+    // Cursor based paging: returns up to limit children with a name greater than start_after,
+    // ordered by name, and the cursor to pass as start_after for the next page.
+    // The cursor is None after the last page.
+    async fn directory_read_children_page(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<DirectoryItem>>;
+    // Like directory_read_children_page, but also fetches the attributes of the children in one go.
+    // Children whose attributes are missing, e.g. as they vanish concurrently, have None.
+    async fn directory_read_children_with_attributes(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>>;
     async fn directory_add_child_checked_new_inode(
         &self,
         parent: ParentStorageIno,
@@ -121,6 +217,7 @@ pub trait HashFsInterface: Send + Sync  {
         child_name: ByteString,
         new_parent: ParentStorageIno,
         new_child_name: ByteString,
+        mode: RenameMode,
     ) -> HashFsResult<()>;
     async fn directory_child_get_all_attributes(
         &self,
@@ -139,6 +236,12 @@ pub trait HashFsInterface: Send + Sync  {
         &self,
         ino: StorageIno,
     ) -> HashFsResult<(Arc<InoDescription>, Arc<InoStorageFileAttr>, Arc<InoSize>, InoAccessTime)>;
+    /// The change iteration id of an inode changes with every modification of its
+    /// content, attributes or directory entries. Inodes without id are missing in the result.
+    async fn inode_get_change_iteration_ids(
+        &self,
+        inos: &[StorageIno],
+    ) -> HashFsResult<HashMap<StorageIno, InoChangeIterationId>>;
     async fn inode_set_all_attributes(
         &self,
         ino: StorageIno,
@@ -177,11 +280,48 @@ pub trait HashFsInterface: Send + Sync  {
         ino: StorageIno,
         block_ranges: &[Range<BlockIndex>],
     ) -> HashFsResult<BTreeMap<BlockIndex, TiFsHash>>;
+    // Global reference counts of the blocks. Hashes of unknown blocks are missing in the result.
+    // The blocks have to be mapped in the source, see hb_get_block_data_by_hashes.
+    async fn hb_get_reference_counts(
+        &self,
+        hashes: &[TiFsHash],
+        source: &BlockDataSource,
+    ) -> HashFsResult<HashMap<TiFsHash, u64>>;
+    // Removes the block hash assignments in the range and releases the referenced blocks.
+    // With collapse == true, the following blocks are moved down to close the gap
+    // and the inode size is reduced accordingly (FALLOC_FL_COLLAPSE_RANGE).
+    // The blocks are moved in chunks of their own transactions, readers can see
+    // a partly moved file and a failure leaves it partly moved at its old size.
+    async fn inode_remove_block_range(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        collapse: bool,
+    ) -> HashFsResult<()>;
+    // Returns the first block index in the range that has a block hash assigned (mapped == true)
+    // or that is a hole (mapped == false). Returns None if there is no such block in the range.
+    async fn inode_seek_block_mapping(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        mapped: bool,
+    ) -> HashFsResult<Option<BlockIndex>>;
     async fn hb_get_block_data_by_hashes(
         &self,
         hashes: &HashSet<&TiFsHash>,
+        source: &BlockDataSource,
     ) -> HashFsResult<HashMap<TiFsHash, Arc<Vec<u8>>>>;
+    // Same as hb_get_block_data_by_hashes, but returns the blocks one by one
+    // in the requested order. Unknown hashes are skipped.
+    async fn hb_get_block_data_by_hashes_stream(
+        &self,
+        hashes: Vec<TiFsHash>,
+        source: BlockDataSource,
+    ) -> HashFsResult<BlockDataStream>;
     async fn file_get_hash(&self, ino: StorageIno) -> HashFsResult<Vec<u8>>;
+    // Root of the binary hash tree over the ordered block hashes of the file.
+    // Computed from the block hash mappings only, without reading block data.
+    async fn file_get_merkle_hash(&self, ino: StorageIno) -> HashFsResult<TiFsHash>;
     async fn file_read_block_hashes(
         &self,
         ino: StorageIno,
@@ -195,16 +335,64 @@ pub trait HashFsInterface: Send + Sync  {
     ) -> HashFsResult<HashMap<TiFsHash, BigUint>>;
     // This uploads data for a new block. Will not do any reference count changes.
     // Will also succeed when the block already existed.
+    // Fails if the data doesn't match its hash.
     async fn hb_upload_new_block(
         &self,
         blocks: &[(&TiFsHash, Arc<Vec<u8>>)],
     ) -> HashFsResult<()>;
+    // Same as hb_upload_new_block, but starts storing the blocks before the
+    // stream ended. Returns when all blocks of the stream are stored.
+    async fn hb_upload_new_block_stream(
+        &self,
+        blocks: NewBlockStream,
+    ) -> HashFsResult<()>;
     async fn inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
         &self,
         ino: StorageIno,
         blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
     ) -> HashFsResult<()>;
+    // Combines hb_increment_reference_count, hb_upload_new_block and
+    // inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes
+    // into one transaction per chunk of blocks. The data is only requested from
+    // missing_data for the blocks that are not stored yet.
+    // The write is not atomic: if it fails, the chunks registered before stay
+    // written and the inode size includes them.
+    async fn inode_write_blocks(
+        &self,
+        ino: StorageIno,
+        blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+        missing_data: MissingBlocksProvider,
+    ) -> HashFsResult<()>;
+    // Assigns already stored blocks to the addresses starting at first_block, without
+    // transferring any block data. The hash of empty data creates a hole.
+    // With truncate == true, the file ends after the given blocks.
+    // Fails with UnknownBlockHash if one of the blocks is not stored.
+    // Long lists are mapped in one transaction per chunk of blocks. A failure
+    // leaves the chunks mapped that were applied before.
+    async fn inode_set_blocks_from_hashes(
+        &self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+        hashes: &[TiFsHash],
+        truncate: bool,
+    ) -> HashFsResult<()>;
     async fn snapshot_create(&self, name: ByteString) -> HashFsResult<GotOrMade<StorageDirItem>>;
+    // Sets the limits of a user, group or directory quota. None as limit means unlimited.
+    // A new directory quota fails with DirNotEmpty if the directory has children.
+    async fn quota_set(&self, target: QuotaTarget, limits: QuotaLimits) -> HashFsResult<()>;
+    // Lists all quotas with their limits and current usage.
+    async fn quota_list(&self) -> HashFsResult<Vec<QuotaReport>>;
+    // Reports limits and usage of a single quota. None if nothing was tracked for it yet.
+    async fn quota_report(&self, target: QuotaTarget) -> HashFsResult<Option<QuotaReport>>;
+    // Deduplication statistics of the whole filesystem. Reads the counters that the
+    // reference count changes maintain, not the blocks themselves.
+    async fn fs_stats(&self) -> HashFsResult<FsStats>;
+    // Number of shared vs. exclusive blocks of a single file.
+    async fn file_stats(&self, ino: StorageIno) -> HashFsResult<FileStats>;
+    /// Stream of the modifications of the filesystem, starting with the subscription.
+    /// Modifications of other instances are read from the change log in TiKV
+    /// and arrive with a delay of up to CHANGE_LOG_POLL_INTERVAL.
+    async fn watch_changes(&self) -> HashFsResult<ChangeEventStream>;
 }
 
 impl From<tonic::Status> for HashFsError {
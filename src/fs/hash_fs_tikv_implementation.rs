@@ -1,17 +1,19 @@
 
 use std::ops::{Deref, Range};
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::sync::{Arc, Weak};
-use std::time::SystemTime;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Instant, SystemTime};
 
 use bytestring::ByteString;
 use fuser::TimeOrNow;
+use futures::StreamExt;
 use num_bigint::BigUint;
 use range_collections::range_set::RangeSetRange;
 use range_collections::RangeSet2;
 use strum::IntoEnumIterator;
 use tikv_client::{Key, KvPair};
 use tikv_client::transaction::Mutation;
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
 use crate::fs::hash_fs_interface::GotOrMadePure;
@@ -20,21 +22,26 @@ use crate::fs::key::{BlockAddress, PARENT_OF_ROOT_INODE};
 use crate::fs::fuse_to_hashfs::MAX_TIKV_SCAN_LIMIT;
 use crate::utils::async_parallel_pipe_stage::AsyncParallelPipeStage;
 
+use super::change_log::{self, ChangeLogCursor, ChangeLogEntry, CHANGE_LOG_BATCH_SIZE, CHANGE_LOG_POLL_INTERVAL, CHANGE_LOG_RETENTION, CHANGE_LOG_WATCH_LEASE};
 use super::error::FsError;
 use super::index::deserialize_json;
 use super::mini_transaction::{DeletionCheckResult, MiniTransaction};
+use super::quota::{QuotaLimits, QuotaReport, QuotaTarget};
+use super::fs_stats::{FileStats, FsStats};
+use super::scrubber::{verify_blocks, ScrubChunk};
 use super::snapshot::CreateSnapshot;
 use super::transaction_client_mux::TransactionClientMux;
 use super::utils::lazy_lock_map::LazyLockMap;
+use super::utils::full_hash::{FullHashMode, FullHasher, FULL_HASH_SEGMENT_BLOCKS, FULL_HASH_SEGMENT_HEIGHT};
 use super::utils::stop_watch::AutoStopWatch;
 use super::utils::txn_data_cache::{TxnFetch, TxnPut, TxnPutMut};
 use super::{
-    error::TiFsResult, flexible_transaction::FlexibleTransaction, fs_config::TiFsConfig, meta::MetaStatic
+    error::TiFsResult, flexible_transaction::FlexibleTransaction, fs_config::TiFsConfig, meta::{MetaMutable, MetaStatic}
     };
-use super::key::{check_file_name, InoMetadata, ROOT_INODE};
+use super::key::{check_file_name, InoMetadata, ROOT_INODE, SNAPSHOT_PARENT_INODE};
 use super::hash_fs_interface::{
-        BlockIndex, GotOrMade, HashFsError, HashFsInterface, HashFsResult};
-use super::inode::{DirectoryItem, InoAccessTime, InoChangeIterationId, InoDescription, InoInlineData, InoSize, InoStorageFileAttr, InoModificationTime, ParentStorageIno, StorageDirItem, StorageDirItemKind, StorageFilePermission, StorageIno};
+        BlockDataSource, BlockDataStream, BlockIndex, ChangeEvent, ChangeEventStream, DirectoryPage, GotOrMade, HashFsError, HashFsInterface, HashFsResult, InoAllAttributes, MissingBlocksProvider, NewBlockStream, RenameMode};
+use super::inode::{DirectoryItem, InoAccessTime, InoChangeIterationId, InoDescription, InoFullHash, InoInlineData, InoSize, InoStorageFileAttr, ParentStorageIno, StorageDirItem, StorageDirItemKind, StorageFilePermission, StorageIno};
 
 
 fn get_time_from_time_or_now(time: TimeOrNow) -> SystemTime {
@@ -44,20 +51,6 @@ fn get_time_from_time_or_now(time: TimeOrNow) -> SystemTime {
     }
 }
 
-fn set_if_changed<T: std::cmp::PartialEq>(
-    change_cnt: &mut usize,
-    field: &mut T,
-    new: Option<T>
-) {
-    if let Some(new_value) = new {
-        let changed = *field != new_value;
-        if changed {
-            *change_cnt += 1;
-            *field = new_value;
-        }
-    }
-}
-
 pub struct TikvBasedHashFs {
     weak: Weak<TikvBasedHashFs>,
     txn_client_mux: Arc<TransactionClientMux>,
@@ -72,8 +65,33 @@ pub struct TikvBasedHashFs {
     pub local_ino_locks_full_hash: LazyLockMap<StorageIno, ()>,
     pub local_ino_locks_clear_full_hash: LazyLockMap<StorageIno, ()>,
     pub local_ino_locks_update_change_iter: LazyLockMap<StorageIno, ()>,
+    /// Event log of the modifications of this instance for watch_changes.
+    change_events: broadcast::Sender<ChangeEvent>,
+    /// Identifies the entries of this instance in the persisted change log.
+    instance_id: Uuid,
+    /// Queue of the change log writer task, started with the first change.
+    change_log_writer: OnceLock<mpsc::UnboundedSender<ChangeLogEntry>>,
 }
 
+/// Events that a watcher may lag behind before it gets an overflow.
+const CHANGE_EVENTS_CAPACITY: usize = 4096;
+
+/// State of the change log stream of a watcher.
+struct ChangeLogPoller {
+    hash_fs: Weak<TikvBasedHashFs>,
+    cursor: ChangeLogCursor,
+    pending: VecDeque<ChangeEvent>,
+    lease_renewed: Instant,
+}
+
+/// Blocks per transaction of the streaming block transfers.
+const BLOCK_STREAM_CHUNK_SIZE: usize = 16;
+/// Blocks registered per transaction by inode_write_blocks. Keeps the transactions
+/// below the size limits and limits the data that is sent again on a retry.
+const WRITE_BLOCKS_TXN_CHUNK_SIZE: usize = 16;
+/// Blocks moved per transaction by a collapse of inode_remove_block_range.
+const COLLAPSE_TXN_CHUNK_SIZE: u64 = 1024;
+
 impl TikvBasedHashFs {
     pub fn new_arc(fs_config: TiFsConfig, txn_client_mux: Arc<TransactionClientMux>) -> Arc<Self> {
         Arc::new_cyclic(|weak| {
@@ -92,10 +110,195 @@ impl TikvBasedHashFs {
                 local_ino_locks_full_hash: LazyLockMap::new(),
                 local_ino_locks_clear_full_hash: LazyLockMap::new(),
                 local_ino_locks_update_change_iter: LazyLockMap::new(),
+                change_events: broadcast::channel(CHANGE_EVENTS_CAPACITY).0,
+                instance_id: Uuid::new_v4(),
+                change_log_writer: OnceLock::new(),
             }
         })
     }
 
+    /// The hashes of uploaded blocks are chosen by the clients. Storing data
+    /// under a wrong hash would change the content of all files using that block.
+    /// Requests the data of the missing blocks and verifies it.
+    /// Fails with UnknownBlockHash if the provider doesn't return one of them.
+    async fn receive_missing_blocks(
+        &self,
+        missing_data: &mut MissingBlocksProvider,
+        missing: Vec<TiFsHash>,
+        new_data: &mut HashMap<TiFsHash, Arc<Vec<u8>>>,
+    ) -> TiFsResult<()> {
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let mut received = missing_data(missing.clone());
+        while let Some((hash, data)) = received.next().await {
+            self.verify_block_hashes(std::iter::once((&hash, data.as_slice())))?;
+            new_data.insert(hash, data);
+        }
+        if let Some(hash) = missing.into_iter().find(|h| !new_data.contains_key(h)) {
+            return Err(FsError::UnknownBlockHash { hash });
+        }
+        Ok(())
+    }
+
+    fn verify_block_hashes<'a>(
+        &self,
+        blocks: impl Iterator<Item = (&'a TiFsHash, &'a [u8])>,
+    ) -> TiFsResult<()> {
+        for (hash, data) in blocks {
+            let actual_hash = self.fs_config.calculate_hash(data);
+            if *hash != actual_hash {
+                return Err(FsError::ChecksumMismatch { hash: hash.clone(), actual_hash });
+            }
+        }
+        Ok(())
+    }
+
+    fn publish_change(&self, event: ChangeEvent) {
+        if let Some(entry) = ChangeLogEntry::new(self.instance_id, &event) {
+            let writer = self.change_log_writer.get_or_init(|| {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                tokio::spawn(Self::change_log_writer_task(self.weak.clone(), receiver));
+                sender
+            });
+            let _ = writer.send(entry);
+        }
+        // fails only without watchers:
+        let _ = self.change_events.send(event);
+    }
+
+    /// Writes the published changes in batches to the change log, such that
+    /// watchers of other instances get them. Also removes the outdated entries.
+    async fn change_log_writer_task(
+        me: Weak<Self>,
+        mut receiver: mpsc::UnboundedReceiver<ChangeLogEntry>,
+    ) {
+        let mut last_cleanup = Instant::now();
+        while let Some(first) = receiver.recv().await {
+            let mut unique = HashSet::new();
+            let mut entries = vec![first];
+            while entries.len() < CHANGE_LOG_BATCH_SIZE {
+                let Ok(entry) = receiver.try_recv() else {
+                    break;
+                };
+                entries.push(entry);
+            }
+            entries.retain(|entry| unique.insert(entry.clone()));
+
+            let Some(strong) = me.upgrade() else {
+                return;
+            };
+            if let Err(err) = strong.change_log_write(&entries).await {
+                tracing::warn!("failed to write {} change log entries: {err:?}", entries.len());
+            }
+            if last_cleanup.elapsed() > CHANGE_LOG_RETENTION / 10 {
+                last_cleanup = Instant::now();
+                if let Err(err) = strong.change_log_cleanup().await {
+                    tracing::warn!("failed to remove outdated change log entries: {err:?}");
+                }
+            }
+        }
+    }
+
+    async fn change_log_write(&self, entries: &[ChangeLogEntry]) -> TiFsResult<()> {
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.change_log_append(entries).await;
+            if let Some(r) = started.finish(r1).await { break r; }
+        }
+    }
+
+    async fn change_log_cleanup(&self) -> TiFsResult<()> {
+        let before_us = change_log::time_us(SystemTime::now() - CHANGE_LOG_RETENTION);
+        loop {
+            let mut spin = self.spinning_mini_txn().await?;
+            let removed = loop {
+                let mut started = spin.start().await?;
+                let r1 = started.change_log_remove_before(before_us, MAX_TIKV_SCAN_LIMIT).await;
+                if let Some(r) = started.finish(r1).await { break r?; }
+            };
+            if removed < MAX_TIKV_SCAN_LIMIT as usize {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn change_log_renew_lease(&self) -> TiFsResult<()> {
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.change_log_renew_lease().await;
+            if let Some(r) = started.finish(r1).await { break r; }
+        }
+    }
+
+    /// Scans the change log from the position of the cursor and
+    /// returns the events of other instances.
+    async fn change_log_poll(&self, cursor: &mut ChangeLogCursor) -> TiFsResult<Vec<ChangeEvent>> {
+        let scan_start_us = change_log::time_us(SystemTime::now());
+        let scan = cursor.next_scan(scan_start_us);
+        let mut events = Vec::new();
+        let mut after = scan.after;
+        loop {
+            let mut spin = self.spinning_mini_txn().await?;
+            let page = loop {
+                let mut started = spin.start().await?;
+                let r1 = started.change_log_scan(scan.from_us, after, MAX_TIKV_SCAN_LIMIT).await;
+                if let Some(r) = started.finish(r1).await { break r?; }
+            };
+            let complete = page.len() < MAX_TIKV_SCAN_LIMIT as usize;
+            after = page.last().map(|(time_us, id, _entry)| (*time_us, *id));
+            for (time_us, id, entry) in page {
+                if cursor.report(time_us, id) && entry.instance != self.instance_id {
+                    events.push(entry.into_event());
+                }
+            }
+            if complete {
+                break;
+            }
+        }
+        cursor.finish_scan(scan, scan_start_us);
+        Ok(events)
+    }
+
+    /// Polls the change log for the modifications of other instances.
+    /// Acquires the lease before, such that other instances log their changes.
+    async fn watch_change_log(&self) -> TiFsResult<ChangeEventStream> {
+        self.change_log_renew_lease().await?;
+        let state = ChangeLogPoller {
+            hash_fs: self.weak.clone(),
+            cursor: ChangeLogCursor::new(change_log::time_us(SystemTime::now())),
+            pending: VecDeque::new(),
+            lease_renewed: Instant::now(),
+        };
+        Box::pin(futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), Some(state)));
+                }
+                tokio::time::sleep(CHANGE_LOG_POLL_INTERVAL).await;
+                let strong = state.hash_fs.upgrade()?;
+                if state.lease_renewed.elapsed() > CHANGE_LOG_WATCH_LEASE / 3 {
+                    if let Err(err) = strong.change_log_renew_lease().await {
+                        return Some((Err(err.into()), None));
+                    }
+                    state.lease_renewed = Instant::now();
+                }
+                let now_us = change_log::time_us(SystemTime::now());
+                if state.cursor.is_stale(now_us) {
+                    state.cursor.reset(now_us);
+                    return Some((Ok(ChangeEvent::Overflow), Some(state)));
+                }
+                match strong.change_log_poll(&mut state.cursor).await {
+                    Ok(events) => state.pending.extend(events),
+                    Err(err) => return Some((Err(err.into()), None)),
+                }
+            }
+        }))
+    }
+
     pub async fn spinning_mini_txn(&self) -> TiFsResult<MiniTransaction> {
         MiniTransaction::new(
             self.txn_client_mux.clone(), self.fs_config.clone()).await
@@ -117,6 +320,14 @@ impl TikvBasedHashFs {
         };
         drop(mini);
 
+        self.inode_delete_if_unreferenced(unlinked_item).await
+    }
+
+    /// Deletes the inode and releases its data if no directory entry refers to it anymore.
+    async fn inode_delete_if_unreferenced(
+        &self,
+        unlinked_item: StorageDirItem,
+    ) -> HashFsResult<()> {
         let mut mini = self.spinning_mini_txn().await?;
         let r: DeletionCheckResult = loop {
             let mut started = mini.start().await?;
@@ -134,6 +345,10 @@ impl TikvBasedHashFs {
                     => self.hb_clear_data_no_sync_no_size_update(unlinked_item.ino).await?,
                 StorageDirItemKind::Directory => {/* directory needs to be empty before deletion */}
                 StorageDirItemKind::Symlink => {/* symlink data is inline only */}
+                StorageDirItemKind::CharDevice
+                | StorageDirItemKind::BlockDevice
+                | StorageDirItemKind::NamedPipe
+                | StorageDirItemKind::Socket => {/* special files only have attributes (rdev) */}
             }
 
             for meta in InoMetadata::iter() {
@@ -173,7 +388,7 @@ impl TikvBasedHashFs {
                 let mut started = spin.start().await?;
                 let r1 = started
                     .hb_replace_block_hash_for_address_only_size_update(
-                        &addr, 0).await;
+                        &addr, 0, prev_hash.len() as u64).await;
                 if let Some(result) = started.finish(
                     r1).await { break result?; }
                 drop(lock);
@@ -256,6 +471,136 @@ impl TikvBasedHashFs {
         Ok(result)
     }
 
+    async fn full_hash_segment_root(
+        &self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+    ) -> HashFsResult<TiFsHash> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let root = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.inode_full_hash_segment_root(ino, first_block).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(root)
+    }
+
+    /// Feeds the block hashes of the file chunk wise into the full hasher.
+    /// Holes contribute the hash of empty data. In merkle tree mode the
+    /// stored roots of full segments are used instead of their block hashes.
+    async fn compute_full_hash(
+        &self,
+        ino: StorageIno,
+        size: &InoSize,
+        mode: FullHashMode,
+    ) -> HashFsResult<TiFsHash> {
+        let mut hasher = FullHasher::new(self.fs_config.hash_algorithm, mode);
+        let blocks = size.blocks();
+        let mut start = 0;
+        if mode == FullHashMode::MerkleTree {
+            while start + FULL_HASH_SEGMENT_BLOCKS <= blocks {
+                let root = self.full_hash_segment_root(ino, BlockIndex(start)).await?;
+                hasher.push_subtree_root(FULL_HASH_SEGMENT_HEIGHT, &root);
+                start += FULL_HASH_SEGMENT_BLOCKS;
+            }
+        }
+        while start < blocks {
+            let end = (start + MAX_TIKV_SCAN_LIMIT as u64).min(blocks);
+            let hash_data = self.read_hashes_of_file(
+                ino, BlockIndex(start)..BlockIndex(end)).await?;
+            for block_hash in hash_data.chunks(self.fs_config.hash_len) {
+                hasher.push_block_hash(block_hash);
+            }
+            start = end;
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Computes the full hash if the stored one is missing or outdated and stores it.
+    pub async fn update_full_hash(&self, ino: StorageIno) -> HashFsResult<TiFsHash> {
+        self.content_hash(ino, self.fs_config.full_hash_mode).await
+    }
+
+    /// Returns the stored hash of the file content in the mode or computes and stores it.
+    /// The hash of the configured mode is the full hash, the merkle hash of flat
+    /// configurations is stored for the change iteration it was computed for.
+    /// The result is only stored if the file wasn't changed during the computation.
+    async fn content_hash(&self, ino: StorageIno, mode: FullHashMode) -> HashFsResult<TiFsHash> {
+        // avoid multiple times hash computation (at least on the same instance)
+        let lock = self.local_ino_locks_full_hash.lock_write(&ino).await;
+
+        let mut spin = self.spinning_mini_txn().await?;
+        let (stored, change_iter_id, size) = loop {
+            let mut mini = spin.start().await?;
+            let r1 = mini.inode_stored_content_hash(ino, mode).await;
+            if let Some(r) = mini.finish(r1).await { break r?; }
+        };
+        if let Some(hash) = stored {
+            return Ok(hash);
+        }
+        let Some(size) = size else {
+            return Err(HashFsError::FileNotFound);
+        };
+
+        let new_hash = self.compute_full_hash(ino, &size, mode).await?;
+        tracing::trace!("freshly_calculated_hash: {:x?}", &new_hash);
+
+        let mut spin = self.spinning_mini_txn().await?;
+        let _stored = loop {
+            let mut mini = spin.start().await?;
+            let r1 = mini.checked_write_of_file_hash(
+                &ino, &change_iter_id, new_hash.clone(), mode).await;
+            if let Some(r) = mini.finish(r1).await { break r?; }
+        };
+        drop(lock);
+
+        // return it anyway. Might block otherwise if file constantly changes
+        Ok(new_hash)
+    }
+
+    /// Verifies the next chunk of stored blocks against their hashes
+    /// and advances the persisted scrub progress.
+    pub async fn scrub_next_chunk(&self, limit: u32, quarantine: bool) -> HashFsResult<ScrubChunk> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let mut progress = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.scrub_progress_read().await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+
+        let (blocks, last_key, round_completed) = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_scan_block_data(progress.last_key.as_deref(), limit).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+
+        let mismatches = verify_blocks(self.fs_config.hash_algorithm, &blocks);
+        let mut quarantined = 0;
+        if quarantine && !mismatches.is_empty() {
+            let hashes = mismatches.iter().map(|m| m.hash.clone()).collect::<Vec<_>>();
+            quarantined = loop {
+                let mut started = spin.start().await?;
+                let r1 = started.hb_quarantine_blocks(&hashes).await;
+                if let Some(r) = started.finish(r1).await { break r?; }
+            };
+        }
+
+        progress.advance(blocks.len() as u64, mismatches.len() as u64, last_key, round_completed);
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.scrub_progress_write(progress.clone()).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+
+        Ok(ScrubChunk {
+            verified: blocks.len() as u64,
+            mismatches,
+            quarantined,
+            round_completed,
+            progress,
+        })
+    }
+
     pub async fn get_or_make_dir(
         &self,
         parent: ParentStorageIno,
@@ -282,7 +627,7 @@ impl TikvBasedHashFs {
         &self,
         ino: StorageIno,
         elements: &[InoMetadata],
-    ) -> TiFsResult<(Vec<TiFsHash>, Vec<InoChangeIterationId>, Vec<InoSize>)> {
+    ) -> TiFsResult<(Vec<InoFullHash>, Vec<InoChangeIterationId>, Vec<InoSize>)> {
         let keys = elements.iter().map(|meta|{
             Key::from(self.fs_config.key_builder().inode_x(ino, *meta).buf)
         }).collect::<Vec<_>>();
@@ -293,7 +638,9 @@ impl TikvBasedHashFs {
         for KvPair(k, v) in data {
             match self.fs_config.key_parser_b(k)?.parse_ino()?.meta {
                 InoMetadata::FullHash => {
-                    existing_hash.push(v);
+                    // entries that don't parse are recomputed on demand:
+                    let _ = deserialize_json::<InoFullHash>(&v)
+                        .map(|h| existing_hash.push(h));
                 }
                 InoMetadata::ChangeIterationId => {
                     let _ = deserialize_json::<InoChangeIterationId>(&v)
@@ -370,6 +717,16 @@ impl HashFsInterface for TikvBasedHashFs {
         Ok(result)
     }
 
+    async fn meta_mutable_read(&self) -> HashFsResult<MetaMutable> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let meta = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.meta_mutable_read().await;
+            if let Some(result) = started.finish(r1).await { break result?; }
+        };
+        meta.ok_or(HashFsError::FsNotInitialized)
+    }
+
     async fn directory_read_children(&self, dir_ino: StorageIno) -> HashFsResult<Vec<DirectoryItem>> {
         let mut spin = self.spinning_mini_txn().await?;
         loop {
@@ -381,6 +738,40 @@ impl HashFsInterface for TikvBasedHashFs {
         }
     }
 
+    async fn directory_read_children_page(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<DirectoryItem>> {
+        let limit = limit.min(MAX_TIKV_SCAN_LIMIT);
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.directory_scan_for_children_page(
+                dir_ino, start_after.as_ref().map(|n|n.as_bytes().as_ref()), limit).await;
+            if let Some(result) =
+                started.finish(r1).await { break Ok(result?); }
+        }
+    }
+
+    async fn directory_read_children_with_attributes(
+        &self,
+        dir_ino: StorageIno,
+        start_after: Option<ByteString>,
+        limit: u32,
+    ) -> HashFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>> {
+        let limit = limit.min(MAX_TIKV_SCAN_LIMIT);
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.directory_scan_for_children_with_attributes(
+                dir_ino, start_after.as_ref().map(|n|n.as_bytes().as_ref()), limit).await;
+            if let Some(result) =
+                started.finish(r1).await { break Ok(result?); }
+        }
+    }
+
     async fn directory_add_child_checked_new_inode(
         &self,
         parent: ParentStorageIno,
@@ -403,6 +794,9 @@ impl HashFsInterface for TikvBasedHashFs {
             if let Some(r) = started.finish(r1).await { break r?; }
         };
 
+        if let GotOrMade::NewlyCreated(_) = &r {
+            self.publish_change(ChangeEvent::Entry { parent, name });
+        }
         Ok(r)
     }
 
@@ -428,6 +822,7 @@ impl HashFsInterface for TikvBasedHashFs {
         if let GotOrMade::ExistedAlready(_existing) = r {
             Err(HashFsError::FileAlreadyExists)
         } else {
+            self.publish_change(ChangeEvent::Entry { parent, name });
             Ok(())
         }
     }
@@ -466,9 +861,15 @@ impl HashFsInterface for TikvBasedHashFs {
         let allowed_types = HashSet::from([
             StorageDirItemKind::File,
             StorageDirItemKind::Symlink,
+            StorageDirItemKind::CharDevice,
+            StorageDirItemKind::BlockDevice,
+            StorageDirItemKind::NamedPipe,
+            StorageDirItemKind::Socket,
         ]);
 
-        self.directory_remove_child_generic(parent, name, allowed_types).await
+        self.directory_remove_child_generic(parent, name.clone(), allowed_types).await?;
+        self.publish_change(ChangeEvent::Entry { parent, name });
+        Ok(())
     }
 
     async fn directory_remove_child_directory(
@@ -484,6 +885,7 @@ impl HashFsInterface for TikvBasedHashFs {
             if let Some(r) = started.finish(r1).await
             { break r?; }
         };
+        self.publish_change(ChangeEvent::Entry { parent, name });
         Ok(())
     }
 
@@ -493,15 +895,23 @@ impl HashFsInterface for TikvBasedHashFs {
         raw_name: ByteString,
         new_parent: ParentStorageIno,
         new_raw_name: ByteString,
+        mode: RenameMode,
     ) -> HashFsResult<()> {
         check_file_name(&raw_name)?;
         check_file_name(&new_raw_name)?;
         let mut spin = self.spinning_mini_txn().await?;
-        loop {
+        let replaced = loop {
             let mut started = spin.start().await?;
             let r1 = started.directory_rename_child(
-                parent, raw_name.clone(), new_parent, new_raw_name.clone()).await;
+                parent, raw_name.clone(), new_parent, new_raw_name.clone(), mode).await;
             if let Some(result) = started.finish(r1).await { break result?; }
+        };
+        drop(spin);
+        self.publish_change(ChangeEvent::Entry { parent, name: raw_name });
+        self.publish_change(ChangeEvent::Entry { parent: new_parent, name: new_raw_name });
+
+        if let Some(replaced) = replaced {
+            self.inode_delete_if_unreferenced(replaced).await?;
         }
         Ok(())
     }
@@ -536,6 +946,26 @@ impl HashFsInterface for TikvBasedHashFs {
         Ok((desc, attr, size, atime))
     }
 
+    async fn inode_get_change_iteration_ids(
+        &self,
+        inos: &[StorageIno],
+    ) -> HashFsResult<HashMap<StorageIno, InoChangeIterationId>> {
+        let keys = inos.iter().map(|ino|{
+            Key::from(self.fs_config.key_builder().inode_x(
+                *ino, InoMetadata::ChangeIterationId).buf)
+        }).collect::<Vec<_>>();
+        let data = self.f_txn.batch_get(keys).await?;
+        let mut ids = HashMap::new();
+        for KvPair(k, v) in data {
+            let ino = self.fs_config.key_parser_b(k)?.parse_ino()?.ino;
+            let id = deserialize_json::<InoChangeIterationId>(&v)
+                .map_err(|err| HashFsError::FsHasInvalidData(Some(
+                    format!("failed to parse InoChangeIterationId: {err:?}"))))?;
+            ids.insert(ino, id);
+        }
+        Ok(ids)
+    }
+
     async fn inode_set_all_attributes(
         &self,
         ino: StorageIno,
@@ -552,33 +982,18 @@ impl HashFsInterface for TikvBasedHashFs {
         flags: Option<u32>,
     ) -> HashFsResult<()> {
 
-        if let Some(atime_modification) = atime {
-            let new_atime = InoAccessTime(get_time_from_time_or_now(atime_modification));
-            self.f_txn.put_json(&ino, Arc::new(new_atime)).await?;
-        }
-
-        if let Some(mtime_modification) = mtime {
-            let new_mtime = InoModificationTime(get_time_from_time_or_now(mtime_modification));
-            self.f_txn.put_json(&ino, Arc::new(new_mtime)).await?;
-        }
-
         // TODO: how to deal with fh, chgtime, bkuptime?
-        let ino_attr_arc: Arc<InoStorageFileAttr> = self.f_txn.fetch(&ino).await?;
-        let mut ino_attr = ino_attr_arc.deref().clone();
-
-        let mut cnt = 0 as usize;
-        set_if_changed(&mut cnt, &mut ino_attr.perm, mode);
-
-        set_if_changed(&mut cnt, &mut ino_attr.uid, uid);
-        set_if_changed(&mut cnt, &mut ino_attr.gid, gid);
-
-        set_if_changed(&mut cnt, &mut ino_attr.last_change,
-            Some(ctime.unwrap_or(SystemTime::now())));
-        set_if_changed(&mut cnt, &mut ino_attr.flags, flags);
-
-        if cnt > 0 {
-            self.f_txn.put_json(&ino, Arc::new(ino_attr)).await?;
+        let atime = atime.map(get_time_from_time_or_now);
+        let mtime = mtime.map(get_time_from_time_or_now);
+        let ctime = ctime.unwrap_or(SystemTime::now());
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut mini = spin.start().await?;
+            let r1 = mini.inode_set_attributes(
+                ino, mode, uid, gid, atime, mtime, ctime, flags).await;
+            if let Some(r) = mini.finish(r1).await { break r?; }
         }
+        self.publish_change(ChangeEvent::Inode(ino));
 
         Ok(())
     }
@@ -598,10 +1013,26 @@ impl HashFsInterface for TikvBasedHashFs {
     async fn inode_close(&self, ino: StorageIno, use_id: Uuid) -> HashFsResult<()> {
         // de-publish opened state
         let mut spin = self.spinning_mini_txn().await?;
-        loop {
+        let update_full_hash = loop {
             let mut mini = spin.start().await?;
             let r1 = mini.inode_close(ino, use_id).await;
             if let Some(r) = mini.finish(r1).await { break r?; }
+        };
+        drop(spin);
+
+        // Writes invalidate the full hash. The last use recomputes it in the background,
+        // such that reading the hash file is fast afterwards. If the file gets opened and
+        // changed again, the change iteration id makes the result being discarded.
+        if update_full_hash {
+            let weak = self.weak.clone();
+            tokio::spawn(async move {
+                let Some(me) = weak.upgrade() else {
+                    return;
+                };
+                if let Err(err) = me.update_full_hash(ino).await {
+                    tracing::debug!("background full hash update of {ino:?} failed: {err:?}");
+                }
+            });
         }
         Ok(())
     }
@@ -619,6 +1050,7 @@ impl HashFsInterface for TikvBasedHashFs {
             if let Some(result) = started.finish(r1).await
             { break result?; }
         }
+        self.publish_change(ChangeEvent::Inode(ino));
         Ok(())
     }
 
@@ -676,6 +1108,88 @@ impl HashFsInterface for TikvBasedHashFs {
         Ok(block_hashes_all.unwrap_or_default())
     }
 
+    async fn hb_get_reference_counts(
+        &self,
+        hashes: &[TiFsHash],
+        _source: &BlockDataSource,
+    ) -> HashFsResult<HashMap<TiFsHash, u64>> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let counts = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_get_reference_counts(hashes.iter()).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(counts)
+    }
+
+    async fn inode_remove_block_range(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        collapse: bool,
+    ) -> HashFsResult<()> {
+        // held until the blocks behind the range are moved down:
+        let lock = self.local_ino_write_size_locks.lock_write(&ino).await;
+        let mut spin = self.spinning_mini_txn().await?;
+        let decrements = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_remove_block_range(ino, block_range.clone()).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        let collapsed: HashFsResult<()> = async {
+            if !collapse {
+                return Ok(());
+            }
+            let shift = block_range.end.0 - block_range.start.0;
+            let mut next = Some(block_range.end);
+            while let Some(from) = next {
+                next = loop {
+                    let mut started = spin.start().await?;
+                    let r1 = started.hb_collapse_blocks_chunk(
+                        ino, from, shift, COLLAPSE_TXN_CHUNK_SIZE).await;
+                    if let Some(r) = started.finish(r1).await { break r?; }
+                };
+            }
+            Ok(())
+        }.await;
+        drop(spin);
+        drop(lock);
+        self.publish_change(ChangeEvent::Inode(ino));
+
+        // the removed blocks are released even if moving the blocks behind them failed:
+        if decrements.len() > 0 {
+            let decrements_ref = decrements.iter().map(|(h,dec)|{
+                (h, *dec)
+            }).collect::<HashMap<_,_>>();
+            let mut spin = self.spinning_mini_txn().await?;
+            loop {
+                let mut started = spin.start().await?;
+                let r1 = started
+                    .hb_decrement_blocks_reference_count_and_delete_if_zero_reached(
+                        &decrements_ref).await;
+                if let Some(result) = started.finish(r1).await
+                { break result?; }
+            }
+        }
+        collapsed
+    }
+
+    async fn inode_seek_block_mapping(
+        &self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        mapped: bool,
+    ) -> HashFsResult<Option<BlockIndex>> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let found = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_seek_block_mapping(
+                ino, block_range.clone(), mapped).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(found)
+    }
+
     async fn inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
         &self,
         ino: StorageIno,
@@ -696,14 +1210,18 @@ impl HashFsInterface for TikvBasedHashFs {
             ()
         }).collect::<Vec<()>>();
 
+        // the replace and the size update share one transaction, such that a
+        // failing quota or space check leaves the previous mappings untouched:
         let mut spin = self.spinning_mini_txn().await?;
         let prev_hash_decrements = loop {
+            let lock = self.local_ino_write_size_locks.lock_write(&ino).await;
             let mut started = spin.start().await?;
             let r1 = started
-                .hb_replace_block_hash_for_address_no_size_update(
-                    &addresses_to_modify).await;
+                .hb_replace_block_hash_for_address_and_size_update(
+                    ino, &addresses_to_modify, max_file_size).await;
             if let Some(result) = started.finish(r1).await
             { break result?; }
+            drop(lock);
         };
         drop(spin);
 
@@ -740,29 +1258,7 @@ impl HashFsInterface for TikvBasedHashFs {
             };
         }
 
-        watch.sync("clear_hash");
-
-        let do_size_update = true;
-        if do_size_update {
-            let size_peek_arc: Arc<InoSize> = self.f_txn.fetch(&ino).await?;
-            let mut size_peek = size_peek_arc.deref().clone();
-            if max_file_size > size_peek.size {
-                size_peek.set_size(max_file_size, self.fs_config.block_size);
-                let mut spin = self.spinning_mini_txn().await?;
-                loop {
-                    let lock = self.local_ino_write_size_locks.lock_write(&ino).await;
-                    let mut started = spin.start().await?;
-                    let r1 = started
-                        .hb_replace_block_hash_for_address_only_size_update_b(
-                            ino, max_file_size).await;
-                    if let Some(result) = started.finish(
-                        r1).await { break result?; }
-                    drop(lock);
-                };
-            }
-        }
-
-        watch.sync("size_update");
+        self.publish_change(ChangeEvent::Inode(ino));
 
         let prev_hash_decrements_ref = prev_hash_decrements.iter().map(|(h,dec)|{
             (h, *dec)
@@ -782,55 +1278,113 @@ impl HashFsInterface for TikvBasedHashFs {
         Ok(())
     }
 
-    async fn file_get_hash(&self, ino: StorageIno) -> HashFsResult<Vec<u8>>
-    {
-        loop {
-            let (hash, _, _)
-                = self.inode_read_metadata_multi(ino, &[InoMetadata::FullHash]).await?;
+    async fn inode_write_blocks(
+        &self,
+        ino: StorageIno,
+        blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+        mut missing_data: MissingBlocksProvider,
+    ) -> HashFsResult<()> {
+        let mut watch = AutoStopWatch::start("write_blocks");
 
-            if let Some(hash) = hash.into_iter().next()  {
-                return Ok(hash);
-            }
+        let mut spin = self.spinning_mini_txn().await?;
+        let missing = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_get_blocks_without_data(
+                blocks.iter().map(|(h, _l, _ids)| *h)).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
 
-            // avoid multiple times hash computation (at least on the same instance)
-            let lock = self.local_ino_locks_full_hash.lock_write(&ino).await;
+        watch.sync("counts");
 
-            let (hash, iter, size)
-                = self.inode_read_metadata_multi(ino, &[
-                    InoMetadata::FullHash,
-                    InoMetadata::ChangeIterationId,
-                    InoMetadata::Size,
-                    ]).await?;
+        let mut new_data = HashMap::new();
+        self.receive_missing_blocks(&mut missing_data, missing, &mut new_data).await?;
 
-            if let Some(hash) = hash.into_iter().next() {
-                return Ok(hash);
-            }
+        watch.sync("receive");
 
-            let Some(size) = size.into_iter().next() else {
-                return Err(HashFsError::FileNotFound);
-            };
+        // chunks registered before an error stay written:
+        let mut registered_any = false;
+        let r: HashFsResult<()> = async {
+            for chunk in blocks.chunks(WRITE_BLOCKS_TXN_CHUNK_SIZE) {
+                // blocks can get deleted after they were reported as stored:
+                loop {
+                    let missing = loop {
+                        let lock = self.local_ino_write_size_locks.lock_write(&ino).await;
+                        let mut started = spin.start().await?;
+                        let r1 = started.inode_write_blocks(ino, chunk, &new_data).await;
+                        if let Some(r) = started.finish(r1).await { break r?; }
+                        drop(lock);
+                    };
+                    if missing.is_empty() {
+                        break;
+                    }
+                    self.receive_missing_blocks(&mut missing_data, missing, &mut new_data).await?;
+                }
+                registered_any = true;
+                for (hash, _len, _ids) in chunk {
+                    new_data.remove(*hash);
+                }
+            }
+            Ok(())
+        }.await;
 
-            let change_iter_id = iter.into_iter().next().unwrap_or_default();
+        watch.sync("register");
+        if registered_any {
+            self.publish_change(ChangeEvent::Inode(ino));
+        }
+        r
+    }
 
-            let size_in_blocks = size.size().div_ceil(self.fs_config.block_size);
-            let block_range = BlockIndex(0)..BlockIndex(
-                size_in_blocks * self.fs_config.hash_len as u64);
-            let hash_data = self.read_hashes_of_file(ino, block_range).await?;
-            let new_full_hash = self.fs_config.calculate_hash(&hash_data);
-            tracing::trace!("freshly_calculated_hash: {:x?}", &new_full_hash);
+    async fn inode_set_blocks_from_hashes(
+        &self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+        hashes: &[TiFsHash],
+        truncate: bool,
+    ) -> HashFsResult<()> {
+        let hole_hash = self.fs_config.calculate_hash(&[]);
+        let mut spin = self.spinning_mini_txn().await?;
+        // unknown blocks are rejected before the first chunk is mapped:
+        let unknown = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.hb_get_blocks_without_data(
+                hashes.iter().filter(|h| **h != hole_hash)).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        if let Some(hash) = unknown.into_iter().next() {
+            return Err(FsError::UnknownBlockHash { hash }.into());
+        }
 
-            let mut spin = self.spinning_mini_txn().await?;
-            let _result = loop {
-                let mut mini = spin.start().await?;
-                let r1 = mini.checked_write_of_file_hash(
-                    &ino, &change_iter_id, new_full_hash.clone()).await;
-                if let Some(r) = mini.finish(r1).await { break r?; }
+        let chunks = if hashes.is_empty() {
+            vec![hashes]
+        } else {
+            hashes.chunks(WRITE_BLOCKS_TXN_CHUNK_SIZE).collect::<Vec<_>>()
+        };
+        let chunk_count = chunks.len();
+        let mut chunk_first = first_block;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            // only the last chunk ends the file:
+            let chunk_truncate = truncate && i + 1 == chunk_count;
+            loop {
+                let lock = self.local_ino_write_size_locks.lock_write(&ino).await;
+                let mut started = spin.start().await?;
+                let r1 = started.inode_set_blocks_from_hashes(
+                    ino, chunk_first, chunk, chunk_truncate).await;
+                if let Some(r) = started.finish(r1).await { break r?; }
+                drop(lock);
             };
-            drop(lock);
-
-            //if result { return Ok(new_full_hash); }
-            return Ok(new_full_hash); // return it anyway. Might block otherwise if file constantly changes
+            chunk_first = chunk_first + BlockIndex(chunk.len() as u64);
         }
+        self.publish_change(ChangeEvent::Inode(ino));
+        Ok(())
+    }
+
+    async fn file_get_hash(&self, ino: StorageIno) -> HashFsResult<Vec<u8>>
+    {
+        self.update_full_hash(ino).await
+    }
+
+    async fn file_get_merkle_hash(&self, ino: StorageIno) -> HashFsResult<TiFsHash> {
+        self.content_hash(ino, FullHashMode::MerkleTree).await
     }
 
     async fn file_read_block_hashes(&self, ino: StorageIno, block_range: Range<BlockIndex>
@@ -841,6 +1395,7 @@ impl HashFsInterface for TikvBasedHashFs {
     async fn hb_get_block_data_by_hashes(
         &self,
         hashes: &HashSet<&TiFsHash>,
+        _source: &BlockDataSource,
     ) -> HashFsResult<HashMap<TiFsHash, Arc<Vec<u8>>>> {
         let mut keys = Vec::with_capacity(hashes.len());
         for hash in hashes {
@@ -857,6 +1412,35 @@ impl HashFsInterface for TikvBasedHashFs {
         Ok(hashed_block_data)
     }
 
+    async fn hb_get_block_data_by_hashes_stream(
+        &self,
+        hashes: Vec<TiFsHash>,
+        source: BlockDataSource,
+    ) -> HashFsResult<BlockDataStream> {
+        let me = self.weak.upgrade().unwrap();
+        let chunks = hashes.chunks(BLOCK_STREAM_CHUNK_SIZE)
+            .map(<[TiFsHash]>::to_vec).collect::<Vec<_>>();
+        // fetches the following chunks while the previous ones are consumed:
+        let fetched = futures::stream::iter(chunks).map(move |chunk| {
+            let me = me.clone();
+            let source = source.clone();
+            async move {
+                let data = me.hb_get_block_data_by_hashes(
+                    &chunk.iter().collect::<HashSet<_>>(), &source).await?;
+                Ok(chunk.into_iter().filter_map(|hash| {
+                    let value = data.get(&hash)?.clone();
+                    Some(Ok((hash, value)))
+                }).collect::<Vec<_>>())
+            }
+        }).buffered(self.fs_config.parallel_jobs.max(1));
+        Ok(Box::pin(fetched.flat_map(|r: HashFsResult<Vec<_>>| {
+            futures::stream::iter(match r {
+                Ok(blocks) => blocks,
+                Err(err) => vec![Err(err)],
+            })
+        })))
+    }
+
     async fn hb_increment_reference_count(
         &self,
         blocks: &[(&TiFsHash, u64)],
@@ -874,17 +1458,112 @@ impl HashFsInterface for TikvBasedHashFs {
     }
 
     async fn hb_upload_new_block(&self, blocks: &[(&TiFsHash, Arc<Vec<u8>>)]) -> HashFsResult<()> {
-        let mutations = blocks.iter().map(|(h,d)|{
+        self.verify_block_hashes(blocks.iter().map(|(h, d)| (*h, d.as_slice())))?;
+        let mutations = blocks.iter().flat_map(|(h,d)|{
             let key = self.fs_config.key_builder().hashed_block(h);
-            Mutation::Put(key.into(), d.deref().clone())
+            let len_key = self.fs_config.key_builder().hashed_block_data_length(h);
+            [Mutation::Put(key.into(), d.deref().clone()),
+             Mutation::Put(len_key.into(), (d.len() as u64).to_be_bytes().to_vec())]
         }).collect::<Vec<_>>();
 
         self.f_txn.batch_mutate(mutations).await?;
         Ok(())
     }
 
+    async fn hb_upload_new_block_stream(&self, blocks: NewBlockStream) -> HashFsResult<()> {
+        let mut chunks = blocks.ready_chunks(BLOCK_STREAM_CHUNK_SIZE);
+        while let Some(chunk) = chunks.next().await {
+            let blocks = chunk.iter().map(|(h, d)| (h, d.clone())).collect::<Vec<_>>();
+            self.hb_upload_new_block(&blocks).await?;
+        }
+        Ok(())
+    }
+
     async fn snapshot_create(&self, name: ByteString) -> HashFsResult<GotOrMade<StorageDirItem>> {
-        self.weak.upgrade().unwrap().snapshot_create_private(name).await
+        let r = self.weak.upgrade().unwrap().snapshot_create_private(name.clone()).await?;
+        if let GotOrMade::NewlyCreated(_) = &r {
+            self.publish_change(ChangeEvent::Entry { parent: SNAPSHOT_PARENT_INODE, name });
+        }
+        Ok(r)
+    }
+
+    async fn quota_set(&self, target: QuotaTarget, limits: QuotaLimits) -> HashFsResult<()> {
+        let mut spin = self.spinning_mini_txn().await?;
+        loop {
+            let mut started = spin.start().await?;
+            let r1 = started.quota_set(target, limits.clone()).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        }
+        Ok(())
+    }
+
+    async fn quota_list(&self) -> HashFsResult<Vec<QuotaReport>> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let reports = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.quota_scan(MAX_TIKV_SCAN_LIMIT).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(reports)
+    }
+
+    async fn quota_report(&self, target: QuotaTarget) -> HashFsResult<Option<QuotaReport>> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let state = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.quota_read(target).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(state.map(|state| QuotaReport { target, state }))
+    }
+
+    async fn fs_stats(&self) -> HashFsResult<FsStats> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let (meta, block_stats) = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.meta_mutable_read().await;
+            let r2 = match r1 {
+                Ok(meta) => started.block_stats_delta_read().await
+                    .map(|block_stats| (meta, block_stats)),
+                Err(err) => Err(err),
+            };
+            if let Some(r) = started.finish(r2).await { break r?; }
+        };
+        let meta = meta.ok_or(HashFsError::FsNotInitialized)?;
+        let mut stats = FsStats {
+            logical_bytes: meta.used_logical_bytes,
+            inode_count: meta.inode_count,
+            unique_blocks: meta.used_physical_blocks,
+            unique_block_capacity_bytes: meta.used_physical_blocks
+                .saturating_mul(self.fs_config.block_size),
+            ..Default::default()
+        };
+        stats.set_block_stats(&block_stats);
+        Ok(stats)
+    }
+
+    async fn file_stats(&self, ino: StorageIno) -> HashFsResult<FileStats> {
+        let mut spin = self.spinning_mini_txn().await?;
+        let stats = loop {
+            let mut started = spin.start().await?;
+            let r1 = started.inode_collect_block_sharing_stats(ino).await;
+            if let Some(r) = started.finish(r1).await { break r?; }
+        };
+        Ok(stats)
+    }
+
+    async fn watch_changes(&self) -> HashFsResult<ChangeEventStream> {
+        let receiver = self.change_events.subscribe();
+        let local = futures::stream::unfold(receiver, |mut receiver| async move {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => ChangeEvent::Overflow,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            Some((Ok(event), receiver))
+        });
+        let logged = self.watch_change_log().await?;
+        Ok(Box::pin(futures::stream::select(local, logged)))
     }
 } // interface impl end
 
@@ -901,6 +1580,14 @@ impl From<HashFsError> for FsError {
             HashFsError::InodeHasNoInlineData => FsError::WrongFileType,
             HashFsError::GrpcMessageIncomplete => FsError::GrpcMessageIncomplete,
             HashFsError::RawTonicTransportError(err) => FsError::UnknownError(format!("RawTonicTransportError: {:?}", err)),
+            HashFsError::DirNotEmpty => FsError::DirNotEmpty { dir: format!("undefined") },
+            HashFsError::NotADirectory => FsError::NotDirectory { file: format!("undefined") },
+            HashFsError::IsADirectory => FsError::IsDirectory { file: format!("undefined") },
+            HashFsError::NoSpaceLeft => FsError::NoSpaceLeft(0),
+            HashFsError::QuotaExceeded => FsError::QuotaExceeded { target: format!("undefined") },
+            HashFsError::CrossQuotaRename => FsError::CrossQuotaRename,
+            HashFsError::UnknownBlockHash => FsError::UnknownBlockHash { hash: vec![] },
+            HashFsError::PermissionDenied => FsError::PermissionDenied,
         }
     }
 }
@@ -910,6 +1597,15 @@ impl From<FsError> for HashFsError {
         match value {
             FsError::InodeNotFound { inode: _ } => HashFsError::FileNotFound,
             FsError::FileNotFound { file: _ } => HashFsError::FileNotFound,
+            FsError::FileExist { file: _ } => HashFsError::FileAlreadyExists,
+            FsError::DirNotEmpty { dir: _ } => HashFsError::DirNotEmpty,
+            FsError::NotDirectory { file: _ } => HashFsError::NotADirectory,
+            FsError::IsDirectory { file: _ } => HashFsError::IsADirectory,
+            FsError::NoSpaceLeft(_) => HashFsError::NoSpaceLeft,
+            FsError::QuotaExceeded { target: _ } => HashFsError::QuotaExceeded,
+            FsError::CrossQuotaRename => HashFsError::CrossQuotaRename,
+            FsError::UnknownBlockHash { hash: _ } => HashFsError::UnknownBlockHash,
+            FsError::PermissionDenied => HashFsError::PermissionDenied,
             FsError::KeyNotFound(msg) =>
                 HashFsError::FsHasMissingData(Some(format!("key not found: {:?}", msg))),
             other => HashFsError::Unspecific(format!("FsError: {other:?}")),
@@ -1,5 +1,7 @@
 use std::{borrow::Cow, mem};
 
+use num_bigint::BigUint;
+
 use super::inode::TiFsHash;
 
 pub type HashBlockData<'ol> = Cow<'ol, [u8]>;
@@ -32,3 +34,59 @@ impl<'ol> HashedBlock<'ol> {
         dest[start_write_pos..write_till_position].copy_from_slice(block_write_data);
     }
 }
+
+/// Value of the reference counter of a stored block.
+/// Without flags it is stored as the plain big endian counter, as before.
+/// With flags, a zero byte and the flags precede the counter (a plain counter
+/// never starts with a zero byte, except the single byte of zero).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockReferenceCount {
+    pub count: BigUint,
+    /// The data was moved to the quarantine by the scrubber. Cleared once
+    /// the data is stored again and the block gets referenced.
+    pub quarantined: bool,
+}
+
+const REFERENCE_COUNT_FLAGS_MARKER: u8 = 0x00;
+const REFERENCE_COUNT_QUARANTINED: u8 = 0x01;
+
+impl BlockReferenceCount {
+    pub fn from_bytes(value: &[u8]) -> Self {
+        match value {
+            [REFERENCE_COUNT_FLAGS_MARKER, flags, count @ ..] => Self {
+                count: BigUint::from_bytes_be(count),
+                quarantined: flags & REFERENCE_COUNT_QUARANTINED != 0,
+            },
+            count => Self { count: BigUint::from_bytes_be(count), quarantined: false },
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if !self.quarantined {
+            return self.count.to_bytes_be();
+        }
+        let mut value = vec![REFERENCE_COUNT_FLAGS_MARKER, REFERENCE_COUNT_QUARANTINED];
+        value.extend_from_slice(&self.count.to_bytes_be());
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+
+    use super::BlockReferenceCount;
+
+    #[test]
+    fn reference_count_with_and_without_flags() {
+        let plain = BlockReferenceCount { count: BigUint::from(300u32), quarantined: false };
+        assert_eq!(plain.to_bytes(), BigUint::from(300u32).to_bytes_be());
+        assert_eq!(BlockReferenceCount::from_bytes(&plain.to_bytes()), plain);
+        assert_eq!(BlockReferenceCount::from_bytes(&[0]), BlockReferenceCount::default());
+
+        let quarantined = BlockReferenceCount { count: BigUint::from(2u32), quarantined: true };
+        assert_eq!(BlockReferenceCount::from_bytes(&quarantined.to_bytes()), quarantined);
+        let zero = BlockReferenceCount { count: BigUint::from(0u32), quarantined: true };
+        assert_eq!(BlockReferenceCount::from_bytes(&zero.to_bytes()), zero);
+    }
+}
@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::key::BlockAddress;
+use super::utils::full_hash::FullHashMode;
 
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -43,6 +44,10 @@ pub enum StorageDirItemKind {
     File,
     Directory,
     Symlink,
+    CharDevice,
+    BlockDevice,
+    NamedPipe,
+    Socket,
 }
 
 #[derive(Debug, Clone)]
@@ -110,8 +115,61 @@ impl InoChangeIterationId {
     }
 }
 
+/// Cached full hash of the file content. Only valid if it was computed
+/// with the full hash mode and format version of the reader.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub struct InoFullHash(pub TiFsHash);
+#[serde(from = "StoredInoFullHash")]
+pub struct InoFullHash {
+    pub hash: TiFsHash,
+    pub mode: FullHashMode,
+    pub version: u32,
+}
+
+impl InoFullHash {
+    /// Version 0 is the plain flat hash stored by older versions. It hashed
+    /// the block hashes of a range larger than the file. Version 1 hashes
+    /// exactly the blocks of the file.
+    pub const VERSION: u32 = 1;
+
+    pub fn new(hash: TiFsHash, mode: FullHashMode) -> Self {
+        Self { hash, mode, version: Self::VERSION }
+    }
+
+    /// Values of older versions stay valid until the file changes,
+    /// such that the hash of unchanged files doesn't change on upgrade.
+    pub fn is_valid_for(&self, mode: FullHashMode) -> bool {
+        self.mode == mode && (self.version == Self::VERSION
+            || (self.version == 0 && mode == FullHashMode::Flat))
+    }
+}
+
+/// Stored merkle tree hash of the file content for flat full hash configurations.
+/// Only valid for the change iteration it was computed for.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InoMerkleHash {
+    pub hash: TiFsHash,
+    pub change_iteration: InoChangeIterationId,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredInoFullHash {
+    Versioned { hash: TiFsHash, mode: FullHashMode, version: u32 },
+    Legacy(TiFsHash),
+}
+
+impl From<StoredInoFullHash> for InoFullHash {
+    fn from(value: StoredInoFullHash) -> Self {
+        match value {
+            StoredInoFullHash::Versioned { hash, mode, version } => Self { hash, mode, version },
+            StoredInoFullHash::Legacy(hash) => Self { hash, mode: FullHashMode::Flat, version: 0 },
+        }
+    }
+}
+
+/// The directory whose quota this inode is charged to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InoQuotaDirectory(pub StorageIno);
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct InoInlineData{
@@ -158,6 +216,11 @@ pub struct InoSize {
     pub size: u64,
     pub blocks: u64,
     pub last_change: SystemTime,
+    /// Number of block indices that actually have a block hash mapped.
+    /// Unmapped indices are holes. None for inodes written before this
+    /// was tracked - those are assumed to be fully allocated.
+    #[serde(default)]
+    pub mapped_blocks: Option<u64>,
 }
 
 impl InoSize {
@@ -166,9 +229,26 @@ impl InoSize {
             size: 0,
             blocks: 0,
             last_change: SystemTime::now(),
+            mapped_blocks: Some(0),
         }
     }
 
+    pub fn mapped_blocks(&self) -> u64 {
+        self.mapped_blocks.unwrap_or(self.blocks)
+    }
+
+    pub fn update_mapped_blocks(&mut self, added: u64, removed: u64) -> bool {
+        let Some(mapped) = self.mapped_blocks else {
+            return false;
+        };
+        let new_mapped = (mapped + added).saturating_sub(removed);
+        if new_mapped == mapped {
+            return false;
+        }
+        self.mapped_blocks = Some(new_mapped);
+        true
+    }
+
     fn update_blocks(&mut self, block_size: u64) {
         self.blocks = self.size.div_ceil(block_size);
     }
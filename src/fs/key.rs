@@ -8,10 +8,15 @@ use uuid::Uuid;
 use strum::{EnumIter, IntoEnumIterator};
 
 use super::error::TiFsResult;
+use super::fs_stats::BlockStatsDelta;
 use super::hash_fs_interface::BlockIndex;
-use super::inode::{InoAccessTime, InoChangeIterationId, InoDescription, InoFullHash, InoInlineData, InoLockState, InoSize, InoStorageFileAttr, InoModificationTime, ParentStorageIno, StorageDirItem, StorageIno};
+use super::inode::{InoAccessTime, InoChangeIterationId, InoDescription, InoFullHash, InoInlineData, InoLockState, InoMerkleHash, InoSize, InoStorageFileAttr, InoModificationTime, InoQuotaDirectory, ParentStorageIno, StorageDirItem, StorageIno};
 use super::meta::{MetaMutable, MetaStatic};
+use super::quota::{QuotaState, QuotaTarget};
+use super::usage::{UsageCounter, UsageDelta};
 use super::reply::LogicalIno;
+use super::scrubber::ScrubProgress;
+use super::change_log::ChangeLogWatchLease;
 use super::tikv_fs::InoUse;
 use super::{error::FsError, inode::TiFsHash};
 
@@ -27,6 +32,7 @@ pub const OPENED_INODE_PARENT_INODE: ParentStorageIno = ParentStorageIno(Storage
 pub const OPENED_INODE_PARENT_INODE_NAME: &str = ".@opened_inodes";
 pub const SNAPSHOT_PARENT_INODE: ParentStorageIno = ParentStorageIno(StorageIno(ROOT_INODE.0.0-1+3)); // NR: 3
 pub const SNAPSHOT_PARENT_INODE_NAME: &str = ".@snapshots";
+pub const FS_STATS_FILE_NAME: &str = ".@stats";
 pub const FIRST_DATA_INODE: StorageIno = StorageIno(10); // keep some reserved inodes for later use
 
 /// ATTENTION: Order of enums in this struct matters for serialization!
@@ -45,6 +51,14 @@ pub enum KeyKind {
     PendingDelete,
     //HashedBlockUsedBy { hash: &'a[u8], ino: u64, block: u64 },
     NamedHashedBlock, // { hash: &[u8], meta, uuid },
+    Quota, // { target_kind: u8, id: u64 } => QuotaState
+    ScrubProgress, // {} => ScrubProgress
+    QuarantinedBlock, // { hash: &[u8] } => { data: Vec<u8> }
+    ChangeLog, // { time_us: u64, id: uuid } => ChangeLogEntry
+    UsageDelta, // { counter: UsageCounter, shard: u16 } => UsageDelta
+    BlockStatsDelta, // { shard: u16 } => BlockStatsDelta
+    ChangeLogWatchLease, // {} => ChangeLogWatchLease
+    FullHashSegment, // { ino: u64, first_block: u64 } => { hash: &[u8] }
 }
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Copy, EnumIter)]
@@ -52,6 +66,7 @@ pub enum HashedBlockMeta {
     ANamedBlock,
     BNamedUsages,
     CCountedNamedUsages,
+    DDataLength, // u64 (big endian), length of the block data
 }
 
 
@@ -90,6 +105,8 @@ pub enum InoMetadata {
     FullHash,           // not part of snapshot
     ChangeIterationId,  // uuid, not counter. not part of snapshot
     InlineData,
+    QuotaDirectory,     // not part of snapshot
+    MerkleHash,         // not part of snapshot
 }
 
 lazy_static!{
@@ -192,6 +209,49 @@ pub fn check_file_name(name: &str) -> TiFsResult<()> {
     }
 }
 
+impl KeyDeSer for QuotaTarget {
+    fn deserialize_from(i: &mut impl Iterator<Item = u8>) -> TiFsResult<Self> {
+        let kind = i.next().unwrap_or(0xFF);
+        let id = read_big_endian::<8, u64>(i)?;
+        match kind {
+            0 => Ok(QuotaTarget::User(id as u32)),
+            1 => Ok(QuotaTarget::Group(id as u32)),
+            2 => Ok(QuotaTarget::Directory(StorageIno(id))),
+            other => Err(FsError::UnknownError(format!("unknown quota target kind: {}", other))),
+        }
+    }
+
+    fn serialize_to(self, buf: &mut KeyBuffer) {
+        let (kind, id) = match self {
+            QuotaTarget::User(uid) => (0u8, uid as u64),
+            QuotaTarget::Group(gid) => (1u8, gid as u64),
+            QuotaTarget::Directory(ino) => (2u8, ino.0),
+        };
+        buf.push(kind);
+        write_big_endian::<u64>(id, buf);
+    }
+}
+
+impl KeyDeSer for UsageCounter {
+    fn deserialize_from(i: &mut impl Iterator<Item = u8>) -> TiFsResult<Self> {
+        match i.next().unwrap_or(0xFF) {
+            0 => Ok(UsageCounter::Fs),
+            1 => Ok(UsageCounter::Quota(QuotaTarget::deserialize_from(i)?)),
+            other => Err(FsError::UnknownError(format!("unknown usage counter kind: {}", other))),
+        }
+    }
+
+    fn serialize_to(self, buf: &mut KeyBuffer) {
+        match self {
+            UsageCounter::Fs => buf.push(0),
+            UsageCounter::Quota(target) => {
+                buf.push(1);
+                target.serialize_to(buf);
+            }
+        }
+    }
+}
+
 pub struct KeyParser<I>
 where I: Iterator<Item = u8>
 {
@@ -281,6 +341,15 @@ where I: Iterator<Item = u8>
         Ok((self, hash))
     }
 
+    /// Returns the hash of any HashedBlock key and whether it is the key of the block data itself.
+    pub fn parse_hash_block_key_is_data(self) -> TiFsResult<(TiFsHash, bool)> {
+        if self.kind != KeyKind::HashedBlock {
+            return Err(FsError::UnknownError(format!("expected a HashedBlock key. got: {:?}", self.kind)))
+        }
+        let (mut me, hash) = self.parse_hash()?;
+        Ok((hash, me.i.next().is_none()))
+    }
+
     pub fn parse_key_hashed_block(self) -> TiFsResult<TiFsHash> {
         Ok(match self.kind {
             KeyKind::HashedBlock => self.parse_hash()?.1,
@@ -295,6 +364,7 @@ where I: Iterator<Item = u8>
         Ok(match self.kind {
             KeyKind::Block => BlockAddress::deserialize_from(&mut self.i)?,
             KeyKind::InoBlockHashMapping => BlockAddress::deserialize_from(&mut self.i)?,
+            KeyKind::FullHashSegment => BlockAddress::deserialize_from(&mut self.i)?,
             other => {
                 return Err(FsError::UnknownError(format!("parse_key_block_address(): unexpected key_type: {:?}", other)));
             }
@@ -328,6 +398,25 @@ where I: Iterator<Item = u8>
         let child_name = self.i.collect::<Vec<_>>();
         Ok((dir_ino, child_name))
     }
+
+    pub fn parse_quota_target(mut self) -> TiFsResult<QuotaTarget> {
+        if self.kind != KeyKind::Quota {
+            return Err(FsError::UnknownError(
+                format!("parse_quota_target(): unexpected key_type: {:?}", self.kind)));
+        }
+        QuotaTarget::deserialize_from(&mut self.i)
+    }
+
+    /// Returns the write time in microseconds and the id of the entry.
+    pub fn parse_change_log(mut self) -> TiFsResult<(u64, Uuid)> {
+        if self.kind != KeyKind::ChangeLog {
+            return Err(FsError::UnknownError(
+                format!("parse_change_log(): unexpected key_type: {:?}", self.kind)));
+        }
+        let time_us = read_big_endian::<8, u64>(&mut self.i)?;
+        let id = parse_uuid(&mut self.i)?;
+        Ok((time_us, id))
+    }
 }
 
 pub struct KeyParserIno<I>
@@ -526,6 +615,28 @@ impl ScopedKeyBuilder {
         me.buf
     }
 
+    pub fn hashed_block_all_range(self) -> BoundRange {
+        self.write_key_kind(KeyKind::HashedBlock).sub_key_range()
+    }
+
+    pub fn hashed_block_data_length(self, hash: &[u8]) -> KeyBuffer {
+        self.named_hashed_block_x(hash, Some(HashedBlockMeta::DDataLength), None)
+    }
+
+    pub fn quarantined_block(self, hash: &[u8]) -> KeyBuffer {
+        let mut me = self.write_key_kind(KeyKind::QuarantinedBlock);
+        me.buf.extend_from_slice(hash);
+        me.buf
+    }
+
+    pub fn scrub_progress(self) -> KeyBuffer {
+        self.write_key_kind(KeyKind::ScrubProgress).buf
+    }
+
+    pub fn change_log_watch_lease(self) -> KeyBuffer {
+        self.write_key_kind(KeyKind::ChangeLogWatchLease).buf
+    }
+
     pub fn named_hashed_block_range<'fl>(self, hash: &'fl[u8]) -> BoundRange {
         let mut me = self.write_key_kind(KeyKind::NamedHashedBlock);
         me.buf.extend_from_slice(hash);
@@ -571,25 +682,116 @@ impl ScopedKeyBuilder {
         }
     }
 
+    /// Links of ino to the given parent.
+    pub fn parent_link_scan_of_parent(self, ino: StorageIno, parent: ParentStorageIno) -> BoundRange {
+        let mut me1 = self.write_key_kind(KeyKind::ParentLink);
+        ino.serialize_to(&mut me1.buf);
+        let mut me2 = me1.clone();
+        parent.0.serialize_to(&mut me1.buf);
+        StorageIno(parent.0.0+1).serialize_to(&mut me2.buf);
+        BoundRange {
+            from: Bound::Included(Key::from(me1.buf)),
+            to: Bound::Excluded(Key::from(me2.buf)),
+        }
+    }
+
     pub fn directory_child_range(self, dir_ino: StorageIno) -> BoundRange {
-        let start_key = self.clone().write_key_kind(KeyKind::DirectoryChild)
-            .write_key_de_ser(dir_ino).buf;
+        self.directory_child_range_after(dir_ino, None)
+    }
+
+    /// Range of all children of dir_ino with a name greater than start_after.
+    pub fn directory_child_range_after(self, dir_ino: StorageIno, start_after: Option<&[u8]>) -> BoundRange {
+        let from = if let Some(name) = start_after {
+            std::ops::Bound::Excluded(Key::from(self.clone().directory_child(dir_ino, name)))
+        } else {
+            let start_key = self.clone().write_key_kind(KeyKind::DirectoryChild)
+                .write_key_de_ser(dir_ino).buf;
+            std::ops::Bound::Included(Key::from(start_key))
+        };
         let end_key = self.write_key_kind(KeyKind::DirectoryChild)
             .write_key_de_ser(StorageIno(dir_ino.0+1)).buf;
         let range = BoundRange {
-            from: std::ops::Bound::Included(Key::from(start_key)),
+            from,
             to: std::ops::Bound::Excluded(Key::from(end_key)),
         };
         range
     }
 
 
+    pub fn quota(self, target: QuotaTarget) -> KeyBuffer {
+        self.write_key_kind(KeyKind::Quota).write_key_de_ser(target).buf
+    }
+
+    pub fn quota_range(self) -> BoundRange {
+        self.write_key_kind(KeyKind::Quota).sub_key_range()
+    }
+
+    pub fn usage_delta(self, counter: UsageCounter, shard: u16) -> KeyBuffer {
+        let mut me = self.write_key_kind(KeyKind::UsageDelta).write_key_de_ser(counter);
+        write_big_endian(shard, &mut me.buf);
+        me.buf
+    }
+
+    /// All shards of the counter.
+    pub fn usage_delta_range(self, counter: UsageCounter) -> BoundRange {
+        let me = self.write_key_kind(KeyKind::UsageDelta).write_key_de_ser(counter);
+        let mut end = me.buf.clone();
+        write_big_endian(u16::MAX, &mut end);
+        BoundRange {
+            from: Bound::Included(Key::from(me.buf)),
+            to: Bound::Included(Key::from(end)),
+        }
+    }
+
+    pub fn block_stats_delta(self, shard: u16) -> KeyBuffer {
+        let mut me = self.write_key_kind(KeyKind::BlockStatsDelta);
+        write_big_endian(shard, &mut me.buf);
+        me.buf
+    }
+
+    pub fn block_stats_delta_range(self) -> BoundRange {
+        self.write_key_kind(KeyKind::BlockStatsDelta).sub_key_range()
+    }
+
+    pub fn change_log(self, time_us: u64, id: Uuid) -> KeyBuffer {
+        let mut me = self.write_key_kind(KeyKind::ChangeLog);
+        write_big_endian(time_us, &mut me.buf);
+        me.buf.extend_from_slice(id.as_bytes());
+        me.buf
+    }
+
+    /// Change log entries written at time_us or later.
+    pub fn change_log_range_from(self, time_us: u64) -> BoundRange {
+        let from = self.clone().change_log(time_us, Uuid::nil());
+        let to = self.write_key_kind(KeyKind::ChangeLog).sub_key_range().to;
+        BoundRange { from: Bound::Included(Key::from(from)), to }
+    }
+
+    /// Change log entries written before time_us.
+    pub fn change_log_range_before(self, time_us: u64) -> BoundRange {
+        let to = self.clone().change_log(time_us, Uuid::nil());
+        let from = self.write_key_kind(KeyKind::ChangeLog).sub_key_range().from;
+        BoundRange { from, to: Bound::Excluded(Key::from(to)) }
+    }
+
     pub fn block_range(self, ino: StorageIno, block_range: Range<BlockIndex>) -> Range<Key> {
         debug_assert_ne!(0, ino.0);
         self.clone().block(BlockAddress { ino, index: block_range.start }).into()
             ..self.block(BlockAddress { ino, index: block_range.end}).into()
     }
 
+    /// Stored root of the merkle tree full hash of the segment starting at addr.
+    pub fn full_hash_segment(self, addr: BlockAddress) -> KeyBuffer {
+        let mut me = self.write_key_kind(KeyKind::FullHashSegment);
+        addr.serialize_to(&mut me.buf);
+        me.buf
+    }
+
+    pub fn full_hash_segment_range(self, ino: StorageIno, block_range: Range<BlockIndex>) -> Range<Key> {
+        self.clone().full_hash_segment(BlockAddress { ino, index: block_range.start })
+            .into()..self.full_hash_segment(BlockAddress { ino, index: block_range.end }).into()
+    }
+
     pub fn block_hash_range(self, ino: StorageIno, block_range: Range<BlockIndex>) -> Range<Key> {
         debug_assert_ne!(0, ino.0);
         self.clone().block_hash(BlockAddress { ino, index: block_range.start, })
@@ -696,16 +898,63 @@ impl KeyGenerator<StorageIno, InoFullHash> for ScopedKeyBuilder {
     }
 }
 
+impl KeyGenerator<StorageIno, InoMerkleHash> for ScopedKeyBuilder {
+    fn generate_key(self, k: &StorageIno) -> KeyBuffer {
+        self.inode_x(*k, InoMetadata::MerkleHash).buf
+    }
+}
+
 impl KeyGenerator<StorageIno, InoInlineData> for ScopedKeyBuilder {
     fn generate_key(self, k: &StorageIno) -> KeyBuffer {
         self.inode_x(*k, InoMetadata::InlineData).buf
     }
 }
 
+impl KeyGenerator<StorageIno, InoQuotaDirectory> for ScopedKeyBuilder {
+    fn generate_key(self, k: &StorageIno) -> KeyBuffer {
+        self.inode_x(*k, InoMetadata::QuotaDirectory).buf
+    }
+}
+
+impl KeyGenerator<QuotaTarget, QuotaState> for ScopedKeyBuilder {
+    fn generate_key(self, k: &QuotaTarget) -> KeyBuffer {
+        self.quota(*k)
+    }
+}
+
+impl KeyGenerator<(UsageCounter, u16), UsageDelta> for ScopedKeyBuilder {
+    fn generate_key(self, k: &(UsageCounter, u16)) -> KeyBuffer {
+        self.usage_delta(k.0, k.1)
+    }
+}
+
+impl KeyGenerator<u16, BlockStatsDelta> for ScopedKeyBuilder {
+    fn generate_key(self, k: &u16) -> KeyBuffer {
+        self.block_stats_delta(*k)
+    }
+}
+
+impl KeyGenerator<(), ScrubProgress> for ScopedKeyBuilder {
+    fn generate_key(self, _k: &()) -> KeyBuffer {
+        self.scrub_progress()
+    }
+}
+
+impl KeyGenerator<(), ChangeLogWatchLease> for ScopedKeyBuilder {
+    fn generate_key(self, _k: &()) -> KeyBuffer {
+        self.change_log_watch_lease()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::fs::{hash_fs_interface::BlockIndex, inode::StorageIno, key::{parse_uuid, read_big_endian, write_big_endian, BlockAddress, InoMetadata, KeyBuffer, KeyKind, KeyParser}, utils::hash_algorithm::HashAlgorithm};
+    use crate::fs::{hash_fs_interface::BlockIndex, inode::StorageIno, quota::QuotaTarget, key::{parse_uuid, read_big_endian, write_big_endian, BlockAddress, InoMetadata, KeyBuffer, KeyKind, KeyParser}, utils::hash_algorithm::HashAlgorithm};
 
+    use std::ops::RangeBounds;
+
+    use tikv_client::Key;
+
+    use crate::fs::usage::UsageCounter;
     use super::ScopedKeyBuilder;
 
     const TEST_PREFIX: &[u8] = b"HelloWorld";
@@ -786,6 +1035,41 @@ mod tests {
         assert_eq!(addr, read_addr);
     }
 
+    #[test]
+    fn serialize_deserialize_quota_target() {
+        for target in [QuotaTarget::User(1000), QuotaTarget::Group(7), QuotaTarget::Directory(StorageIno(42))] {
+            let kb = ScopedKeyBuilder::new(TEST_PREFIX).quota(target);
+            let mut i = kb.iter().cloned();
+            let kp = KeyParser::start(&mut i, TEST_PREFIX, 32).unwrap();
+            assert_eq!(kp.kind, KeyKind::Quota);
+            assert_eq!(kp.parse_quota_target().unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn serialize_deserialize_change_log() {
+        let id = uuid::Uuid::new_v4();
+        let kb = ScopedKeyBuilder::new(TEST_PREFIX).change_log(1 << 50, id);
+        let mut i = kb.iter().cloned();
+        let kp = KeyParser::start(&mut i, TEST_PREFIX, 32).unwrap();
+        assert_eq!(kp.kind, KeyKind::ChangeLog);
+        assert_eq!(kp.parse_change_log().unwrap(), (1 << 50, id));
+        // keys sort by their time:
+        assert!(kb < ScopedKeyBuilder::new(TEST_PREFIX).change_log((1 << 50) + 1, uuid::Uuid::nil()));
+    }
+
+    #[test]
+    fn usage_delta_range_covers_all_shards_of_the_counter() {
+        let user = UsageCounter::Quota(QuotaTarget::User(u32::MAX));
+        let range = ScopedKeyBuilder::new(TEST_PREFIX).usage_delta_range(user);
+        for shard in [0, 1, u16::MAX] {
+            let key = Key::from(ScopedKeyBuilder::new(TEST_PREFIX).usage_delta(user, shard));
+            assert!(range.contains(&key));
+        }
+        let other = Key::from(ScopedKeyBuilder::new(TEST_PREFIX).usage_delta(UsageCounter::Fs, 0));
+        assert!(!range.contains(&other));
+    }
+
     #[test]
     fn serialize_deserialize_big_endian_integer() {
         let mut buf = KeyBuffer::new();
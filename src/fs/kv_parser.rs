@@ -2,12 +2,12 @@ use std::collections::HashMap;
 
 use tikv_client::KvPair;
 
-use super::{error::TiFsResult, fs_config::TiFsConfig, index::deserialize_json, inode::{InoAccessTime, InoChangeIterationId, InoDescription, InoInlineData, InoModificationTime, InoSize, InoStorageFileAttr, StorageIno, TiFsHash}, key::InoMetadata};
+use super::{error::TiFsResult, fs_config::TiFsConfig, index::deserialize_json, inode::{InoAccessTime, InoChangeIterationId, InoDescription, InoFullHash, InoInlineData, InoModificationTime, InoSize, InoStorageFileAttr, StorageIno}, key::InoMetadata};
 
 #[derive(Default)]
 pub struct InodesAttrsHashMaps {
     pub descriptions: HashMap<StorageIno, InoDescription>,
-    pub existing_hash: HashMap<StorageIno, TiFsHash>,
+    pub existing_hash: HashMap<StorageIno, InoFullHash>,
     pub change_iter_id: HashMap<StorageIno, InoChangeIterationId>,
     pub size: HashMap<StorageIno, InoSize>,
     pub attrs: HashMap<StorageIno, InoStorageFileAttr>,
@@ -36,7 +36,9 @@ impl KvPairParser {
                         .map(|id| maps.descriptions.insert(parsed.ino, id));
                 }
                 InoMetadata::FullHash => {
-                    maps.existing_hash.insert(parsed.ino, v);
+                    // entries that don't parse are recomputed on demand:
+                    let _ = deserialize_json::<InoFullHash>(&v)
+                        .map(|h| maps.existing_hash.insert(parsed.ino, h));
                 }
                 InoMetadata::ChangeIterationId => {
                     let _ = deserialize_json::<InoChangeIterationId>(&v)
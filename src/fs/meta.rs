@@ -15,6 +15,25 @@ pub struct MetaStatic{
 pub struct MetaMutable {
     pub inode_next: u64,
     pub last_stat: Option<StatFs>,
+    // The usage counters below are base values. Changes are written as
+    // UsageDelta shards and added by meta_mutable_read.
+    /// Sum of the sizes of all inodes.
+    #[serde(default)]
+    pub used_logical_bytes: u64,
+    /// Number of stored hashed blocks. Deduplicated blocks are counted once.
+    #[serde(default)]
+    pub used_physical_blocks: u64,
+    /// Number of existing inodes.
+    #[serde(default)]
+    pub inode_count: u64,
+}
+
+impl MetaMutable {
+    pub fn apply_usage_delta(&mut self, logical_bytes: i64, physical_blocks: i64, inodes: i64) {
+        self.used_logical_bytes = self.used_logical_bytes.saturating_add_signed(logical_bytes);
+        self.used_physical_blocks = self.used_physical_blocks.saturating_add_signed(physical_blocks);
+        self.inode_count = self.inode_count.saturating_add_signed(inodes);
+    }
 }
 
 impl MetaMutable {
@@ -0,0 +1,281 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytestring::ByteString;
+
+use super::fuse_to_hashfs::MAX_TIKV_SCAN_LIMIT;
+use super::hash_fs_interface::{HashFsInterface, HashFsResult, InoAllAttributes};
+use super::inode::{InoChangeIterationId, ParentStorageIno, StorageIno};
+
+/// Time of the last validation. Shared by the clones of a cached value, such
+/// that a revalidation doesn't write the entry and doesn't count as its use.
+#[derive(Clone)]
+struct Validated(Arc<Mutex<Instant>>);
+
+impl Validated {
+    fn new(at: Instant) -> Self {
+        Self(Arc::new(Mutex::new(at)))
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+
+    fn extend_to(&self, at: Instant) {
+        let mut validated = self.0.lock().unwrap();
+        *validated = (*validated).max(at);
+    }
+}
+
+#[derive(Clone)]
+struct CachedAttrs {
+    attrs: InoAllAttributes,
+    /// Read before the attributes. None if the inode has no id yet.
+    change_id: Option<InoChangeIterationId>,
+    validated: Validated,
+}
+
+#[derive(Clone)]
+struct CachedEntry {
+    ino: StorageIno,
+    /// Directory entries are validated with the id of their parent.
+    parent_change_id: Option<InoChangeIterationId>,
+    validated: Validated,
+}
+
+/// Entries that weren't used for the ttl are dropped, so only recently
+/// used entries are revalidated.
+fn idle_expiring_cache<K, V>(capacity: u64, ttl: Duration) -> moka::future::Cache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    let builder = moka::future::Cache::builder().max_capacity(capacity);
+    if ttl.is_zero() {
+        builder.build()
+    } else {
+        builder.time_to_idle(ttl).build()
+    }
+}
+
+/// Client side cache of inode attributes and directory entries.
+/// Entries are used for attr_ttl / entry_ttl after their last validation.
+/// While the change feed of the hash fs is active, its events invalidate the
+/// entries modified by other mounts and cached entries stay valid. Otherwise
+/// the revalidation compares the change iteration ids of the entries used
+/// within the last ttl in batches. Local modifications invalidate the
+/// affected entries directly.
+#[derive(Clone)] // only clones references
+pub struct MetaCache {
+    attrs: moka::future::Cache<StorageIno, CachedAttrs>,
+    entries: moka::future::Cache<(ParentStorageIno, ByteString), CachedEntry>,
+    attr_ttl: Duration,
+    entry_ttl: Duration,
+    change_feed_active: Arc<AtomicBool>,
+}
+
+impl MetaCache {
+    pub fn new(capacity: u64, attr_ttl: Duration, entry_ttl: Duration) -> Self {
+        Self {
+            attrs: idle_expiring_cache(capacity, attr_ttl),
+            entries: idle_expiring_cache(capacity, entry_ttl),
+            attr_ttl,
+            entry_ttl,
+            change_feed_active: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Set while change events are received. Events lost while it is unset
+    /// have to be covered by invalidate_all.
+    pub fn set_change_feed_active(&self, active: bool) {
+        self.change_feed_active.store(active, Ordering::Relaxed);
+    }
+
+    fn is_valid(&self, validated: &Validated, ttl: Duration) -> bool {
+        self.change_feed_active.load(Ordering::Relaxed) || validated.elapsed() < ttl
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.attr_ttl.is_zero() || !self.entry_ttl.is_zero()
+    }
+
+    /// Interval of the background revalidation. Half the smallest ttl,
+    /// to keep the used entries valid in between.
+    pub fn revalidation_interval(&self) -> Duration {
+        [self.attr_ttl, self.entry_ttl].into_iter()
+            .filter(|ttl| !ttl.is_zero())
+            .min().unwrap_or(Duration::from_secs(1)) / 2
+    }
+
+    pub async fn get_attrs(&self, ino: StorageIno) -> Option<InoAllAttributes> {
+        let cached = self.attrs.get(&ino).await?;
+        self.is_valid(&cached.validated, self.attr_ttl).then_some(cached.attrs)
+    }
+
+    pub async fn insert_attrs(
+        &self,
+        ino: StorageIno,
+        change_id: Option<InoChangeIterationId>,
+        attrs: InoAllAttributes,
+        fetch_start: Instant,
+    ) {
+        if self.attr_ttl.is_zero() {
+            return;
+        }
+        self.attrs.insert(ino, CachedAttrs {
+            attrs, change_id, validated: Validated::new(fetch_start),
+        }).await;
+    }
+
+    pub async fn get_entry(&self, parent: ParentStorageIno, name: &ByteString) -> Option<StorageIno> {
+        let cached = self.entries.get(&(parent, name.clone())).await?;
+        self.is_valid(&cached.validated, self.entry_ttl).then_some(cached.ino)
+    }
+
+    pub async fn insert_entry(
+        &self,
+        parent: ParentStorageIno,
+        name: ByteString,
+        ino: StorageIno,
+        parent_change_id: Option<InoChangeIterationId>,
+        fetch_start: Instant,
+    ) {
+        if self.entry_ttl.is_zero() {
+            return;
+        }
+        self.entries.insert((parent, name), CachedEntry {
+            ino, parent_change_id, validated: Validated::new(fetch_start),
+        }).await;
+    }
+
+    pub async fn invalidate_ino(&self, ino: StorageIno) {
+        self.attrs.remove(&ino).await;
+    }
+
+    /// Invalidates the entry and the attributes of the directory.
+    pub async fn invalidate_entry(&self, parent: ParentStorageIno, name: &ByteString) {
+        self.entries.remove(&(parent, name.clone())).await;
+        self.attrs.remove(&parent.0).await;
+    }
+
+    /// Used when change events got lost.
+    pub fn invalidate_all(&self) {
+        self.attrs.invalidate_all();
+        self.entries.invalidate_all();
+    }
+
+    /// Names of all cached directory entries.
+    pub fn cached_entries(&self) -> Vec<(ParentStorageIno, ByteString)> {
+        self.entries.iter().map(|(key, _)| key.as_ref().clone()).collect()
+    }
+
+    /// All inodes whose change iteration id is needed for the revalidation.
+    pub fn revalidation_inos(&self) -> Vec<StorageIno> {
+        let mut inos = self.attrs.iter().map(|(ino, _)| *ino).collect::<HashSet<_>>();
+        inos.extend(self.entries.iter().map(|(key, _)| key.0.0));
+        inos.into_iter().collect()
+    }
+
+    /// Keeps all entries whose id still matches and drops the others.
+    /// ids has to be read after revalidation_start.
+    pub async fn apply_revalidation(
+        &self,
+        ids: &HashMap<StorageIno, InoChangeIterationId>,
+        revalidation_start: Instant,
+    ) {
+        for (ino, cached) in self.attrs.iter() {
+            if ids.get(ino.as_ref()) == cached.change_id.as_ref() {
+                cached.validated.extend_to(revalidation_start);
+            } else {
+                self.attrs.remove(ino.as_ref()).await;
+            }
+        }
+        for (key, cached) in self.entries.iter() {
+            if ids.get(&key.0.0) == cached.parent_change_id.as_ref() {
+                cached.validated.extend_to(revalidation_start);
+            } else {
+                self.entries.remove(key.as_ref()).await;
+            }
+        }
+    }
+
+    /// Not needed while the change feed is active.
+    pub async fn revalidate(&self, hash_fs: &Arc<dyn HashFsInterface>) -> HashFsResult<()> {
+        if self.change_feed_active.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let revalidation_start = Instant::now();
+        let inos = self.revalidation_inos();
+        let mut ids = HashMap::new();
+        for chunk in inos.chunks(MAX_TIKV_SCAN_LIMIT as usize) {
+            ids.extend(hash_fs.inode_get_change_iteration_ids(chunk).await?);
+        }
+        self.apply_revalidation(&ids, revalidation_start).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::fs::inode::{InoAccessTime, InoDescription, InoSize, InoStorageFileAttr, StorageDirItemKind, StorageFilePermission};
+
+    use super::*;
+
+    fn attrs(ino: StorageIno) -> InoAllAttributes {
+        let now = SystemTime::now();
+        (
+            Arc::new(InoDescription { ino, typ: StorageDirItemKind::File, creation_time: now }),
+            Arc::new(InoStorageFileAttr {
+                perm: StorageFilePermission(0o644), uid: 0, gid: 0, rdev: 0, flags: 0, last_change: now,
+            }),
+            Arc::new(InoSize::new()),
+            InoAccessTime(now),
+        )
+    }
+
+    #[tokio::test]
+    async fn revalidation_drops_entries_with_changed_ids() {
+        let cache = MetaCache::new(100, Duration::from_secs(60), Duration::from_secs(60));
+        let (dir, unchanged, changed) = (StorageIno(1), StorageIno(2), StorageIno(3));
+        let old_id = InoChangeIterationId::random();
+        let start = Instant::now();
+        cache.insert_attrs(unchanged, Some(old_id.clone()), attrs(unchanged), start).await;
+        cache.insert_attrs(changed, Some(old_id.clone()), attrs(changed), start).await;
+        cache.insert_entry(ParentStorageIno(dir), "a".into(), unchanged, None, start).await;
+
+        let mut inos = cache.revalidation_inos();
+        inos.sort();
+        assert_eq!(inos, vec![dir, unchanged, changed]);
+
+        // the directory got its first id and the second file was modified:
+        let ids = HashMap::from([
+            (dir, InoChangeIterationId::random()),
+            (unchanged, old_id.clone()),
+            (changed, InoChangeIterationId::random()),
+        ]);
+        cache.apply_revalidation(&ids, Instant::now()).await;
+
+        assert!(cache.get_attrs(unchanged).await.is_some());
+        assert!(cache.get_attrs(changed).await.is_none());
+        assert_eq!(cache.get_entry(ParentStorageIno(dir), &"a".into()).await, None);
+    }
+
+    #[tokio::test]
+    async fn entries_stay_valid_while_the_change_feed_is_active() {
+        let cache = MetaCache::new(100, Duration::from_millis(50), Duration::from_millis(50));
+        let ino = StorageIno(2);
+        let old = Instant::now() - Duration::from_millis(100);
+        cache.insert_attrs(ino, None, attrs(ino), old).await;
+        assert!(cache.get_attrs(ino).await.is_none());
+
+        cache.set_change_feed_active(true);
+        assert!(cache.get_attrs(ino).await.is_some());
+        cache.invalidate_ino(ino).await;
+        assert!(cache.get_attrs(ino).await.is_none());
+    }
+}
@@ -2,7 +2,7 @@ use std::{any::type_name, collections::{BTreeMap, HashMap, HashSet}, fmt::Debug,
 
 use bytestring::ByteString;
 use num_bigint::BigUint;
-use num_traits::{FromPrimitive, Zero};
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
 use serde::{Deserialize, Serialize};
 use tikv_client::{transaction::Mutation, Key, KvPair, Transaction};
 use tokio::time::sleep;
@@ -10,15 +10,23 @@ use uuid::Uuid;
 
 use crate::fs::{inode::ParentStorageIno, meta::MetaMutable};
 
-use super::{dir::StorageDirectory, error::{FsError, TiFsResult}, inode::{DirectoryItem, InoChangeIterationId, InoFullHash, InoInlineData, TiFsHash}};
+use super::{dir::StorageDirectory, error::{FsError, TiFsResult}, inode::{DirectoryItem, InoAccessTime, InoChangeIterationId, InoFullHash, InoInlineData, InoMerkleHash, InoModificationTime, InoQuotaDirectory, TiFsHash}};
 use super::utils::txn_data_cache::{TxnDeleteMut, TxnFetchMut, TxnPutMut};
-use super::key::{BlockAddress, HashedBlockMeta, OPENED_INODE_PARENT_INODE};
-use super::hash_fs_interface::{BlockIndex, GotOrMade};
+use super::key::{BlockAddress, HashedBlockMeta, InoMetadata, OPENED_INODE_PARENT_INODE};
+use super::hash_fs_interface::{BlockIndex, DirectoryPage, GotOrMade, InoAllAttributes, RenameMode};
+use super::kv_parser::KvPairParser;
+use super::hashed_block::BlockReferenceCount;
 use super::fuse_to_hashfs::MAX_TIKV_SCAN_LIMIT;
 use super::index::{deserialize_json, serialize_json};
 use super::fs_config::TiFsConfig;
 use super::flexible_transaction::{FlexibleTransaction, SpinningTxn, TransactionError, TransactionResult};
 use super::meta::MetaStatic;
+use super::fs_stats::{BlockStatsDelta, FileStats};
+use super::quota::{QuotaLimits, QuotaReport, QuotaState, QuotaTarget};
+use super::scrubber::ScrubProgress;
+use super::usage::{random_usage_shard, UsageCounter, UsageDelta, USAGE_SHARDS};
+use super::change_log::{self, ChangeLogEntry, ChangeLogWatchLease, CHANGE_LOG_WATCH_LEASE};
+use super::utils::full_hash::{full_hash_segment_start, FullHashMode, FullHasher, FULL_HASH_SEGMENT_BLOCKS};
 use super::key::{KeyGenerator, ScopedKeyBuilder};
 use super::inode::{InoDescription, InoSize, StorageDirItem, StorageDirItemKind, InoStorageFileAttr, StorageFilePermission, StorageIno};
 
@@ -198,6 +206,10 @@ impl TransactionWithFsConfig {
         let initial_mutable_meta = MetaMutable {
             inode_next: crate::fs::key::FIRST_DATA_INODE.0,
             last_stat: None,
+            used_logical_bytes: 0,
+            used_physical_blocks: 0,
+            // the root inode is created right after this:
+            inode_count: 0,
         };
         self.put(&(), Arc::new(initial_mutable_meta)).await
     }
@@ -226,6 +238,348 @@ impl TransactionWithFsConfig {
         Ok(StorageIno(ino))
     }
 
+    /// The usage counters include the deltas of all shards.
+    pub async fn meta_mutable_read(&mut self) -> TiFsResult<Option<MetaMutable>> {
+        let read_meta: Option<MetaMutable> = self.fetch_try(&()).await?;
+        let Some(mut read_meta) = read_meta else {
+            return Ok(None);
+        };
+        let delta = self.usage_delta_read(UsageCounter::Fs).await?;
+        read_meta.apply_usage_delta(delta.logical_bytes, delta.physical_blocks, delta.inodes);
+        Ok(Some(read_meta))
+    }
+
+    /// Applies the changes to the usage counters within this transaction.
+    /// Fails with NoSpaceLeft if new physical blocks would exceed the configured max_size.
+    pub async fn meta_mutable_update_usage(
+        &mut self,
+        logical_bytes: i64,
+        physical_blocks: i64,
+        inodes: i64,
+    ) -> TiFsResult<()> {
+        if logical_bytes == 0 && physical_blocks == 0 && inodes == 0 {
+            return Ok(());
+        }
+
+        if let (Some(max_size), true) = (self.fs_config().max_size, physical_blocks > 0) {
+            if let Some(mut dyn_meta) = self.meta_mutable_read().await? {
+                dyn_meta.apply_usage_delta(logical_bytes, physical_blocks, inodes);
+                let used = dyn_meta.used_physical_blocks * self.fs_config().block_size;
+                if used > max_size {
+                    return Err(FsError::NoSpaceLeft(max_size));
+                }
+            }
+        }
+
+        self.usage_delta_add(UsageCounter::Fs,
+            UsageDelta::new(logical_bytes, physical_blocks, inodes)).await
+    }
+
+    /// Adds the delta to a random shard of the counter. Concurrent transactions
+    /// thereby rarely write the same key, unlike with the base value of the counter.
+    pub async fn usage_delta_add(&mut self, counter: UsageCounter, delta: UsageDelta) -> TiFsResult<()> {
+        let key = (counter, random_usage_shard());
+        let mut sum: UsageDelta = self.fetch_try(&key).await?.unwrap_or_default();
+        sum.add(&delta);
+        self.put(&key, Arc::new(sum)).await
+    }
+
+    /// Sum of the deltas of all shards of the counter.
+    pub async fn usage_delta_read(&mut self, counter: UsageCounter) -> TiFsResult<UsageDelta> {
+        let range = self.fs_config().key_builder().usage_delta_range(counter);
+        let mut sum = UsageDelta::default();
+        for KvPair(_k, v) in self.mini.scan(range, USAGE_SHARDS as u32).await? {
+            sum.add(&deserialize_json::<UsageDelta>(&v)?);
+        }
+        Ok(sum)
+    }
+
+    /// Adds the change of the block statistics to a random shard, see usage_delta_add.
+    pub async fn block_stats_delta_add(&mut self, delta: BlockStatsDelta) -> TiFsResult<()> {
+        if delta.is_empty() {
+            return Ok(());
+        }
+        let shard = random_usage_shard();
+        let mut sum: BlockStatsDelta = self.fetch_try(&shard).await?.unwrap_or_default();
+        sum.add(&delta);
+        self.put(&shard, Arc::new(sum)).await
+    }
+
+    /// Sum of the block statistics changes of all shards.
+    pub async fn block_stats_delta_read(&mut self) -> TiFsResult<BlockStatsDelta> {
+        let range = self.fs_config().key_builder().block_stats_delta_range();
+        let mut sum = BlockStatsDelta::default();
+        for KvPair(_k, v) in self.mini.scan(range, USAGE_SHARDS as u32).await? {
+            sum.add(&deserialize_json::<BlockStatsDelta>(&v)?);
+        }
+        Ok(sum)
+    }
+
+    /// Applies a change of the size or existence of ino to the global usage
+    /// counters and to the quotas the inode is charged to.
+    pub async fn inode_update_usage(
+        &mut self,
+        ino: StorageIno,
+        logical_bytes: i64,
+        inodes: i64,
+    ) -> TiFsResult<()> {
+        self.meta_mutable_update_usage(logical_bytes, 0, inodes).await?;
+        self.quota_update_usage(ino, logical_bytes, inodes).await
+    }
+
+    /// Charges the usage change to the owner, group and quota directory of ino.
+    /// Fails with QuotaExceeded if a growing usage exceeds one of the limits.
+    pub async fn quota_update_usage(
+        &mut self,
+        ino: StorageIno,
+        bytes: i64,
+        inodes: i64,
+    ) -> TiFsResult<()> {
+        if bytes == 0 && inodes == 0 {
+            return Ok(());
+        }
+
+        let mut targets = Vec::with_capacity(3);
+        let attr: Option<InoStorageFileAttr> = self.fetch_try(&ino).await?;
+        if let Some(attr) = attr {
+            targets.push((QuotaTarget::User(attr.uid), true));
+            targets.push((QuotaTarget::Group(attr.gid), true));
+        }
+        let quota_dir: Option<InoQuotaDirectory> = self.fetch_try(&ino).await?;
+        if let Some(InoQuotaDirectory(dir_ino)) = quota_dir {
+            targets.push((QuotaTarget::Directory(dir_ino), false));
+        }
+
+        for (target, create_missing) in targets {
+            let state: Option<QuotaState> = self.fetch_try(&target).await?;
+            let state = match state {
+                Some(state) => state,
+                // users and groups are always tracked, removed directory quotas are not:
+                None if create_missing => {
+                    let state = QuotaState::default();
+                    self.put(&target, Arc::new(state.clone())).await?;
+                    state
+                }
+                None => continue,
+            };
+            self.quota_add_usage(target, &state, bytes, inodes).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds the usage change as a delta to the quota with the given stored state.
+    /// Fails with QuotaExceeded if a growing usage exceeds one of the limits,
+    /// the limits are checked against the usage including all deltas.
+    async fn quota_add_usage(
+        &mut self,
+        target: QuotaTarget,
+        state: &QuotaState,
+        bytes: i64,
+        inodes: i64,
+    ) -> TiFsResult<()> {
+        let grows = bytes > 0 || inodes > 0;
+        let limited = state.limits.max_bytes.is_some() || state.limits.max_inodes.is_some();
+        if grows && limited {
+            let mut total = self.usage_delta_read(UsageCounter::Quota(target)).await?
+                .apply_to_quota(state.clone());
+            if !total.apply_usage_delta(bytes, inodes) {
+                return Err(FsError::QuotaExceeded { target: format!("{target:?}") });
+            }
+        }
+        self.usage_delta_add(UsageCounter::Quota(target), UsageDelta::new(bytes, 0, inodes)).await
+    }
+
+    /// Sets the limits of the quota and keeps its current usage.
+    /// Setting a directory quota marks the directory such that inodes
+    /// created below it are charged to it. The usage of existing children
+    /// is unknown, thus a new directory quota requires an empty directory.
+    pub async fn quota_set(
+        &mut self,
+        target: QuotaTarget,
+        limits: QuotaLimits,
+    ) -> TiFsResult<()> {
+        let prev_state: Option<QuotaState> = self.fetch_try(&target).await?;
+        let mut state = prev_state.clone().unwrap_or_default();
+        if let QuotaTarget::Directory(dir_ino) = target {
+            let desc: Arc<InoDescription> = self.fetch(&dir_ino).await?;
+            if desc.typ != StorageDirItemKind::Directory {
+                return Err(FsError::NotDirectory { file: format!("{dir_ino:?}") });
+            }
+            if prev_state.is_none()
+                && !self.directory_scan_for_children_after(dir_ino, None, 1).await?.is_empty() {
+                return Err(FsError::DirNotEmpty { dir: format!("{dir_ino:?}") });
+            }
+            let prev_dir: Option<InoQuotaDirectory> = self.fetch_try(&dir_ino).await?;
+            if prev_dir != Some(InoQuotaDirectory(dir_ino)) {
+                // the directory itself moves from the enclosing quota to its own one:
+                if let Some(InoQuotaDirectory(outer_ino)) = prev_dir {
+                    let outer = QuotaTarget::Directory(outer_ino);
+                    let outer_state: Option<QuotaState> = self.fetch_try(&outer).await?;
+                    if let Some(outer_state) = outer_state {
+                        self.quota_add_usage(outer, &outer_state, 0, -1).await?;
+                    }
+                }
+                self.put(&dir_ino, Arc::new(InoQuotaDirectory(dir_ino))).await?;
+            }
+            if prev_state.is_none() {
+                state.apply_usage_delta(0, 1);
+            }
+        }
+        state.limits = limits;
+        self.put(&target, Arc::new(state)).await
+    }
+
+    /// Moves the usage of ino to the quota directory of new_parent, after ino got
+    /// moved there. Directories with an own quota keep being charged to it.
+    /// The subtree of a directory is not walked, directories with children can
+    /// only move within the same quota and fail with CrossQuotaRename otherwise.
+    /// Fails with QuotaExceeded if the new quota is too small.
+    pub async fn quota_move_to_directory_of(
+        &mut self,
+        ino: StorageIno,
+        new_parent: ParentStorageIno,
+    ) -> TiFsResult<()> {
+        let from: Option<InoQuotaDirectory> = self.fetch_try(&ino).await?;
+        if from == Some(InoQuotaDirectory(ino)) {
+            return Ok(());
+        }
+        let to: Option<InoQuotaDirectory> = self.fetch_try(&new_parent.0).await?;
+        if from == to {
+            return Ok(());
+        }
+
+        let desc: Option<InoDescription> = self.fetch_try(&ino).await?;
+        if desc.map(|d| d.typ == StorageDirItemKind::Directory).unwrap_or(false)
+            && !self.directory_scan_for_children_after(ino, None, 1).await?.is_empty() {
+            return Err(FsError::CrossQuotaRename);
+        }
+
+        let size: Option<InoSize> = self.fetch_try(&ino).await?;
+        let bytes = size.map(|s| s.size() as i64).unwrap_or(0);
+        if let Some(to) = &to {
+            self.put(&ino, Arc::new(to.clone())).await?;
+        } else {
+            TxnDeleteMut::<StorageIno, InoQuotaDirectory>::delete(self, &ino).await?;
+        }
+
+        if let Some(InoQuotaDirectory(from_ino)) = from {
+            let from = QuotaTarget::Directory(from_ino);
+            let from_state: Option<QuotaState> = self.fetch_try(&from).await?;
+            if let Some(from_state) = from_state {
+                self.quota_add_usage(from, &from_state, -bytes, -1).await?;
+            }
+        }
+        if let Some(InoQuotaDirectory(to_ino)) = to {
+            let to = QuotaTarget::Directory(to_ino);
+            let to_state: Option<QuotaState> = self.fetch_try(&to).await?;
+            if let Some(to_state) = to_state {
+                self.quota_add_usage(to, &to_state, bytes, 1).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The usage includes the deltas of all shards.
+    pub async fn quota_read(&mut self, target: QuotaTarget) -> TiFsResult<Option<QuotaState>> {
+        let state: Option<QuotaState> = self.fetch_try(&target).await?;
+        let Some(state) = state else {
+            return Ok(None);
+        };
+        Ok(Some(self.usage_delta_read(UsageCounter::Quota(target)).await?.apply_to_quota(state)))
+    }
+
+    pub async fn quota_scan(&mut self, limit: u32) -> TiFsResult<Vec<QuotaReport>> {
+        let range = self.fs_config().key_builder().quota_range();
+        let data = self.mini.scan(range, limit).await?.collect::<Vec<_>>();
+        let mut result = Vec::with_capacity(data.len());
+        for KvPair(k, v) in data {
+            let target = self.fs_config().key_parser_b(k)?.parse_quota_target()?;
+            let state = deserialize_json::<QuotaState>(&v)?;
+            let state = self.usage_delta_read(UsageCounter::Quota(target)).await?.apply_to_quota(state);
+            result.push(QuotaReport { target, state });
+        }
+        Ok(result)
+    }
+
+    /// Changes owner and/or group of ino and moves its usage to the new user and group quotas.
+    pub async fn inode_change_owner(
+        &mut self,
+        ino: StorageIno,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> TiFsResult<()> {
+        let mut attr = TxnFetchMut::<StorageIno, InoStorageFileAttr>::fetch(
+            self, &ino).await?.deref().clone();
+        let new_uid = uid.unwrap_or(attr.uid);
+        let new_gid = gid.unwrap_or(attr.gid);
+        if new_uid == attr.uid && new_gid == attr.gid {
+            return Ok(());
+        }
+
+        let size = TxnFetchMut::<StorageIno, InoSize>::fetch_try(self, &ino).await?
+            .map(|s|s.size()).unwrap_or(0) as i64;
+        let mut moves = Vec::with_capacity(2);
+        if new_uid != attr.uid {
+            moves.push((QuotaTarget::User(attr.uid), QuotaTarget::User(new_uid)));
+        }
+        if new_gid != attr.gid {
+            moves.push((QuotaTarget::Group(attr.gid), QuotaTarget::Group(new_gid)));
+        }
+        for (from, to) in moves {
+            let from_state: QuotaState = self.fetch_try(&from).await?.unwrap_or_default();
+            self.quota_add_usage(from, &from_state, -size, -1).await?;
+            let to_state: Option<QuotaState> = self.fetch_try(&to).await?;
+            let to_state = match to_state {
+                Some(state) => state,
+                None => {
+                    let state = QuotaState::default();
+                    self.put(&to, Arc::new(state.clone())).await?;
+                    state
+                }
+            };
+            self.quota_add_usage(to, &to_state, size, 1).await?;
+        }
+
+        attr.uid = new_uid;
+        attr.gid = new_gid;
+        attr.last_change = SystemTime::now();
+        self.put(&ino, Arc::new(attr)).await
+    }
+
+    /// Writes the given attributes of ino. An owner change moves the usage
+    /// to the quotas of the new owner within the same transaction.
+    pub async fn inode_set_attributes(
+        &mut self,
+        ino: StorageIno,
+        perm: Option<StorageFilePermission>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        atime: Option<SystemTime>,
+        mtime: Option<SystemTime>,
+        ctime: SystemTime,
+        flags: Option<u32>,
+    ) -> TiFsResult<()> {
+        if let Some(atime) = atime {
+            self.put(&ino, Arc::new(InoAccessTime(atime))).await?;
+        }
+        if let Some(mtime) = mtime {
+            self.put(&ino, Arc::new(InoModificationTime(mtime))).await?;
+        }
+        if uid.is_some() || gid.is_some() {
+            self.inode_change_owner(ino, uid, gid).await?;
+        }
+
+        let mut attr = TxnFetchMut::<StorageIno, InoStorageFileAttr>::fetch(
+            self, &ino).await?.deref().clone();
+        attr.perm = perm.unwrap_or(attr.perm);
+        attr.flags = flags.unwrap_or(attr.flags);
+        attr.last_change = ctime;
+        self.put(&ino, Arc::new(attr)).await?;
+
+        // metadata caches of other mounts revalidate with this id:
+        self.inode_touch_change_iteration_id(ino).await
+    }
+
     pub async fn directory_get_child(&mut self, parent: ParentStorageIno, name: ByteString
     ) -> TiFsResult<Option<StorageDirItem>> {
         tracing::trace!("get dir item. parent {:?}, name {:?}", parent, name);
@@ -234,7 +588,17 @@ impl TransactionWithFsConfig {
 
     pub async fn directory_scan_for_children(&mut self, dir_ino: StorageIno, limit: u32
     ) -> TiFsResult<Vec<DirectoryItem>> {
-        let range = self.fs_config().key_builder().directory_child_range(dir_ino);
+        self.directory_scan_for_children_after(dir_ino, None, limit).await
+    }
+
+    pub async fn directory_scan_for_children_after(
+        &mut self,
+        dir_ino: StorageIno,
+        start_after: Option<&[u8]>,
+        limit: u32,
+    ) -> TiFsResult<Vec<DirectoryItem>> {
+        let range = self.fs_config().key_builder()
+            .directory_child_range_after(dir_ino, start_after);
         let data = self.mini.scan(range, limit).await?.collect::<Vec<_>>();
         let mut result = StorageDirectory::with_capacity(data.len());
 //        data.iter().enumerate().map(|(i, KvPair(k,v))|{
@@ -254,6 +618,66 @@ impl TransactionWithFsConfig {
         Ok(result)
     }
 
+    /// Page of children and the cursor of the next page. The cursor is derived
+    /// from the scanned children, before any of them are filtered by the caller.
+    pub async fn directory_scan_for_children_page(
+        &mut self,
+        dir_ino: StorageIno,
+        start_after: Option<&[u8]>,
+        limit: u32,
+    ) -> TiFsResult<DirectoryPage<DirectoryItem>> {
+        let children = self.directory_scan_for_children_after(
+            dir_ino, start_after, limit).await?;
+        let next_cursor = if children.len() >= limit as usize {
+            children.last().map(|child| ByteString::from(child.name.clone()))
+        } else { None };
+        Ok((children, next_cursor))
+    }
+
+    pub async fn directory_scan_for_children_with_attributes(
+        &mut self,
+        dir_ino: StorageIno,
+        start_after: Option<&[u8]>,
+        limit: u32,
+    ) -> TiFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>> {
+        let (children, next_cursor) = self.directory_scan_for_children_page(
+            dir_ino, start_after, limit).await?;
+        let metas = [
+            InoMetadata::Description,
+            InoMetadata::UnixAttributes,
+            InoMetadata::Size,
+            InoMetadata::AccessTime,
+        ];
+        let mut keys = Vec::with_capacity(children.len() * metas.len());
+        for child in &children {
+            for meta in metas {
+                keys.push(Key::from(self.fs_config().key_builder().inode_x(child.ino, meta).buf));
+            }
+        }
+        let data = self.mini.batch_get(keys).await?.collect::<Vec<_>>();
+        let kv_parser = KvPairParser{fs_config: self.fs_config().clone()};
+        let maps = kv_parser.parse_inode_attrs_kv_pairs(data)?;
+
+        let mut result = Vec::with_capacity(children.len());
+        for child in children {
+            let (Some(desc), Some(attr), Some(size)) = (
+                maps.descriptions.get(&child.ino),
+                maps.attrs.get(&child.ino),
+                maps.size.get(&child.ino),
+            ) else {
+                tracing::warn!("child without attributes: {child:?}");
+                result.push((child, None));
+                continue;
+            };
+            let atime = maps.access_times.get(&child.ino).cloned().unwrap_or_else(||{
+                InoAccessTime(size.last_change.max(attr.last_change))
+            });
+            result.push((child, Some((
+                Arc::new(desc.clone()), Arc::new(attr.clone()), Arc::new(size.clone()), atime))));
+        }
+        Ok((result, next_cursor))
+    }
+
     pub async fn directory_add_child_link_unchecked(
         &mut self,
         parent: ParentStorageIno,
@@ -262,9 +686,15 @@ impl TransactionWithFsConfig {
     ) -> TiFsResult<()> {
         self.put(&(item.ino, parent, name.as_bytes().deref()), Arc::new(())).await?;
         self.put(&(parent, name.as_bytes().deref()), item).await?;
+        self.inode_touch_change_iteration_id(parent.0).await?;
         Ok(())
     }
 
+    /// Publishes a modification of the inode to the metadata caches of all mounts.
+    pub async fn inode_touch_change_iteration_id(&mut self, ino: StorageIno) -> TiFsResult<()> {
+        self.put(&ino, Arc::new(InoChangeIterationId::random())).await
+    }
+
     pub async fn directory_add_child_checked_existing_inode(
         &mut self,
         parent: ParentStorageIno,
@@ -327,6 +757,7 @@ impl TransactionWithFsConfig {
         let item = Arc::new(StorageDirItem { ino: new_ino, typ });
         self.put(&(parent, name.as_bytes().deref()), item.clone()).await?;
         self.put(&(new_ino, parent, name.as_bytes().deref()), Arc::new(())).await?;
+        self.inode_touch_change_iteration_id(parent.0).await?;
 
         if let Some(data) = inline_data {
             self.put(&new_ino, Arc::new(InoInlineData{
@@ -335,6 +766,13 @@ impl TransactionWithFsConfig {
             })).await?;
         }
 
+        let quota_dir: Option<InoQuotaDirectory> = self.fetch_try(&parent.0).await?;
+        if let Some(quota_dir) = quota_dir {
+            self.put(&new_ino, Arc::new(quota_dir)).await?;
+        }
+
+        self.inode_update_usage(new_ino, 0, 1).await?;
+
         Ok(GotOrMade::NewlyCreated(item.deref().clone()))
     }
 
@@ -351,7 +789,8 @@ impl TransactionWithFsConfig {
         let mut mutations = Vec::with_capacity(2);
         mutations.push(Mutation::Delete(key_parent_to_child));
         mutations.push(Mutation::Delete(key_child_to_parent));
-        Ok(self.mini.batch_mutate(mutations).await?)
+        self.mini.batch_mutate(mutations).await?;
+        self.inode_touch_change_iteration_id(parent.0).await
     }
 
     pub async fn directory_remove_child(
@@ -397,24 +836,97 @@ impl TransactionWithFsConfig {
         Ok(child_item)
     }
 
+    /// Renames a directory entry within this transaction. With RenameMode::Replace,
+    /// an existing target is replaced atomically. The replaced item is returned
+    /// in this case, as its inode might need to be deleted by the caller.
     pub async fn directory_rename_child(
         &mut self,
         parent: ParentStorageIno,
         name: ByteString,
         new_parent: ParentStorageIno,
         new_name: ByteString,
-    ) -> TiFsResult<()> {
+        mode: RenameMode,
+    ) -> TiFsResult<Option<StorageDirItem>> {
+        let no_replace = mode == RenameMode::NoReplace;
+        let exchange = mode == RenameMode::Exchange;
+
         let dir_item = self.directory_get_child(parent, name.clone()).await?.ok_or(
-            FsError::FileNotFound { file: name.clone().into() }
+            FsError::FileNotFound { file: name.to_string() }
         )?;
-        self.directory_add_child_checked_existing_inode(
-            new_parent, new_name, dir_item.ino).await?;
-        self.directory_remove_child(
-            parent,
-            name,
-            &HashSet::from([dir_item.typ])
-        ).await?;
-        Ok(())
+
+        if (parent == new_parent) && (name == new_name) {
+            return if no_replace {
+                Err(FsError::FileExist { file: new_name.to_string() })
+            } else {
+                Ok(None)
+            };
+        }
+
+        let existing = self.directory_get_child(new_parent, new_name.clone()).await?;
+
+        if exchange {
+            let Some(existing) = existing else {
+                return Err(FsError::FileNotFound { file: new_name.to_string() });
+            };
+            self.directory_remove_child_links_unchecked(
+                parent, name.clone(), dir_item.ino).await?;
+            self.directory_remove_child_links_unchecked(
+                new_parent, new_name.clone(), existing.ino).await?;
+            let (item_ino, existing_ino) = (dir_item.ino, existing.ino);
+            self.directory_add_child_link_unchecked(
+                new_parent, new_name, Arc::new(dir_item)).await?;
+            self.directory_add_child_link_unchecked(
+                parent, name, Arc::new(existing)).await?;
+            if parent != new_parent {
+                self.quota_move_to_directory_of(item_ino, new_parent).await?;
+                self.quota_move_to_directory_of(existing_ino, parent).await?;
+            }
+            return Ok(None);
+        }
+
+        let replaced = if let Some(existing) = existing {
+            if no_replace {
+                return Err(FsError::FileExist { file: new_name.to_string() });
+            }
+
+            if existing.ino == dir_item.ino {
+                // both names are links to the same inode: posix says do nothing
+                return Ok(None);
+            }
+
+            match (dir_item.typ, existing.typ) {
+                (StorageDirItemKind::Directory, StorageDirItemKind::Directory) => {
+                    let children = self.directory_scan_for_children(
+                        existing.ino, 1 /* just check if its empty */).await?;
+                    if children.len() > 0 {
+                        return Err(FsError::DirNotEmpty { dir: new_name.to_string() });
+                    }
+                }
+                (StorageDirItemKind::Directory, _) => {
+                    return Err(FsError::NotDirectory { file: new_name.to_string() });
+                }
+                (_, StorageDirItemKind::Directory) => {
+                    return Err(FsError::IsDirectory { file: new_name.to_string() });
+                }
+                _ => {}
+            }
+
+            self.directory_remove_child_links_unchecked(
+                new_parent, new_name.clone(), existing.ino).await?;
+            Some(existing)
+        } else {
+            None
+        };
+
+        let item_ino = dir_item.ino;
+        self.directory_remove_child_links_unchecked(
+            parent, name, dir_item.ino).await?;
+        self.directory_add_child_link_unchecked(
+            new_parent, new_name, Arc::new(dir_item)).await?;
+        if parent != new_parent {
+            self.quota_move_to_directory_of(item_ino, new_parent).await?;
+        }
+        Ok(replaced)
     }
 
     pub async fn inode_check_for_delete_and_delete_atomically_description(
@@ -425,7 +937,11 @@ impl TransactionWithFsConfig {
         let key_usages = self.fs_config().key_builder().parent_link_scan(ino);
         let usage_count = self.mini.scan_keys(key_usages, MAX_TIKV_SCAN_LIMIT).await?.count();
         if usage_count == 0 {
+            let size = TxnFetchMut::<StorageIno, InoSize>::fetch_try(self, &ino).await?
+                .map(|s|s.size()).unwrap_or(0);
             TxnDeleteMut::<StorageIno, InoDescription>::delete(self, &ino).await?;
+            self.inode_touch_change_iteration_id(ino).await?;
+            self.inode_update_usage(ino, -(size as i64), -1).await?;
             Ok(DeletionCheckResult::DeletedInoDesc)
         } else {
             Ok(DeletionCheckResult::StillUsed)
@@ -452,11 +968,13 @@ impl TransactionWithFsConfig {
         Ok(())
     }
 
+    /// Returns true if this was the last use of ino and the full hash got
+    /// invalidated by a write, such that it should be computed again.
     pub async fn inode_close(
         &mut self,
         ino: StorageIno,
         use_id: Uuid,
-    ) -> TiFsResult<()> {
+    ) -> TiFsResult<bool> {
         // remove published opened state
         let used_id_str = use_id.to_string();
         let result = self.directory_remove_child(
@@ -465,14 +983,53 @@ impl TransactionWithFsConfig {
             &HashSet::from([
                 StorageDirItemKind::Directory,
                 StorageDirItemKind::File,
-                StorageDirItemKind::Symlink
+                StorageDirItemKind::Symlink,
+                StorageDirItemKind::CharDevice,
+                StorageDirItemKind::BlockDevice,
+                StorageDirItemKind::NamedPipe,
+                StorageDirItemKind::Socket,
             ]),
         ).await?;
         if result.ino != ino {
             // return err -> force rollback (un-delete)
             return Err(FsError::CloseFailedDueToWrongInoOrUseId);
         }
-        Ok(())
+
+        let full_hash: Option<InoFullHash> = self.fetch_try(&ino).await?;
+        if full_hash.map(|h| h.is_valid_for(self.fs_config().full_hash_mode)).unwrap_or(false) {
+            return Ok(false);
+        }
+        let other_uses = self.fs_config().key_builder()
+            .parent_link_scan_of_parent(ino, OPENED_INODE_PARENT_INODE);
+        Ok(self.mini.scan_keys(other_uses, 1).await?.next().is_none())
+    }
+
+    /// Stored hash of the file content in the mode if it is still valid, together with
+    /// the change iteration and the size (None if the file doesn't exist) to compute it for.
+    pub async fn inode_stored_content_hash(
+        &mut self,
+        ino: StorageIno,
+        mode: FullHashMode,
+    ) -> TiFsResult<(Option<TiFsHash>, InoChangeIterationId, Option<InoSize>)> {
+        let change_iteration: Option<InoChangeIterationId> = self.fetch_try(&ino).await?;
+        let change_iteration = change_iteration.unwrap_or_default();
+        let size: Option<InoSize> = self.fetch_try(&ino).await?;
+        // entries that don't parse are recomputed:
+        let hash = if mode == self.fs_config().full_hash_mode {
+            let key = self.fs_config().key_builder().inode_x(ino, InoMetadata::FullHash).buf;
+            self.mini.get(key).await?
+                .and_then(|v| deserialize_json::<InoFullHash>(&v).ok())
+                .filter(|h| h.is_valid_for(mode))
+                .map(|h| h.hash)
+        } else {
+            debug_assert_eq!(mode, FullHashMode::MerkleTree);
+            let key = self.fs_config().key_builder().inode_x(ino, InoMetadata::MerkleHash).buf;
+            self.mini.get(key).await?
+                .and_then(|v| deserialize_json::<InoMerkleHash>(&v).ok())
+                .filter(|h| h.change_iteration == change_iteration)
+                .map(|h| h.hash)
+        };
+        Ok((hash, change_iteration, size))
     }
 
     pub async fn checked_write_of_file_hash(
@@ -480,11 +1037,19 @@ impl TransactionWithFsConfig {
         ino: &StorageIno,
         change_iteration: &InoChangeIterationId,
         new_full_hash: TiFsHash,
+        mode: FullHashMode,
     ) -> TiFsResult<bool> {
         let ino_change_id: Arc<InoChangeIterationId> = self.fetch(ino).await?;
         // was data changed in the meantime?
         if ino_change_id.as_ref() == change_iteration {
-            self.put(ino, Arc::new(InoFullHash(new_full_hash))).await?;
+            if mode == self.fs_config().full_hash_mode {
+                self.put(ino, Arc::new(InoFullHash::new(new_full_hash, mode))).await?;
+            } else {
+                self.put(ino, Arc::new(InoMerkleHash {
+                    hash: new_full_hash,
+                    change_iteration: change_iteration.clone(),
+                })).await?;
+            }
             Ok(true)
         } else {
             Ok(false)
@@ -504,9 +1069,12 @@ impl TransactionWithFsConfig {
             return Ok(());
         }
 
+        let growth = target_size - ino_size.size();
         ino_size.set_size(target_size, self.fs_config().block_size);
         ino_size.last_change = SystemTime::now();
         self.put(&ino, Arc::new(ino_size)).await?;
+        self.inode_touch_change_iteration_id(ino).await?;
+        self.inode_update_usage(ino, growth as i64, 0).await?;
         Ok(())
     }
 
@@ -522,26 +1090,87 @@ impl TransactionWithFsConfig {
                 None).into()
         }).collect::<Vec<Key>>();
 
-        let prev_counter_values = self.mini.batch_get_for_update(keys).await?
+        let prev_counters = self.mini.batch_get_for_update(keys).await?
             .into_iter().filter_map(|KvPair(k,v)|{
                 let kp = self.fs_config().key_parser_b(k).ok()?;
                 let hash = kp.parse_hash().ok()?.1;
-                let cnt = BigUint::from_bytes_be(&v);
-                Some((hash, cnt))
+                Some((hash, BlockReferenceCount::from_bytes(&v)))
             }).collect::<HashMap<_,_>>();
 
-        let new_counter_values = blocks.iter().map(|(h,c)|{
-            let new_cnt = c + prev_counter_values
-                .get(*h).cloned().unwrap_or(BigUint::zero());
+        // quarantined blocks are reported as new, such that the caller uploads them again:
+        let flagged = prev_counters.iter()
+            .filter(|(_h, c)| c.quarantined && !c.count.is_zero()).map(|(h, _c)| h);
+        let quarantined = self.hb_quarantined_blocks_without_data(flagged).await?;
+
+        let mut increments = HashMap::<&TiFsHash, u64>::new();
+        for (h, c) in blocks {
+            *increments.entry(*h).or_default() += c;
+        }
+        let mut stats = BlockStatsDelta::default();
+        let new_counter_values = increments.iter().map(|(h,c)|{
+            let prev_cnt = prev_counters
+                .get(*h).map(|c| c.count.clone()).unwrap_or(BigUint::zero());
+            let new_cnt = BlockReferenceCount {
+                count: &prev_cnt + *c,
+                quarantined: quarantined.contains(*h),
+            };
+            stats.add_count_change(
+                prev_cnt.to_u64().unwrap_or(u64::MAX), new_cnt.count.to_u64().unwrap_or(u64::MAX));
             let key: Key = self.fs_config().key_builder().named_hashed_block_x(
                 h,
                 Some(super::key::HashedBlockMeta::CCountedNamedUsages),
                 None).into();
-            Mutation::Put(key, new_cnt.to_bytes_be())
+            Mutation::Put(key, new_cnt.to_bytes())
         }).collect::<Vec<_>>();
 
         self.mini.batch_mutate(new_counter_values).await?;
-        Ok(prev_counter_values)
+        self.block_stats_delta_add(stats).await?;
+
+        let new_blocks = increments.keys().filter(|h|{
+            prev_counters.get(**h).map(|c|c.count.is_zero()).unwrap_or(true)
+        }).count();
+        self.meta_mutable_update_usage(0, new_blocks as i64, 0).await?;
+
+        Ok(prev_counters.into_iter().map(|(h, c)|{
+            let count = if quarantined.contains(&h) { BigUint::zero() } else { c.count };
+            (h, count)
+        }).collect())
+    }
+
+    /// Returns the blocks that have no references or whose data is in the quarantine.
+    /// The data of these blocks needs to be uploaded before they can be referenced.
+    pub async fn hb_get_blocks_without_data<'fl>(
+        &mut self,
+        hashes: impl Iterator<Item = &'fl TiFsHash> + Clone,
+    ) -> TiFsResult<Vec<TiFsHash>> {
+        let counters = self.hb_get_reference_counters(hashes.clone()).await?;
+        let flagged = counters.iter()
+            .filter(|(_h, c)| c.quarantined && !c.count.is_zero()).map(|(h, _c)| h);
+        let quarantined = self.hb_quarantined_blocks_without_data(flagged).await?;
+        Ok(hashes.filter(|h| {
+            counters.get(*h).map(|c| c.count.is_zero()).unwrap_or(true) || quarantined.contains(*h)
+        }).cloned().collect())
+    }
+
+    /// Returns the blocks of the given quarantined ones (flagged in their reference counter)
+    /// whose data wasn't uploaded again.
+    pub async fn hb_quarantined_blocks_without_data<'fl>(
+        &mut self,
+        flagged: impl Iterator<Item = &'fl TiFsHash>,
+    ) -> TiFsResult<HashSet<TiFsHash>> {
+        let quarantined = flagged.cloned().collect::<Vec<_>>();
+        if quarantined.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let data_keys = quarantined.iter().map(|h|{
+            Key::from(self.fs_config().key_builder().hashed_block(h))
+        }).collect::<Vec<_>>();
+        let repaired = self.mini.batch_get(data_keys).await?
+            .filter_map(|KvPair(key, _value)|{
+                self.fs_config().key_parser_b(key).ok()?.parse_key_hashed_block().ok()
+            }).collect::<HashSet<_>>();
+        Ok(quarantined.into_iter().filter(|h| !repaired.contains(h)).collect())
     }
 
     pub async fn hb_replace_block_hash_for_address_no_size_update(
@@ -562,6 +1191,12 @@ impl TransactionWithFsConfig {
             }
             key
         }).collect::<Vec<_>>();
+        let segments = addresses.iter().map(|(addr, _)|{
+            BlockAddress { ino: addr.ino, index: BlockIndex(full_hash_segment_start(addr.index.0)) }
+        }).collect::<HashSet<_>>();
+        mutations.extend(segments.into_iter().map(|addr|{
+            Mutation::Delete(self.fs_config().key_builder().full_hash_segment(addr).into())
+        }));
         let prev_block_hash = self.mini.batch_get(keys).await?;
         self.mini.batch_mutate(mutations).await?;
 
@@ -578,32 +1213,67 @@ impl TransactionWithFsConfig {
         Ok(decrements)
     }
 
+    /// Maps the blocks of ino and grows the file to at least new_target_size.
+    /// The size update and its quota check happen together with the mapping
+    /// change, thus a failure leaves the previous mappings in place.
+    /// Returns the reference count decrements of the replaced blocks.
+    pub async fn hb_replace_block_hash_for_address_and_size_update(
+        &mut self,
+        ino: StorageIno,
+        addresses: &[(BlockAddress, Option<&TiFsHash>)],
+        new_target_size: u64,
+    ) -> TiFsResult<HashMap<TiFsHash, u64>> {
+        let decrements = self.hb_replace_block_hash_for_address_no_size_update(
+            addresses).await?;
+
+        // addresses that had no block hash before are new mappings (former holes):
+        let previously_mapped = decrements.values().sum::<u64>();
+        let added_mappings = (addresses.len() as u64).saturating_sub(previously_mapped);
+        self.hb_replace_block_hash_for_address_only_size_update_b(
+            ino, new_target_size, added_mappings, 0).await?;
+        Ok(decrements)
+    }
+
     pub async fn hb_replace_block_hash_for_address_only_size_update(
         &mut self,
         addr: &BlockAddress,
-        new_blocks_actual_size: u64
+        new_blocks_actual_size: u64,
+        removed_mappings: u64,
     ) -> TiFsResult<()> {
         let ino_size_arc: Arc<InoSize> = self.fetch(&addr.ino).await?;
         let mut ino_size = ino_size_arc.deref().clone();
-        let was_changed = ino_size.block_write_size_update(
+        let mut was_changed = ino_size.block_write_size_update(
             addr, self.fs_config().block_size, new_blocks_actual_size);
+        let growth = ino_size.size() - ino_size_arc.size();
+        was_changed |= ino_size.update_mapped_blocks(0, removed_mappings);
         if was_changed {
             self.put(&addr.ino, Arc::new(ino_size)).await?;
         }
+        self.inode_update_usage(addr.ino, growth as i64, 0).await?;
         Ok(())
     }
 
     pub async fn hb_replace_block_hash_for_address_only_size_update_b(
         &mut self,
         ino: StorageIno,
-        new_target_size: u64
+        new_target_size: u64,
+        added_mappings: u64,
+        removed_mappings: u64,
     ) -> TiFsResult<()> {
         let ino_size_arc: Arc<InoSize> = self.fetch(&ino).await?;
         let mut ino_size = ino_size_arc.deref().clone();
+        let mut was_changed = false;
+        let mut growth = 0;
         if new_target_size > ino_size.size {
+            growth = new_target_size - ino_size.size;
             ino_size.set_size(new_target_size, self.fs_config().block_size);
+            was_changed = true;
+        }
+        was_changed |= ino_size.update_mapped_blocks(added_mappings, removed_mappings);
+        if was_changed {
             self.put(&ino, Arc::new(ino_size)).await?;
         }
+        self.inode_update_usage(ino, growth as i64, 0).await?;
         Ok(())
     }
 
@@ -617,36 +1287,52 @@ impl TransactionWithFsConfig {
             key
         }).collect::<Vec<_>>();
 
-        let prev_counter_values = self.mini.batch_get(block_ref_cnt_keys.clone()).await?
+        let prev_counters = self.mini.batch_get(block_ref_cnt_keys.clone()).await?
             .filter_map(|KvPair(key, value)|{
-                let cnt = BigUint::from_bytes_be(&value);
                 let hash = self.fs_config().key_parser_b(key).ok()?.parse_hash().ok()?.1;
-                Some((hash, cnt))
+                Some((hash, BlockReferenceCount::from_bytes(&value)))
             }).collect::<HashMap<_,_>>();
 
         let mut mutations = Vec::new();
+        let mut deleted_blocks = 0;
+        let mut stats = BlockStatsDelta::default();
         for (h, dec) in decrements {
             let mut actual_dec = BigUint::from_u64(*dec).unwrap();
-            let prev_counter_value = prev_counter_values.get(*h).cloned().unwrap_or(BigUint::ZERO);
+            let prev_counter = prev_counters.get(*h).cloned().unwrap_or_default();
+            let prev_counter_value = prev_counter.count;
             if prev_counter_value < actual_dec {
                 tracing::error!("full decrement by {actual_dec} of block reference counter not possible with value {prev_counter_value}.");
                 actual_dec = prev_counter_value.clone();
             }
             let new_counter_value = prev_counter_value.clone() - actual_dec;
+            stats.add_count_change(
+                prev_counter_value.to_u64().unwrap_or(u64::MAX),
+                new_counter_value.to_u64().unwrap_or(u64::MAX));
 
             let key = Key::from(self.fs_config().key_builder().named_hashed_block_x(
                 h, Some(HashedBlockMeta::CCountedNamedUsages), None));
 
             if new_counter_value == BigUint::from_u8(0).unwrap() {
                 let block_key = self.fs_config().key_builder().hashed_block(h);
+                let len_key = self.fs_config().key_builder().hashed_block_data_length(h);
                 mutations.push(Mutation::Delete(Key::from(key)));
                 mutations.push(Mutation::Delete(Key::from(block_key)));
+                mutations.push(Mutation::Delete(Key::from(len_key)));
                 tracing::warn!("deleting block with hash: {h:?}");
+                if !prev_counter_value.is_zero() {
+                    deleted_blocks += 1;
+                }
             } else {
-                mutations.push(Mutation::Put(key.clone(), new_counter_value.to_bytes_be()));
+                let new_counter = BlockReferenceCount {
+                    count: new_counter_value,
+                    quarantined: prev_counter.quarantined,
+                };
+                mutations.push(Mutation::Put(key.clone(), new_counter.to_bytes()));
             }
         }
         self.mini.batch_mutate(mutations).await?;
+        self.block_stats_delta_add(stats).await?;
+        self.meta_mutable_update_usage(0, -deleted_blocks, 0).await?;
 
         Ok(())
     }
@@ -701,6 +1387,518 @@ impl TransactionWithFsConfig {
         tracing::trace!("result: len:{}", result.len());
         Ok(result)
     }
+
+    /// Removes the block hash assignments of the block range and returns the reference count
+    /// decrements that are needed for the removed hashes.
+    /// A collapse continues with hb_collapse_blocks_chunk.
+    pub async fn hb_remove_block_range(
+        &mut self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+    ) -> TiFsResult<HashMap<TiFsHash, u64>> {
+        let mut ino_size = TxnFetchMut::<StorageIno, InoSize>::fetch(
+            self, &ino).await?.deref().clone();
+
+        let removed = self.hb_get_block_hash_list_by_block_range_chunked(
+            ino, block_range.clone()).await?;
+
+        let mut mutations = Vec::with_capacity(removed.len());
+        let mut decrements = HashMap::<TiFsHash, u64>::new();
+        for (index, hash) in removed.iter() {
+            let key = Key::from(self.fs_config().key_builder().block_hash(
+                BlockAddress { ino, index: *index }));
+            mutations.push(Mutation::Delete(key));
+            *decrements.entry(hash.clone()).or_default() += 1;
+        }
+        self.mini.batch_mutate(mutations).await?;
+
+        ino_size.update_mapped_blocks(0, removed.len() as u64);
+        ino_size.last_change = SystemTime::now();
+        self.put(&ino, Arc::new(ino_size)).await?;
+        self.inode_invalidate_content_hashes(ino, block_range).await?;
+
+        Ok(decrements)
+    }
+
+    /// Moves the block hash assignments of up to `limit` blocks starting at `from`
+    /// down by `shift` blocks, to close the gap left by hb_remove_block_range.
+    /// Returns where the next chunk starts. Once the end of the file is reached,
+    /// the inode size is reduced by the shift and None is returned.
+    pub async fn hb_collapse_blocks_chunk(
+        &mut self,
+        ino: StorageIno,
+        from: BlockIndex,
+        shift: u64,
+        limit: u64,
+    ) -> TiFsResult<Option<BlockIndex>> {
+        let block_size = self.fs_config().block_size;
+        let mut ino_size = TxnFetchMut::<StorageIno, InoSize>::fetch(
+            self, &ino).await?.deref().clone();
+        let end_of_file = BlockIndex(ino_size.size().div_ceil(block_size));
+
+        if from >= end_of_file {
+            let new_size = ino_size.size().saturating_sub(shift * block_size);
+            let shrink = ino_size.size() - new_size;
+            ino_size.set_size(new_size, block_size);
+            ino_size.last_change = SystemTime::now();
+            self.put(&ino, Arc::new(ino_size)).await?;
+            self.inode_invalidate_content_hashes(ino, from..from).await?;
+            self.inode_update_usage(ino, -(shrink as i64), 0).await?;
+            return Ok(None);
+        }
+
+        let to = BlockIndex(from.0.saturating_add(limit)).min(end_of_file);
+        let moved = self.hb_get_block_hash_list_by_block_range_chunked(ino, from..to).await?;
+        // collect final state per key, such that a key that is
+        // deleted and re-assigned ends up with a single mutation.
+        let mut new_state = BTreeMap::<Key, Option<TiFsHash>>::new();
+        for (index, _hash) in moved.iter() {
+            let key = Key::from(self.fs_config().key_builder().block_hash(
+                BlockAddress { ino, index: *index }));
+            new_state.insert(key, None);
+        }
+        for (index, hash) in moved.into_iter() {
+            let key = Key::from(self.fs_config().key_builder().block_hash(
+                BlockAddress { ino, index: BlockIndex(index.0 - shift) }));
+            new_state.insert(key, Some(hash));
+        }
+        let mutations = new_state.into_iter().map(|(key, hash)|{
+            match hash {
+                Some(hash) => Mutation::Put(key, hash),
+                None => Mutation::Delete(key),
+            }
+        }).collect::<Vec<_>>();
+        self.mini.batch_mutate(mutations).await?;
+        self.inode_invalidate_content_hashes(ino, BlockIndex(from.0 - shift)..end_of_file).await?;
+        Ok(Some(to))
+    }
+
+    /// Content changed: invalidates the full hash, the stored segment roots of
+    /// the changed blocks and the cached attributes of clients.
+    async fn inode_invalidate_content_hashes(
+        &mut self,
+        ino: StorageIno,
+        changed: Range<BlockIndex>,
+    ) -> TiFsResult<()> {
+        let full_hash_key = self.fs_config().key_builder().inode_x(
+            ino, super::key::InoMetadata::FullHash).buf;
+        self.mini.delete(full_hash_key).await?;
+        self.put(&ino, Arc::new(InoChangeIterationId::random())).await?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut from = BlockIndex(full_hash_segment_start(changed.start.0));
+        while from < changed.end {
+            let range = self.fs_config().key_builder().full_hash_segment_range(
+                ino, from..changed.end);
+            let keys = self.mini.scan_keys(range, MAX_TIKV_SCAN_LIMIT).await?;
+            let Some(last) = keys.last().cloned() else {
+                break;
+            };
+            let done = keys.len() < MAX_TIKV_SCAN_LIMIT as usize;
+            self.mini.batch_mutate(keys.into_iter().map(Mutation::Delete)).await?;
+            if done {
+                break;
+            }
+            let last = self.fs_config().key_parser_b(last)?.parse_key_block_address()?;
+            from = BlockIndex(last.index.0 + 1);
+        }
+        Ok(())
+    }
+
+    /// Root of the merkle tree full hash over the full segment starting at first_block.
+    /// The root is stored until a block mapping of the segment changes. Reading the
+    /// mappings and storing the root in the same transaction avoids storing an outdated root.
+    pub async fn inode_full_hash_segment_root(
+        &mut self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+    ) -> TiFsResult<TiFsHash> {
+        let key = self.fs_config().key_builder().full_hash_segment(
+            BlockAddress { ino, index: first_block });
+        if let Some(root) = self.mini.get(key.clone()).await? {
+            if root.len() == self.fs_config().hash_len {
+                return Ok(root);
+            }
+        }
+
+        let end = BlockIndex(first_block.0 + FULL_HASH_SEGMENT_BLOCKS);
+        let hashes = self.hb_get_block_hash_list_by_block_range_chunked(
+            ino, first_block..end).await?;
+        let zero_hash = self.fs_config().calculate_hash(&[]);
+        let mut hasher = FullHasher::new(
+            self.fs_config().hash_algorithm, FullHashMode::MerkleTree);
+        for index in first_block.0..end.0 {
+            hasher.push_block_hash(hashes.get(&BlockIndex(index)).unwrap_or(&zero_hash));
+        }
+        let root = hasher.finish();
+        self.mini.put(key, root.clone()).await?;
+        Ok(root)
+    }
+
+    /// Registers the blocks at their addresses including the reference counting
+    /// and the size update. Stores the data of blocks that are not stored yet.
+    /// Returns the blocks whose data is needed but not in new_data. Nothing
+    /// is changed in that case.
+    pub async fn inode_write_blocks(
+        &mut self,
+        ino: StorageIno,
+        blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+        new_data: &HashMap<TiFsHash, Arc<Vec<u8>>>,
+    ) -> TiFsResult<Vec<TiFsHash>> {
+        let block_size = self.fs_config().block_size;
+        let without_data = self.hb_get_blocks_without_data(
+            blocks.iter().map(|(h, _l, _ids)| *h)).await?;
+        let missing = without_data.iter()
+            .filter(|h| !new_data.contains_key(*h)).cloned().collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Ok(missing);
+        }
+
+        let increments = blocks.iter().map(|(h, _l, ids)|{
+            (*h, ids.len() as u64)
+        }).collect::<Vec<_>>();
+        self.hb_increment_blocks_reference_count(&increments).await?;
+
+        let uploads = without_data.iter().flat_map(|hash|{
+            let key = self.fs_config().key_builder().hashed_block(hash);
+            let len_key = self.fs_config().key_builder().hashed_block_data_length(hash);
+            let data = new_data[hash].deref().clone();
+            let len = (data.len() as u64).to_be_bytes().to_vec();
+            [Mutation::Put(key.into(), data), Mutation::Put(len_key.into(), len)]
+        }).collect::<Vec<_>>();
+        self.mini.batch_mutate(uploads).await?;
+
+        let mut max_file_size = 0;
+        let mut addresses = Vec::new();
+        for (hash, len, ids) in blocks {
+            for id in ids {
+                addresses.push((BlockAddress { ino, index: *id }, Some(*hash)));
+                max_file_size = max_file_size.max(id.0 * block_size + len);
+            }
+        }
+        let decrements = self.hb_replace_block_hash_for_address_and_size_update(
+            ino, &addresses, max_file_size).await?;
+
+        // content changed -> invalidate full hash:
+        let full_hash_key = self.fs_config().key_builder().inode_x(
+            ino, super::key::InoMetadata::FullHash).buf;
+        self.mini.delete(full_hash_key).await?;
+        self.put(&ino, Arc::new(InoChangeIterationId::random())).await?;
+
+        let decrements = decrements.iter().map(|(h, c)| (h, *c)).collect::<HashMap<_,_>>();
+        self.hb_decrement_blocks_reference_count_and_delete_if_zero_reached(&decrements).await?;
+        Ok(vec![])
+    }
+
+    /// Assigns already stored blocks to the block addresses starting at first_block.
+    /// The hash of empty data creates a hole. With truncate == true, the file ends
+    /// after the given blocks. All blocks except the last one of the file are
+    /// expected to be full blocks.
+    /// Fails with UnknownBlockHash if one of the blocks is not stored.
+    pub async fn inode_set_blocks_from_hashes(
+        &mut self,
+        ino: StorageIno,
+        first_block: BlockIndex,
+        hashes: &[TiFsHash],
+        truncate: bool,
+    ) -> TiFsResult<()> {
+        let block_size = self.fs_config().block_size;
+        let hole_hash = self.fs_config().calculate_hash(&[]);
+
+        let mut increments = HashMap::<&TiFsHash, u64>::new();
+        for hash in hashes.iter().filter(|h| **h != hole_hash) {
+            *increments.entry(hash).or_default() += 1;
+        }
+        let without_data = self.hb_get_blocks_without_data(increments.keys().cloned()).await?;
+        if let Some(unknown) = without_data.into_iter().next() {
+            return Err(FsError::UnknownBlockHash { hash: unknown });
+        }
+        let increments = increments.into_iter().collect::<Vec<_>>();
+        self.hb_increment_blocks_reference_count(&increments).await?;
+
+        let mut ino_size = TxnFetchMut::<StorageIno, InoSize>::fetch(
+            self, &ino).await?.deref().clone();
+        let end_block = first_block.0 + hashes.len() as u64;
+        let mut addresses = hashes.iter().enumerate().map(|(i, hash)|{
+            let addr = BlockAddress { ino, index: BlockIndex(first_block.0 + i as u64) };
+            (addr, (*hash != hole_hash).then_some(hash))
+        }).collect::<Vec<_>>();
+        if truncate {
+            for index in end_block..ino_size.blocks() {
+                addresses.push((BlockAddress { ino, index: BlockIndex(index) }, None));
+            }
+        }
+        let decrements = self.hb_replace_block_hash_for_address_no_size_update(
+            &addresses).await?;
+
+        let last_block_len = match hashes.last() {
+            Some(hash) if *hash != hole_hash => self.hb_get_block_data_length(hash).await?,
+            Some(_) => block_size,
+            None => 0,
+        };
+        let written_end = if hashes.is_empty() {
+            first_block.0 * block_size
+        } else {
+            (end_block - 1) * block_size + last_block_len
+        };
+        let prev_size = ino_size.size();
+        let new_size = if truncate { written_end } else { prev_size.max(written_end) };
+
+        let added_mappings = addresses.iter().filter(|(_a, h)| h.is_some()).count() as u64;
+        let removed_mappings = decrements.values().sum::<u64>();
+        ino_size.set_size(new_size, block_size);
+        ino_size.update_mapped_blocks(added_mappings, removed_mappings);
+        ino_size.last_change = SystemTime::now();
+        self.put(&ino, Arc::new(ino_size)).await?;
+
+        // content changed -> invalidate full hash:
+        let full_hash_key = self.fs_config().key_builder().inode_x(
+            ino, super::key::InoMetadata::FullHash).buf;
+        self.mini.delete(full_hash_key).await?;
+        self.put(&ino, Arc::new(InoChangeIterationId::random())).await?;
+        self.inode_update_usage(ino, new_size as i64 - prev_size as i64, 0).await?;
+
+        let decrements = decrements.iter().map(|(h, c)| (h, *c)).collect::<HashMap<_,_>>();
+        self.hb_decrement_blocks_reference_count_and_delete_if_zero_reached(&decrements).await
+    }
+
+    /// Length of the stored block data. Blocks uploaded before the length was
+    /// stored next to the data are read once and get the length added.
+    pub async fn hb_get_block_data_length(&mut self, hash: &TiFsHash) -> TiFsResult<u64> {
+        let len_key = self.fs_config().key_builder().hashed_block_data_length(hash);
+        if let Some(value) = self.mini.get(len_key.clone()).await? {
+            let bytes = <[u8; 8]>::try_from(value.as_slice()).map_err(|_| FsError::Serialize {
+                target: "block data length", typ: "BIN", msg: format!("unexpected length {}", value.len())})?;
+            return Ok(u64::from_be_bytes(bytes));
+        }
+        let block_key = self.fs_config().key_builder().hashed_block(hash);
+        let Some(data) = self.mini.get(block_key).await? else {
+            return Err(FsError::UnknownBlockHash { hash: hash.clone() });
+        };
+        let len = data.len() as u64;
+        self.mini.put(len_key, len.to_be_bytes().to_vec()).await?;
+        Ok(len)
+    }
+
+    /// Reads the data of the stored blocks behind the key `after`.
+    /// Returns the blocks, the last scanned key and whether all blocks were scanned.
+    pub async fn hb_scan_block_data(
+        &mut self,
+        after: Option<&[u8]>,
+        limit: u32,
+    ) -> TiFsResult<(Vec<(TiFsHash, Vec<u8>)>, Option<Vec<u8>>, bool)> {
+        let mut range = self.fs_config().key_builder().hashed_block_all_range();
+        if let Some(after) = after {
+            range.from = std::ops::Bound::Excluded(Key::from(after.to_vec()));
+        }
+        let pairs = self.mini.scan(range, limit).await?.collect::<Vec<_>>();
+        let complete = pairs.len() < limit as usize;
+
+        let mut blocks = Vec::new();
+        let mut last_key = None;
+        for KvPair(key, value) in pairs {
+            let key_bytes = Vec::<u8>::from(key.clone());
+            let (hash, is_data) = self.fs_config().key_parser_b(key)?
+                .parse_hash_block_key_is_data()?;
+            if is_data {
+                blocks.push((hash, value));
+            }
+            last_key = Some(key_bytes);
+        }
+        Ok((blocks, last_key, complete))
+    }
+
+    /// Moves the data of blocks that don't match their hash to the quarantine.
+    /// Blocks that were repaired in the meantime stay in place. Reference counters
+    /// are not changed. Writers of such a block upload its data again, see
+    /// hb_get_blocks_without_data, which repairs the block.
+    pub async fn hb_quarantine_blocks(&mut self, hashes: &[TiFsHash]) -> TiFsResult<u64> {
+        let mut moved = 0;
+        for hash in hashes {
+            let key = self.fs_config().key_builder().hashed_block(hash);
+            let Some(data) = self.mini.get(key.clone()).await? else {
+                continue;
+            };
+            if self.fs_config().calculate_hash(&data) == *hash {
+                continue;
+            }
+            let quarantine_key = self.fs_config().key_builder().quarantined_block(hash);
+            self.mini.put(quarantine_key, data).await?;
+            self.mini.delete(key).await?;
+            let counter_key = self.fs_config().key_builder().named_hashed_block_x(
+                hash, Some(HashedBlockMeta::CCountedNamedUsages), None);
+            if let Some(value) = self.mini.get(counter_key.clone()).await? {
+                let mut counter = BlockReferenceCount::from_bytes(&value);
+                counter.quarantined = true;
+                self.mini.put(counter_key, counter.to_bytes()).await?;
+            }
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    pub async fn scrub_progress_read(&mut self) -> TiFsResult<ScrubProgress> {
+        let progress: Option<ScrubProgress> = self.fetch_try(&()).await?;
+        Ok(progress.unwrap_or_default())
+    }
+
+    pub async fn scrub_progress_write(&mut self, progress: ScrubProgress) -> TiFsResult<()> {
+        self.put(&(), Arc::new(progress)).await
+    }
+
+    /// Appends the entries with the current time to the change log, if a watcher holds
+    /// the lease. Watchers acquire it before they read anything, so they don't miss
+    /// the entries of changes that happened after their reads.
+    pub async fn change_log_append(&mut self, entries: &[ChangeLogEntry]) -> TiFsResult<()> {
+        let time_us = change_log::time_us(SystemTime::now());
+        let lease: Option<ChangeLogWatchLease> = self.fetch_try(&()).await?;
+        if !lease.is_some_and(|lease| lease.is_active(time_us)) {
+            return Ok(());
+        }
+        for entry in entries {
+            let key = self.fs_config().key_builder().change_log(time_us, Uuid::new_v4());
+            self.mini.put(key, serialize_json(entry)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Extends the lease of the watchers to a full lease duration from now.
+    pub async fn change_log_renew_lease(&mut self) -> TiFsResult<()> {
+        let until_us = change_log::time_us(SystemTime::now() + CHANGE_LOG_WATCH_LEASE);
+        let lease: Option<ChangeLogWatchLease> = self.fetch_try(&()).await?;
+        if lease.as_ref().is_some_and(|lease| lease.until_us >= until_us) {
+            return Ok(());
+        }
+        self.put(&(), Arc::new(ChangeLogWatchLease { until_us })).await
+    }
+
+    /// Change log entries written at from_us or later, continuing after the given entry.
+    /// Returns the write time, the id and the entry.
+    pub async fn change_log_scan(
+        &mut self,
+        from_us: u64,
+        after: Option<(u64, Uuid)>,
+        limit: u32,
+    ) -> TiFsResult<Vec<(u64, Uuid, ChangeLogEntry)>> {
+        let mut range = self.fs_config().key_builder().change_log_range_from(from_us);
+        if let Some((time_us, id)) = after {
+            let key = self.fs_config().key_builder().change_log(time_us, id);
+            range.from = std::ops::Bound::Excluded(Key::from(key));
+        }
+        let data = self.mini.scan(range, limit).await?.collect::<Vec<_>>();
+        let mut result = Vec::with_capacity(data.len());
+        for KvPair(k, v) in data {
+            let (time_us, id) = self.fs_config().key_parser_b(k)?.parse_change_log()?;
+            result.push((time_us, id, deserialize_json::<ChangeLogEntry>(&v)?));
+        }
+        Ok(result)
+    }
+
+    /// Removes up to limit entries written before time_us. Returns the number of removed entries.
+    pub async fn change_log_remove_before(&mut self, time_us: u64, limit: u32) -> TiFsResult<usize> {
+        let range = self.fs_config().key_builder().change_log_range_before(time_us);
+        let keys = self.mini.scan_keys(range, limit).await?.collect::<Vec<_>>();
+        let count = keys.len();
+        self.mini.batch_mutate(keys.into_iter().map(Mutation::Delete)).await?;
+        Ok(count)
+    }
+
+    /// Reads the global reference counts of the blocks. Unknown hashes are missing in the result.
+    pub async fn hb_get_reference_counts<'fl>(
+        &mut self,
+        hashes: impl Iterator<Item = &'fl TiFsHash>,
+    ) -> TiFsResult<HashMap<TiFsHash, u64>> {
+        let counters = self.hb_get_reference_counters(hashes).await?;
+        Ok(counters.into_iter().map(|(hash, counter)|{
+            (hash, counter.count.to_u64().unwrap_or(u64::MAX))
+        }).collect())
+    }
+
+    /// Reads the global reference counters of the blocks including their flags.
+    pub async fn hb_get_reference_counters<'fl>(
+        &mut self,
+        hashes: impl Iterator<Item = &'fl TiFsHash>,
+    ) -> TiFsResult<HashMap<TiFsHash, BlockReferenceCount>> {
+        let keys = hashes.map(|h|{
+            Key::from(self.fs_config().key_builder().named_hashed_block_x(
+                h, Some(HashedBlockMeta::CCountedNamedUsages), None))
+        }).collect::<Vec<_>>();
+        let counters = self.mini.batch_get(keys).await?
+            .filter_map(|KvPair(key, value)|{
+                let hash = self.fs_config().key_parser_b(key).ok()?.parse_hash().ok()?.1;
+                Some((hash, BlockReferenceCount::from_bytes(&value)))
+            }).collect::<HashMap<_,_>>();
+        Ok(counters)
+    }
+
+    /// Counts the mapped blocks of ino that are shared with other block references.
+    pub async fn inode_collect_block_sharing_stats(&mut self, ino: StorageIno) -> TiFsResult<FileStats> {
+        let ino_size: Arc<InoSize> = self.fetch(&ino).await?;
+        let hashes = self.hb_get_block_hash_list_by_block_range_chunked(
+            ino, BlockIndex(0)..BlockIndex(ino_size.blocks())).await?;
+
+        let unique_hashes = hashes.values().collect::<HashSet<_>>();
+        let counters = self.hb_get_reference_counts(unique_hashes.into_iter()).await?;
+
+        let mut stats = FileStats {
+            size: ino_size.size(),
+            mapped_blocks: hashes.len() as u64,
+            ..Default::default()
+        };
+        for hash in hashes.values() {
+            if counters.get(hash).map(|c| *c > 1).unwrap_or(false) {
+                stats.shared_blocks += 1;
+            } else {
+                stats.exclusive_blocks += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Returns the first block index within the range that has a block hash assigned
+    /// (mapped == true) or that is a hole (mapped == false).
+    #[tracing::instrument(skip(self))]
+    pub async fn hb_seek_block_mapping(
+        &mut self,
+        ino: StorageIno,
+        block_range: Range<BlockIndex>,
+        mapped: bool,
+    ) -> TiFsResult<Option<BlockIndex>> {
+        if mapped {
+            let range = self.fs_config().key_builder().block_hash_range(ino, block_range);
+            let first = self.mini.scan_keys(range, 1).await?.next();
+            let Some(key) = first else {
+                return Ok(None);
+            };
+            let addr = self.fs_config().key_parser_b(key)?.parse_key_block_address()?;
+            return Ok(Some(addr.index));
+        }
+
+        let mut i = block_range.start;
+        while i < block_range.end {
+            let chunk_start = i;
+            let chunk_end = BlockIndex(chunk_start.0+MAX_TIKV_SCAN_LIMIT as u64)
+                                        .min(block_range.end);
+            i = chunk_end;
+
+            let range = self.fs_config().key_builder().block_hash_range(ino, chunk_start..chunk_end);
+            let keys = self.mini.scan_keys(range, (chunk_end.0 - chunk_start.0) as u32).await?;
+            let mut expected = chunk_start;
+            for key in keys {
+                let addr = self.fs_config().key_parser_b(key)?.parse_key_block_address()?;
+                if addr.index != expected {
+                    return Ok(Some(expected));
+                }
+                expected = BlockIndex(expected.0 + 1);
+            }
+            if expected < chunk_end {
+                return Ok(Some(expected));
+            }
+        }
+        Ok(None)
+    }
 }
 
 
@@ -6,10 +6,13 @@ use super::{hash_block::block_splitter::BlockSplitterRead, hash_fs_interface::Bl
 use crate::fs::error::Result;
 
 
+/// Reads the range of bs from the blocks. Holes and short blocks read as zeros
+/// up to the end of the range or the end of the file, whichever comes first.
 pub fn hb_read_from_blocks(
     bs: &BlockSplitterRead,
     block_hashes: &BTreeMap<BlockIndex, TiFsHash>,
-    blocks_data: &HashMap<TiFsHash, Arc<Vec<u8>>>
+    blocks_data: &HashMap<TiFsHash, Arc<Vec<u8>>>,
+    file_size: u64,
 ) -> Result<Vec<u8>> {
     let mut result = Vec::with_capacity(bs.block_size as usize * block_hashes.len());
     for block_index in bs.first_block_index..bs.end_block_index {
@@ -28,11 +31,59 @@ pub fn hb_read_from_blocks(
         };
 
         if rd_start < block_data.len() {
+            // holes (unmapped blocks) and short blocks in front of this one read as zeros
+            let pos_in_result = match rel_index {
+                0 => 0,
+                _ => (bs.bytes_to_read_first_block + (rel_index - 1) * bs.block_size) as usize,
+            };
+            if result.len() < pos_in_result {
+                result.resize(pos_in_result, 0);
+            }
             // do a copy, as some blocks might be used multiple times
             let rd_end = (rd_start + rd_size).min(block_data.len());
             //eprintln!("extend (result.len(): {}) from slice ({block_index}): {rd_start}..{rd_end}", result.len());
             result.extend_from_slice(&block_data[rd_start..rd_end]);
         }
     }
+    // trailing holes within the file:
+    let expected_len = (bs.start + bs.size).min(file_size).saturating_sub(bs.start) as usize;
+    if result.len() < expected_len {
+        result.resize(expected_len, 0);
+    }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::{BTreeMap, HashMap}, sync::Arc};
+
+    use super::hb_read_from_blocks;
+    use crate::fs::{hash_block::block_splitter::BlockSplitterRead, hash_fs_interface::BlockIndex};
+
+    #[test]
+    fn read_over_hole_fills_zeros() {
+        let bs = BlockSplitterRead::new(4, 2, 10);
+        let block_hashes = BTreeMap::from([
+            (BlockIndex(0), vec![1u8]),
+            (BlockIndex(2), vec![2u8]),
+        ]);
+        let blocks_data = HashMap::from([
+            (vec![1u8], Arc::new(vec![1u8, 1, 1, 1])),
+            (vec![2u8], Arc::new(vec![2u8, 2, 2, 2])),
+        ]);
+        let result = hb_read_from_blocks(&bs, &block_hashes, &blocks_data, 20).unwrap();
+        assert_eq!(result, vec![1, 1, 0, 0, 0, 0, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn read_trailing_hole_is_filled_up_to_file_size() {
+        let bs = BlockSplitterRead::new(4, 2, 12);
+        let block_hashes = BTreeMap::from([(BlockIndex(0), vec![1u8])]);
+        let blocks_data = HashMap::from([(vec![1u8], Arc::new(vec![1u8, 1, 1, 1]))]);
+        // e.g. after fallocate, the file ends within the hole:
+        let result = hb_read_from_blocks(&bs, &block_hashes, &blocks_data, 10).unwrap();
+        assert_eq!(result, vec![1, 1, 0, 0, 0, 0, 0, 0]);
+        let result = hb_read_from_blocks(&bs, &block_hashes, &blocks_data, 4).unwrap();
+        assert_eq!(result, vec![1, 1]);
+    }
+}
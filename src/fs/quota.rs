@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+use super::inode::StorageIno;
+
+/// What a quota entry limits.
+/// A directory quota applies to all inodes below the directory. It can only be
+/// added to an empty directory. The innermost directory quota of a subtree wins.
+/// Directories with children can't be renamed into another quota (EXDEV).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum QuotaTarget {
+    User(u32),
+    Group(u32),
+    Directory(StorageIno),
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    pub max_bytes: Option<u64>,
+    pub max_inodes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    /// Sum of the sizes of the charged inodes.
+    pub used_bytes: u64,
+    pub used_inodes: u64,
+}
+
+/// Stored state of a quota. Usage of users and groups is tracked
+/// even without configured limits. The stored usage is a base value,
+/// changes are written as UsageDelta shards.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuotaState {
+    pub limits: QuotaLimits,
+    pub usage: QuotaUsage,
+}
+
+impl QuotaState {
+    /// Applies the change to the usage. Returns false if a growing usage exceeds a limit.
+    pub fn apply_usage_delta(&mut self, bytes: i64, inodes: i64) -> bool {
+        self.usage.used_bytes = self.usage.used_bytes.saturating_add_signed(bytes);
+        self.usage.used_inodes = self.usage.used_inodes.saturating_add_signed(inodes);
+        let bytes_exceeded = bytes > 0 && self.limits.max_bytes
+            .map(|max| self.usage.used_bytes > max).unwrap_or(false);
+        let inodes_exceeded = inodes > 0 && self.limits.max_inodes
+            .map(|max| self.usage.used_inodes > max).unwrap_or(false);
+        !(bytes_exceeded || inodes_exceeded)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuotaReport {
+    pub target: QuotaTarget,
+    pub state: QuotaState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_usage_delta_only_fails_on_growth_beyond_limit() {
+        let mut state = QuotaState {
+            limits: QuotaLimits { max_bytes: Some(100), max_inodes: Some(1) },
+            usage: QuotaUsage::default(),
+        };
+        assert!(state.apply_usage_delta(100, 1));
+        assert!(!state.apply_usage_delta(1, 0));
+        assert!(!state.apply_usage_delta(0, 1));
+        // shrinking is always allowed, even when still above the limit:
+        assert!(state.apply_usage_delta(-1, -1));
+        assert_eq!(state.usage, QuotaUsage { used_bytes: 100, used_inodes: 1 });
+    }
+}
@@ -1,5 +1,5 @@
 use std::fmt::Debug;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 use fuser::{FileAttr, FileType, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyLock, ReplyLseek, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr};
 use serde::{Deserialize, Serialize};
@@ -10,11 +10,7 @@ use super::inode::StorageIno;
 use super::key::ROOT_INODE;
 use super::utils::common_prints::debug_print_start_and_end_bytes_of_buffer;
 
-pub fn get_time() -> Duration {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Entry {
     pub time: Duration,
     pub stat: FileAttr,
@@ -22,9 +18,9 @@ pub struct Entry {
 }
 
 impl Entry {
-    pub fn new(stat: FileAttr, generation: u64) -> Self {
+    pub fn new(time: Duration, stat: FileAttr, generation: u64) -> Self {
         Self {
-            time: get_time(),
+            time,
             stat,
             generation,
         }
@@ -48,9 +44,9 @@ pub struct Attr {
     pub attr: FileAttr,
 }
 impl Attr {
-    pub fn new(attr: FileAttr) -> Self {
+    pub fn new(time: Duration, attr: FileAttr) -> Self {
         Self {
-            time: get_time(),
+            time,
             attr,
         }
     }
@@ -75,6 +71,9 @@ pub enum InoKind {
     Regular,
     Hash,
     Hashes,
+    Stats,
+    Blocks,
+    Merkle,
 }
 
 impl InoKind {
@@ -83,6 +82,9 @@ impl InoKind {
             InoKind::Regular => 0u64,
             InoKind::Hash => 1u64,
             InoKind::Hashes => 2u64,
+            InoKind::Stats => 3u64,
+            InoKind::Blocks => 4u64,
+            InoKind::Merkle => 5u64,
         }
     }
 }
@@ -108,6 +110,9 @@ impl LogicalIno {
             0 => InoKind::Regular,
             1 => InoKind::Hash,
             2 => InoKind::Hashes,
+            3 => InoKind::Stats,
+            4 => InoKind::Blocks,
+            5 => InoKind::Merkle,
             _ => InoKind::Regular,
         };
 
@@ -164,6 +169,8 @@ impl Dir {
 
 pub type Directory = Vec<DirItem>;
 
+pub type DirectoryPlus = Vec<(DirItem, Entry)>;
+
 #[derive(Debug, Default)]
 pub struct DirPlus {
     offset: usize,
@@ -243,9 +250,9 @@ pub struct Create {
     pub flags: u32,
 }
 impl Create {
-    pub fn new(attr: FileAttr, generation: u64, fh: u64, flags: u32) -> Self {
+    pub fn new(ttl: Duration, attr: FileAttr, generation: u64, fh: u64, flags: u32) -> Self {
         Self {
-            ttl: get_time(),
+            ttl,
             attr,
             generation,
             fh,
@@ -394,7 +401,7 @@ impl FsReply<DirPlus> for ReplyDirectoryPlus {
         for (index, (item, entry)) in dir.items.into_iter().enumerate() {
             if self.add(
                 item.ino.to_raw(),
-                (dir.offset + index) as i64,
+                (index + 1 + dir.offset) as i64,
                 item.name,
                 &entry.time,
                 &entry.stat,
@@ -526,6 +533,22 @@ mod test {
         assert_eq!(l_ino.storage_ino().0, 1);
     }
 
+    #[test]
+    fn stats_kind_check_root_ino() {
+        let l_ino = LogicalIno::from_raw(4);
+        assert_eq!(l_ino.kind, InoKind::Stats);
+        assert_eq!(l_ino.to_raw(), 4);
+        assert_eq!(l_ino.storage_ino().0, 1);
+    }
+
+    #[test]
+    fn merkle_kind_check_root_ino() {
+        let l_ino = LogicalIno::from_raw(6);
+        assert_eq!(l_ino.kind, InoKind::Merkle);
+        assert_eq!(l_ino.to_raw(), 6);
+        assert_eq!(l_ino.storage_ino().0, 1);
+    }
+
     #[test]
     fn check_second_ino() {
         let l_ino = LogicalIno::from_raw(1 + LOGICAL_INO_FACTOR);
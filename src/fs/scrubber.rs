@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::hash_fs_interface::HashFsResult;
+use super::hash_fs_tikv_implementation::TikvBasedHashFs;
+use super::inode::TiFsHash;
+use super::utils::hash_algorithm::HashAlgorithm;
+
+/// Persisted position of the scrubber. A restarted scrubber continues
+/// after the last verified block instead of starting over.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubProgress {
+    /// Number of completed passes over all blocks.
+    pub round: u64,
+    /// Last scanned key of the current round. The key itself, not the hash of its block,
+    /// as the scan continues right after it.
+    pub last_key: Option<Vec<u8>>,
+    /// Verified blocks of the current round.
+    pub verified_blocks: u64,
+    /// Mismatches found in all rounds.
+    pub mismatches: u64,
+    pub last_round_completed: Option<SystemTime>,
+}
+
+impl ScrubProgress {
+    pub fn advance(
+        &mut self,
+        verified: u64,
+        mismatches: u64,
+        last_key: Option<Vec<u8>>,
+        round_completed: bool,
+    ) {
+        self.verified_blocks += verified;
+        self.mismatches += mismatches;
+        if round_completed {
+            self.round += 1;
+            self.last_key = None;
+            self.verified_blocks = 0;
+            self.last_round_completed = Some(SystemTime::now());
+        } else if last_key.is_some() {
+            self.last_key = last_key;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScrubberConfig {
+    /// Upper limit of verified blocks per second. None means no limit.
+    pub blocks_per_second: Option<u64>,
+    /// Number of keys that are scanned per transaction.
+    pub chunk_size: u32,
+    /// Move the data of mismatching blocks to the quarantine instead of only reporting them.
+    /// Reads of these blocks fail afterwards, until the correct data is uploaded again.
+    pub quarantine: bool,
+    /// Stop after one complete round.
+    pub single_round: bool,
+    pub pause_between_rounds: Duration,
+}
+
+impl Default for ScrubberConfig {
+    fn default() -> Self {
+        Self {
+            blocks_per_second: Some(100),
+            chunk_size: 64,
+            quarantine: false,
+            single_round: false,
+            pause_between_rounds: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScrubMismatch {
+    pub hash: TiFsHash,
+    pub actual_hash: TiFsHash,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScrubChunk {
+    pub verified: u64,
+    pub mismatches: Vec<ScrubMismatch>,
+    pub quarantined: u64,
+    pub round_completed: bool,
+    pub progress: ScrubProgress,
+}
+
+pub fn verify_blocks(algorithm: HashAlgorithm, blocks: &[(TiFsHash, Vec<u8>)]) -> Vec<ScrubMismatch> {
+    blocks.iter().filter_map(|(hash, data)| {
+        let actual_hash = algorithm.calculate_hash(data);
+        (actual_hash != *hash).then(|| ScrubMismatch { hash: hash.clone(), actual_hash })
+    }).collect()
+}
+
+/// Walks all stored blocks at the configured rate and verifies them against their hashes.
+/// Runs forever, unless single_round is configured.
+pub async fn run_scrubber(fs: Arc<TikvBasedHashFs>, config: ScrubberConfig) -> HashFsResult<()> {
+    tracing::info!("starting scrubber: {config:?}");
+    loop {
+        let chunk_start = Instant::now();
+        let chunk = fs.scrub_next_chunk(config.chunk_size, config.quarantine).await?;
+        for mismatch in chunk.mismatches.iter() {
+            tracing::error!("scrubber: block data doesn't match its hash {:x?}. actual: {:x?}, quarantined: {}",
+                mismatch.hash, mismatch.actual_hash, config.quarantine);
+        }
+
+        if chunk.round_completed {
+            tracing::info!("scrubber: completed round {}, mismatches so far: {}",
+                chunk.progress.round, chunk.progress.mismatches);
+            if config.single_round {
+                return Ok(());
+            }
+            tokio::time::sleep(config.pause_between_rounds).await;
+            continue;
+        }
+
+        if let Some(rate) = config.blocks_per_second.filter(|r| *r > 0) {
+            let target = Duration::from_secs_f64(chunk.verified as f64 / rate as f64);
+            tokio::time::sleep(target.saturating_sub(chunk_start.elapsed())).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_blocks_reports_mismatches_and_progress_resumes() {
+        let algo = HashAlgorithm::Blake3;
+        let good = (algo.calculate_hash(b"good"), b"good".to_vec());
+        let bad = (algo.calculate_hash(b"original"), b"corrupted".to_vec());
+        let mismatches = verify_blocks(algo, &[good.clone(), bad.clone()]);
+        assert_eq!(mismatches, vec![ScrubMismatch {
+            hash: bad.0.clone(),
+            actual_hash: algo.calculate_hash(b"corrupted"),
+        }]);
+
+        let mut progress = ScrubProgress::default();
+        progress.advance(2, 1, Some(bad.0.clone()), false);
+        assert_eq!(progress.last_key, Some(bad.0));
+        progress.advance(0, 0, None, true);
+        assert_eq!(progress.round, 1);
+        assert_eq!(progress.last_key, None);
+        assert_eq!(progress.verified_blocks, 0);
+        assert_eq!(progress.mismatches, 1);
+    }
+}
@@ -123,6 +123,9 @@ impl CreateSnapshot {
         let kv_parser = KvPairParser{fs_config: self.src_txn.fs_config().clone()};
         let mut maps = kv_parser.parse_inode_attrs_kv_pairs(attr_data)?;
 
+        let cloned_inodes = maps.descriptions.len() as i64;
+        let cloned_bytes = maps.size.values().map(|s|s.size()).sum::<u64>() as i64;
+
         let mut spin = self.spinning_mini_txn().await?;
         loop {
             let mut started = spin.start().await?;
@@ -150,10 +153,17 @@ impl CreateSnapshot {
                     if r1.is_err() { break; }
                 }
                 if let Some(value) = maps.size.get(&src.ino).cloned() {
+                    let size = value.size() as i64;
                     r1 = started.put(new_ino, Arc::new(value)).await;
                     if r1.is_err() { break; }
+                    // the copy is owned by the same user and group:
+                    r1 = started.quota_update_usage(*new_ino, size, 1).await;
+                    if r1.is_err() { break; }
                 }
             }
+            if r1.is_ok() {
+                r1 = started.meta_mutable_update_usage(cloned_bytes, 0, cloned_inodes).await;
+            }
             if let Some(r) = started.finish(r1).await { break r?; }
         }
         Ok(maps.size)
@@ -264,7 +274,11 @@ impl CreateSnapshot {
                     StorageDirItemKind::Directory => {
                         copy_jobs.push_back((ParentStorageIno(src_child.ino), ParentStorageIno(*new_ino)));
                     }
-                    StorageDirItemKind::Symlink => {}
+                    StorageDirItemKind::Symlink
+                    | StorageDirItemKind::CharDevice
+                    | StorageDirItemKind::BlockDevice
+                    | StorageDirItemKind::NamedPipe
+                    | StorageDirItemKind::Socket => {}
                 }
             }
         }
@@ -5,7 +5,7 @@ use std::io::BufRead;
 use std::mem;
 use std::ops::DerefMut;
 use std::pin::Pin;
-use std::sync::{Arc, Weak};
+use std::sync::{Arc, OnceLock, Weak};
 use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::anyhow;
@@ -13,7 +13,7 @@ use bimap::BiHashMap;
 use bytes::Bytes;
 use bytestring::ByteString;
 use fuser::{FileAttr, FileType};
-use futures::FutureExt;
+use futures::{FutureExt, StreamExt};
 
 use moka::future::Cache;
 use range_collections::range_set::RangeSetRange;
@@ -28,14 +28,18 @@ use crate::fs::hash_fs_tikv_implementation::TikvBasedHashFs;
 use crate::fs::inode::DirectoryItem;
 use crate::fs::reply::{DirItem, InoKind};
 use super::error::{FsError, Result, TiFsResult};
+use super::dir_handler::DirHandler;
+use super::disk_block_cache::DiskBlockCache;
+use super::meta_cache::MetaCache;
 use super::file_handler::FileHandler;
 use super::fs_config::{MountOption, TiFsConfig};
-use super::hash_fs_interface::{BlockIndex, HashFsInterface};
+use super::hash_fs_interface::{BlockIndex, ChangeEvent, HashFsInterface};
 use super::inode::{InoAccessTime, InoDescription, InoLockState, InoSize, InoModificationTime, ParentStorageIno, StorageDirItemKind, InoStorageFileAttr, StorageIno, TiFsHash};
-use super::key::{OPENED_INODE_PARENT_INODE, ROOT_INODE, SNAPSHOT_PARENT_INODE};
+use super::key::{FS_STATS_FILE_NAME, OPENED_INODE_PARENT_INODE, ROOT_INODE, SNAPSHOT_PARENT_INODE};
 use super::reply::{
-    Data, Directory, LogicalIno
+    Data, Directory, DirectoryPlus, Entry, LogicalIno
 };
+use super::fs_stats::BlockInfo;
 use super::fuse_to_hashfs::{Txn, TxnArc};
 use super::transaction_client_mux::TransactionClientMux;
 use super::utils::lazy_lock_map::LazyLockMap;
@@ -50,6 +54,10 @@ lazy_static! {
         m.insert(StorageDirItemKind::Directory, FileType::Directory);
         m.insert(StorageDirItemKind::File, FileType::RegularFile);
         m.insert(StorageDirItemKind::Symlink, FileType::Symlink);
+        m.insert(StorageDirItemKind::CharDevice, FileType::CharDevice);
+        m.insert(StorageDirItemKind::BlockDevice, FileType::BlockDevice);
+        m.insert(StorageDirItemKind::NamedPipe, FileType::NamedPipe);
+        m.insert(StorageDirItemKind::Socket, FileType::Socket);
         m
     };
 }
@@ -91,23 +99,32 @@ impl InoUse {
     }
 }
 
+/// Name suffixes of the virtual files next to each regular file.
+const VIRTUAL_FILE_SUFFIXES: [(&[u8], InoKind); 5] = [
+    (b".@hash", InoKind::Hash),
+    (b".@hashes", InoKind::Hashes),
+    (b".@blocks", InoKind::Blocks),
+    (b".@merkle", InoKind::Merkle),
+    (b".@stats", InoKind::Stats),
+];
+
 pub fn parse_filename(name: ByteString) -> (ByteString, InoKind) {
-    if (name.len() > 1) && (name.as_bytes()[0] == b'.') {
-        if name.as_bytes().ends_with(b".@hash") {
-            (name.slice_ref(&name[1..name.len()-b".@hash".len()]), InoKind::Hash)
-        } else if name.as_bytes().ends_with(b".@hashes") {
-            (name.slice_ref(&name[1..name.len()-b".@hashes".len()]), InoKind::Hashes)
-        } else {
-            (name, InoKind::Regular)
+    if name.as_bytes().first() == Some(&b'.') {
+        for (suffix, kind) in VIRTUAL_FILE_SUFFIXES {
+            // a bare suffix like ".@stats" names no file and stays regular:
+            if (name.len() > 1 + suffix.len()) && name.as_bytes().ends_with(suffix) {
+                return (name.slice_ref(&name[1..name.len()-suffix.len()]), kind);
+            }
         }
-    } else {
-        (name, InoKind::Regular)
     }
+    (name, InoKind::Regular)
 }
 
 #[derive(Clone)] // Caches do only clone reference, not content
 pub struct TiFsCaches {
     pub block: TiFsBlockCache,
+    pub disk_block: Option<Arc<DiskBlockCache>>,
+    pub meta: MetaCache,
     pub inode_desc: TxnDataCache<StorageIno, InoDescription>,
     pub inode_size: TxnDataCache<StorageIno, InoSize>,
     pub inode_lock_state: TxnDataCache<StorageIno, InoLockState>,
@@ -122,6 +139,7 @@ pub struct TiFsMutable {
     pub next_fh: u64,
     pub opened_ino: HashMap<u64, Weak<InoUse>>,
     pub file_handlers: HashMap<u64, Arc<FileHandler>>,
+    pub dir_handlers: HashMap<u64, Arc<DirHandler>>,
     pub caches: TiFsCaches,
 }
 
@@ -134,8 +152,15 @@ impl TiFsMutable {
             next_fh: 0,
             opened_ino: HashMap::new(),
             file_handlers: HashMap::new(),
+            dir_handlers: HashMap::new(),
             caches: TiFsCaches{
                 block: Cache::new(fs_config.hashed_blocks_cache_size as u64 / fs_config.block_size),
+                disk_block: fs_config.block_cache_dir.clone().and_then(|dir|{
+                    DiskBlockCache::open(dir, fs_config.block_cache_size, fs_config.hash_algorithm)
+                        .map_err(|err| tracing::error!("disk block cache disabled: {err:?}"))
+                        .ok().map(Arc::new)
+                }),
+                meta: MetaCache::new(ino_cache_size, fs_config.attr_ttl, fs_config.entry_ttl),
                 inode_desc: TxnDataCache::new(ino_cache_size, Duration::from_secs(100)),
                 inode_size: TxnDataCache::new(ino_cache_size, Duration::from_secs(5)),
                 inode_atime: TxnDataCache::new(ino_cache_size, Duration::from_secs(5)),
@@ -168,10 +193,19 @@ impl TiFsMutable {
             Some(handler)
         } else { None }
     }
+
+    pub fn release_dir_handler(&mut self, fh: u64) -> Option<Arc<DirHandler>> {
+        if let Some(handler) = self.dir_handlers.remove(&fh) {
+            self.freed_fhs.insert(fh);
+            Some(handler)
+        } else { None }
+    }
 }
 
 
 const DEFAULT_TLS_CONFIG_PATH: &str = "~/.tifs/tls.toml";
+const STAT_BLOCK_SIZE: u64 = 512;
+const READ_DIR_PAGE_SIZE: u32 = 1024;
 
 fn default_tls_config_path() -> anyhow::Result<std::path::PathBuf> {
     Ok(DEFAULT_TLS_CONFIG_PATH.parse()?)
@@ -185,6 +219,7 @@ pub struct TiFs {
     pub client_config: Config,
     pub direct_io: bool,
     pub fs_config: TiFsConfig,
+    pub kernel_notifier: OnceLock<fuser::Notifier>,
     mut_data: RwLock<TiFsMutable>,
 }
 
@@ -266,6 +301,7 @@ impl TiFs {
                 client_config: cfg,
                 direct_io: fs_config.direct_io,
                 mut_data: RwLock::new(TiFsMutable::new(&fs_config)),
+                kernel_notifier: OnceLock::new(),
                 fs_config,
             }
         });
@@ -306,6 +342,7 @@ impl TiFs {
                 client_config: cfg,
                 direct_io: fs_config.direct_io,
                 mut_data: RwLock::new(TiFsMutable::new(&fs_config)),
+                kernel_notifier: OnceLock::new(),
                 fs_config,
             }
         });
@@ -359,6 +396,122 @@ impl TiFs {
         }
     }
 
+    /// Keeps the metadata cache coherent with the modifications of other mounts
+    /// while no change events are received.
+    #[tracing::instrument]
+    pub async fn meta_cache_revalidation(me: Weak<TiFs>) {
+        let Some(strong) = me.upgrade() else {
+            return;
+        };
+        let meta = strong.get_caches().await.meta;
+        drop(strong);
+        if !meta.is_enabled() {
+            return;
+        }
+        let mut timer = tokio::time::interval(meta.revalidation_interval());
+        loop {
+            timer.tick().await;
+            let Some(strong) = me.upgrade() else {
+                return;
+            };
+            if let Err(err) = meta.revalidate(&strong.hash_fs).await {
+                // entries expire without revalidation:
+                debug!("metadata cache revalidation failed: {err:?}");
+            }
+        }
+    }
+
+    /// Drops the cached metadata of the local and the kernel cache
+    /// for every change reported by the hash fs.
+    async fn apply_change_event(&self, meta: &MetaCache, event: ChangeEvent) {
+        let notifier = self.kernel_notifier.get();
+        match event {
+            ChangeEvent::Inode(ino) => {
+                meta.invalidate_ino(ino).await;
+                if let Some(notifier) = notifier {
+                    let raw = LogicalIno { storage_ino: ino, kind: InoKind::Regular }.to_raw();
+                    // fails with ENOENT for inodes unknown to the kernel:
+                    let _ = notifier.inval_inode(raw, 0, 0);
+                }
+            }
+            ChangeEvent::Entry { parent, name } => {
+                meta.invalidate_entry(parent, &name).await;
+                if let Some(notifier) = notifier {
+                    let raw = LogicalIno { storage_ino: parent.0, kind: InoKind::Regular }.to_raw();
+                    let _ = notifier.inval_entry(raw, std::ffi::OsStr::new(&*name));
+                }
+            }
+            ChangeEvent::Overflow => {
+                // the kernel can't drop all of its metadata at once. It caches no longer
+                // than the local cache, invalidating the locally cached and the opened
+                // inodes covers it:
+                if let Some(notifier) = notifier {
+                    for (parent, name) in meta.cached_entries() {
+                        let raw = LogicalIno { storage_ino: parent.0, kind: InoKind::Regular }.to_raw();
+                        let _ = notifier.inval_entry(raw, std::ffi::OsStr::new(&*name));
+                    }
+                    let mut raw_inos = meta.revalidation_inos().into_iter().map(|ino| {
+                        LogicalIno { storage_ino: ino, kind: InoKind::Regular }.to_raw()
+                    }).collect::<HashSet<_>>();
+                    if let Ok(opened) = self.with_mut_data(
+                        |d| d.opened_ino.keys().cloned().collect::<Vec<_>>()).await {
+                        raw_inos.extend(opened);
+                    }
+                    for raw in raw_inos {
+                        let _ = notifier.inval_inode(raw, 0, 0);
+                    }
+                }
+                meta.invalidate_all();
+            }
+        }
+    }
+
+    /// Subscribes to the change events of the hash fs and resubscribes
+    /// after failures. Events lost in between are covered by dropping
+    /// the whole metadata cache.
+    #[tracing::instrument]
+    pub async fn watch_changes_task(me: Weak<TiFs>) {
+        let mut backoff = Duration::from_millis(100);
+        loop {
+            let Some(strong) = me.upgrade() else {
+                return;
+            };
+            let meta = strong.get_caches().await.meta;
+            let events = strong.hash_fs.watch_changes().await;
+            drop(strong);
+            match events {
+                Err(err) => {
+                    debug!("subscribing to change events failed: {err:?}");
+                }
+                Ok(mut events) => {
+                    backoff = Duration::from_millis(100);
+                    // the events replace the polling revalidation:
+                    meta.set_change_feed_active(true);
+                    while let Some(event) = events.next().await {
+                        let Some(strong) = me.upgrade() else {
+                            return;
+                        };
+                        match event {
+                            Ok(event) => strong.apply_change_event(&meta, event).await,
+                            Err(err) => {
+                                debug!("change event stream failed: {err:?}");
+                                break;
+                            }
+                        }
+                    }
+                    meta.set_change_feed_active(false);
+                    // events until the next subscription get lost:
+                    let Some(strong) = me.upgrade() else {
+                        return;
+                    };
+                    strong.apply_change_event(&meta, ChangeEvent::Overflow).await;
+                }
+            }
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(Duration::from_secs(30));
+        }
+    }
+
     pub async fn check_metadata(self: TiFsArc) -> Result<()> {
         let metadata = self
             .clone().spin_no_delay(format!("check_metadata"),
@@ -399,6 +552,11 @@ impl TiFs {
         self.get_file_handler(fh).await.ok_or(FsError::FhNotFound { fh })
     }
 
+    pub async fn get_dir_handler_checked(&self, fh: u64) -> Result<Arc<DirHandler>> {
+        let d = self.mut_data.read().await;
+        d.dir_handlers.get(&fh).cloned().ok_or(FsError::FhNotFound { fh })
+    }
+
     pub async fn read_kind_regular(
         &self,
         ino: StorageIno,
@@ -463,6 +621,66 @@ impl TiFs {
         Ok(Data::new(data))
     }
 
+    #[tracing::instrument]
+    pub async fn read_kind_merkle(
+        &self,
+        ino: StorageIno,
+        start: u64,
+        size: u32,
+    ) -> Result<Data> {
+
+        let arc = self.weak.upgrade().unwrap();
+        let data = arc
+            .spin_no_delay(format!("read_kind_merkle, ino:{ino}, start:{start}, size:{size}"),
+            move |_, txn| txn.read_merkle_hash_of_file(ino, start, size as u64).boxed())
+            .await?;
+        Ok(Data::new(data))
+    }
+
+    #[tracing::instrument]
+    pub async fn read_kind_blocks(
+        &self,
+        ino: StorageIno,
+        start: u64,
+        size: u32,
+    ) -> Result<Data> {
+
+        let arc = self.weak.upgrade().unwrap();
+        let data = arc
+            .spin_no_delay(format!("read_kind_blocks, ino:{ino}, start:{start}, size:{size}"),
+            move |_, txn| txn.read_block_infos_of_file(ino, start, size as u64).boxed())
+            .await?;
+        Ok(Data::new(data))
+    }
+
+    /// The text is generated once per open, reads of further chunks slice it.
+    #[tracing::instrument(skip(file_handler))]
+    pub async fn read_kind_stats(
+        &self,
+        ino: StorageIno,
+        file_handler: Arc<FileHandler>,
+        start: u64,
+        size: u32,
+    ) -> Result<Data> {
+        let mut stats_text = file_handler.stats_text.write().await;
+        let text = match stats_text.as_ref() {
+            Some(text) => text.clone(),
+            None => {
+                let arc = self.weak.upgrade().unwrap();
+                let text = arc
+                    .spin_no_delay(format!("read_kind_stats, ino:{ino}"),
+                    move |_, txn| txn.stats_text_of_ino(ino).boxed())
+                    .await?;
+                stats_text.insert(Bytes::from(text)).clone()
+            }
+        };
+        mem::drop(stats_text);
+
+        let start = (start as usize).min(text.len());
+        let end = (start + size as usize).min(text.len());
+        Ok(Data::new(text[start..end].to_vec()))
+    }
+
     #[tracing::instrument]
     pub async fn read_file_kind_hashes(
         &self,
@@ -488,6 +706,33 @@ impl TiFs {
         Ok(Data { data: data.into() })
     }
 
+    /// Writing to the hashes virtual file assigns the written block hashes to the blocks
+    /// of the file. Blocks behind the written ones stay, truncating happens
+    /// via O_TRUNC or setting the size of the hashes file.
+    #[tracing::instrument(skip(data))]
+    pub async fn write_kind_hashes(
+        &self,
+        ino: StorageIno,
+        start: u64,
+        data: Bytes,
+    ) -> Result<()> {
+        let hash_size = self.fs_config.hash_len;
+        if start % hash_size as u64 != 0 {
+            return Err(FsError::InvalidOffset { ino: ino.0, offset: start as i64 });
+        }
+        if data.len() % hash_size != 0 {
+            let partial = data[data.len() - data.len() % hash_size..].to_vec();
+            return Err(FsError::UnknownBlockHash { hash: partial });
+        }
+        let first_block = BlockIndex(start / hash_size as u64);
+        let hashes = data.chunks(hash_size).map(|h| h.to_vec()).collect::<Vec<_>>();
+
+        let arc = self.weak.upgrade().unwrap();
+        arc.spin_no_delay(format!("write_kind_hashes, ino:{ino}, start:{start}, hashes:{}", hashes.len()),
+            move |_, txn| txn.set_blocks_from_hashes(ino, first_block, hashes.clone(), false).boxed())
+            .await
+    }
+
     #[instrument(skip(txn, f))]
     pub async fn process_txn<'b, F, T>(&self, txn: TxnArc, f: F) -> Result<T>
     where
@@ -602,19 +847,32 @@ impl TiFs {
             size: match kind {
                 InoKind::Regular => size.size(),
                 InoKind::Hash => self.fs_config.hash_len as u64,
+                InoKind::Merkle => self.fs_config.hash_len as u64,
                 InoKind::Hashes => self.fs_config.hash_len as u64 * size.blocks(),
+                InoKind::Blocks => BlockInfo::line_len(self.fs_config.hash_len) as u64 * size.blocks(),
+                // generated on read. Opened with direct io, so the size is not needed.
+                InoKind::Stats => 0,
             },
+            // st_blocks is in units of 512 bytes. Holes don't occupy space.
             blocks: match kind {
-                InoKind::Regular => size.blocks(),
+                InoKind::Regular => (size.mapped_blocks() * self.fs_config.block_size)
+                                    .div_ceil(STAT_BLOCK_SIZE),
                 InoKind::Hash => 0,
+                InoKind::Merkle => 0,
                 InoKind::Hashes => (self.fs_config.hash_len as u64 * size.blocks())
-                                    .div_ceil(self.fs_config.block_size),
+                                    .div_ceil(STAT_BLOCK_SIZE),
+                InoKind::Blocks => (BlockInfo::line_len(self.fs_config.hash_len) as u64 * size.blocks())
+                                    .div_ceil(STAT_BLOCK_SIZE),
+                InoKind::Stats => 0,
             },
             atime: atime.unwrap_or(size.last_change),
             mtime: size.last_change.max(attr.last_change),
             ctime: attr.last_change,
             crtime: desc.creation_time,
-            kind: map_storage_dir_item_kind_to_file_type(desc.typ),
+            kind: match kind {
+                InoKind::Stats => FileType::RegularFile,
+                _ => map_storage_dir_item_kind_to_file_type(desc.typ),
+            },
             perm: attr.perm.0,
             nlink: 1,
             uid: attr.uid,
@@ -633,7 +891,7 @@ impl TiFs {
             move |_, txn| {
                 let name = name.clone();
                 Box::pin(async move {
-                    Ok(txn.hash_fs.directory_child_get_all_attributes(parent, name).await?)
+                    txn.lookup_child_all_attributes(parent, name).await
                 })
             }).await?;
         let stat = self.map_storage_attr_to_fuser(
@@ -652,7 +910,7 @@ impl TiFs {
             move |_, txn| {
                 let name = name.clone();
                 Box::pin(async move {
-                    Ok(txn.hash_fs.directory_child_get_all_attributes(parent, name).await?)
+                    txn.lookup_child_all_attributes(parent, name).await
                 })
             }).await?;
         let stat = self.map_storage_attr_to_fuser(
@@ -698,7 +956,23 @@ impl TiFs {
             }).await?;
         let stat = self.map_storage_attr_to_fuser(
             kind, &desc, &size, &attr, Some(atime.0));
-        Ok(super::reply::Entry::new(stat, 0))
+        Ok(super::reply::Entry::new(self.entry_ttl(kind), stat, 0))
+    }
+
+    /// Validity of attributes in the kernel. Virtual files are derived from other data and not cached.
+    pub fn attr_ttl(&self, kind: InoKind) -> Duration {
+        match kind {
+            InoKind::Regular => self.fs_config.attr_ttl,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Validity of name lookups in the kernel.
+    pub fn entry_ttl(&self, kind: InoKind) -> Duration {
+        match kind {
+            InoKind::Regular => self.fs_config.entry_ttl,
+            _ => Duration::ZERO,
+        }
     }
 
     pub async fn get_all_file_attributes(&self, l_ino: LogicalIno
@@ -706,14 +980,17 @@ impl TiFs {
         self.get_all_file_attributes_storage_ino(l_ino.storage_ino(), l_ino.kind).await
     }
 
+    /// Reads one page of the directory, starting after the child named start_after.
+    /// Returns the entries and the cursor for the next page (None if this was the last page).
     #[tracing::instrument]
-    pub async fn read_dir1(&self, dir_ino: StorageIno) -> Result<Directory> {
+    pub async fn read_dir_page(&self, dir_ino: StorageIno, start_after: Option<ByteString>
+    ) -> Result<(Directory, Option<ByteString>)> {
         let arc = self.weak.upgrade().unwrap();
-        let dir = arc.spin_no_delay(format!("read_dir"),
-            move |_, txn| Box::pin(txn.read_dir(dir_ino)))
+        let (dir, next_cursor) = arc.spin_no_delay(format!("read_dir"),
+            move |_, txn| Box::pin(txn.read_dir(dir_ino, start_after.clone(), READ_DIR_PAGE_SIZE)))
             .await?;
 
-        let mut dir_complete = Vec::with_capacity(dir.len() * 3);
+        let mut dir_complete = Vec::with_capacity(dir.len() * 6);
         for DirectoryItem{ino, name, typ} in dir.into_iter() {
             if typ == StorageDirItemKind::File {
                 let full_hash_entry = DirItem {
@@ -732,8 +1009,35 @@ impl TiFs {
                     name: format!(".{}.@hashes", &name),
                     typ: FileType::RegularFile,
                 };
+                let blocks_entry = DirItem {
+                    ino: LogicalIno{
+                        storage_ino: ino,
+                        kind: InoKind::Blocks,
+                    },
+                    name: format!(".{}.@blocks", &name),
+                    typ: FileType::RegularFile,
+                };
+                let merkle_entry = DirItem {
+                    ino: LogicalIno{
+                        storage_ino: ino,
+                        kind: InoKind::Merkle,
+                    },
+                    name: format!(".{}.@merkle", &name),
+                    typ: FileType::RegularFile,
+                };
+                let stats_entry = DirItem {
+                    ino: LogicalIno{
+                        storage_ino: ino,
+                        kind: InoKind::Stats,
+                    },
+                    name: format!(".{}.@stats", &name),
+                    typ: FileType::RegularFile,
+                };
                 dir_complete.push(full_hash_entry);
                 dir_complete.push(block_hashes_entry);
+                dir_complete.push(blocks_entry);
+                dir_complete.push(merkle_entry);
+                dir_complete.push(stats_entry);
             }
             let regular_entry = DirItem {
                 ino: LogicalIno{
@@ -746,7 +1050,7 @@ impl TiFs {
             dir_complete.push(regular_entry);
         }
 
-        if dir_ino == crate::fs::key::ROOT_INODE.0 {
+        if next_cursor.is_none() && (dir_ino == crate::fs::key::ROOT_INODE.0) {
             dir_complete.push(DirItem {
                 ino: LogicalIno {
                     storage_ino: crate::fs::key::OPENED_INODE_PARENT_INODE.0,
@@ -763,10 +1067,164 @@ impl TiFs {
                 name: format!("{}", crate::fs::key::SNAPSHOT_PARENT_INODE_NAME),
                 typ: FileType::Directory,
             });
+            dir_complete.push(DirItem {
+                ino: LogicalIno {
+                    storage_ino: ROOT_INODE.0,
+                    kind: InoKind::Stats,
+                },
+                name: format!("{}", FS_STATS_FILE_NAME),
+                typ: FileType::RegularFile,
+            });
         }
 
-        trace!("read_dir1 - out: {dir_complete:?}");
-        Ok(dir_complete)
+        trace!("read_dir_page - out: {dir_complete:?}");
+        Ok((dir_complete, next_cursor))
+    }
+
+    /// Same entries as read_dir_page, but with the attributes of each entry.
+    /// All attributes of a page are fetched in a single call to the hash fs.
+    #[tracing::instrument]
+    pub async fn read_dir_plus_page(&self, dir_ino: StorageIno, start_after: Option<ByteString>
+    ) -> Result<(DirectoryPlus, Option<ByteString>)> {
+        let arc = self.weak.upgrade().unwrap();
+        let (dir, next_cursor) = arc.spin_no_delay(format!("read_dir_plus"),
+            move |_, txn| Box::pin(txn.read_dir_with_attributes(
+                dir_ino, start_after.clone(), READ_DIR_PAGE_SIZE)))
+            .await?;
+
+        let mut dir_complete = Vec::with_capacity(dir.len() * 6);
+        for (DirectoryItem{ino, name, typ}, all) in dir.into_iter() {
+            let mut entries = Vec::with_capacity(6);
+            if typ == StorageDirItemKind::File {
+                for (kind, virtual_name) in [
+                    (InoKind::Hash, format!(".{}.@hash", &name)),
+                    (InoKind::Hashes, format!(".{}.@hashes", &name)),
+                    (InoKind::Blocks, format!(".{}.@blocks", &name)),
+                    (InoKind::Merkle, format!(".{}.@merkle", &name)),
+                    (InoKind::Stats, format!(".{}.@stats", &name)),
+                ] {
+                    entries.push((kind, virtual_name, FileType::RegularFile));
+                }
+            }
+            entries.push((InoKind::Regular, name, map_storage_dir_item_kind_to_file_type(typ)));
+
+            for (kind, name, typ) in entries {
+                let ino = LogicalIno { storage_ino: ino, kind };
+                let entry = match &all {
+                    Some((desc, attr, size, atime)) => Entry::new(self.entry_ttl(kind),
+                        self.map_storage_attr_to_fuser(kind, desc, size, attr, Some(atime.0)), 0),
+                    None => self.read_dir_plus_missing_entry(ino, typ).await,
+                };
+                dir_complete.push((DirItem { ino, name, typ }, entry));
+            }
+        }
+
+        if next_cursor.is_none() && (dir_ino == crate::fs::key::ROOT_INODE.0) {
+            let root_stat = self.get_all_file_attributes_storage_ino(
+                ROOT_INODE.0, InoKind::Regular).await?;
+            for (storage_ino, name) in [
+                (OPENED_INODE_PARENT_INODE.0, crate::fs::key::OPENED_INODE_PARENT_INODE_NAME),
+                (SNAPSHOT_PARENT_INODE.0, crate::fs::key::SNAPSHOT_PARENT_INODE_NAME),
+            ] {
+                let ino = LogicalIno {
+                    storage_ino,
+                    kind: InoKind::Regular,
+                };
+                let mut stat = root_stat.clone();
+                stat.ino = ino.to_raw();
+                dir_complete.push((DirItem {
+                    ino,
+                    name: format!("{}", name),
+                    typ: FileType::Directory,
+                }, Entry::new(self.entry_ttl(InoKind::Regular), stat, 0)));
+            }
+            let stats_stat = self.get_all_file_attributes_storage_ino(
+                ROOT_INODE.0, InoKind::Stats).await?;
+            dir_complete.push((DirItem {
+                ino: LogicalIno {
+                    storage_ino: ROOT_INODE.0,
+                    kind: InoKind::Stats,
+                },
+                name: format!("{}", FS_STATS_FILE_NAME),
+                typ: FileType::RegularFile,
+            }, Entry::new(self.entry_ttl(InoKind::Stats), stats_stat, 0)));
+        }
+
+        trace!("read_dir_plus_page - out: {dir_complete:?}");
+        Ok((dir_complete, next_cursor))
+    }
+
+    /// Entry of a child whose attributes were missing in the listing, e.g. as it
+    /// changed concurrently. Without attributes, placeholder ones with a ttl of 0
+    /// are returned, such that the kernel looks the entry up before using it.
+    async fn read_dir_plus_missing_entry(&self, ino: LogicalIno, typ: FileType) -> Entry {
+        match self.get_all_file_attributes_storage_ino(ino.storage_ino, ino.kind).await {
+            Ok(stat) => Entry::new(self.entry_ttl(ino.kind), stat, 0),
+            Err(err) => {
+                debug!("read_dir_plus - attributes of {ino:?} are missing: {err:?}");
+                let stat = FileAttr {
+                    ino: ino.to_raw(),
+                    size: 0,
+                    blocks: 0,
+                    atime: SystemTime::UNIX_EPOCH,
+                    mtime: SystemTime::UNIX_EPOCH,
+                    ctime: SystemTime::UNIX_EPOCH,
+                    crtime: SystemTime::UNIX_EPOCH,
+                    kind: typ,
+                    perm: 0,
+                    nlink: 1,
+                    uid: 0,
+                    gid: 0,
+                    rdev: 0,
+                    blksize: self.fs_config.block_size as u32,
+                    flags: 0,
+                };
+                Entry::new(Duration::ZERO, stat, 0)
+            }
+        }
+    }
+
+    /// Returns the directory entries starting at the given readdir offset.
+    /// The offsets are positions in the complete listing. The dir handler
+    /// remembers where each fetched page started, so a call only needs to
+    /// fetch the page that contains the offset. The kernel reads a page with
+    /// several calls, these are answered from the last fetched page.
+    /// Offset 0 (rewinddir) always fetches again.
+    pub async fn read_dir_at_offset<T, F, Fut>(
+        &self,
+        dir_handler: &DirHandler,
+        offset: usize,
+        fetch_page: F,
+    ) -> Result<Vec<T>>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Fn(Option<ByteString>) -> Fut,
+        Fut: Future<Output = Result<(Vec<T>, Option<ByteString>)>>,
+    {
+        if offset > 0 {
+            if let Some(items) = dir_handler.cached_items_from::<T>(offset).await {
+                return Ok(items);
+            }
+        }
+        let (mut page_start, mut cursor) = dir_handler.page_start_for(offset).await;
+        loop {
+            let (items, next_cursor) = fetch_page(cursor).await?;
+            let page_end = page_start + items.len();
+            if let Some(next) = &next_cursor {
+                dir_handler.register_page_start(page_end, Some(next.clone())).await;
+            }
+            match next_cursor {
+                Some(next) if offset >= page_end => {
+                    page_start = page_end;
+                    cursor = Some(next);
+                }
+                next_cursor => {
+                    let result = items.iter().skip(offset - page_start).cloned().collect();
+                    dir_handler.cache_page(page_start, items, next_cursor.is_none()).await;
+                    return Ok(result);
+                }
+            }
+        }
     }
 /*
     pub async fn setlkw(
@@ -872,3 +1330,20 @@ impl Debug for TiFs {
         f.write_fmt(format_args!("TiFs"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytestring::ByteString;
+
+    use super::parse_filename;
+    use crate::fs::reply::InoKind;
+
+    #[test]
+    fn parses_virtual_file_names() {
+        assert_eq!(parse_filename(ByteString::from(".a.@hashes")), (ByteString::from("a"), InoKind::Hashes));
+        assert_eq!(parse_filename(ByteString::from(".a.@hash")), (ByteString::from("a"), InoKind::Hash));
+        for bare in [".@stats", ".@blocks", ".@merkle", ".@hash", "..@hash"] {
+            assert_eq!(parse_filename(ByteString::from(bare)), (ByteString::from(bare), InoKind::Regular));
+        }
+    }
+}
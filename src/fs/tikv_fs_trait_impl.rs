@@ -1,4 +1,4 @@
-use std::{mem, sync::Arc, time::{Duration, SystemTime}};
+use std::{mem, sync::Arc, time::SystemTime};
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -9,13 +9,13 @@ use tracing::{debug, trace};
 
 use crate::fs::{error::{FsError, Result}, inode::StorageFilePermission, reply::InoKind};
 
-use super::{async_fs::AsyncFileSystem, file_handler::FileHandler, hash_fs_interface::GotOrMade};
-use super::inode::{ParentStorageIno, StorageDirItem};
+use super::{async_fs::AsyncFileSystem, dir_handler::DirHandler, file_handler::FileHandler, hash_fs_interface::{BlockIndex, GotOrMade, RenameMode}};
+use super::inode::{ParentStorageIno, StorageDirItem, StorageDirItemKind};
 use super::key::{check_file_name, ROOT_INODE};
 use super::open_modes::OpenMode;
 use super::mode::{as_file_kind, as_file_perm};
 use super::tikv_fs::{map_file_type_to_storage_dir_item_kind, parse_filename, InoUse, TiFs, TiFsMutable};
-use super::reply::{get_time, Attr, Create, Data, Dir, Entry, LogicalIno, Open, StatFs, Write, Xattr};
+use super::reply::{Attr, Create, Data, Dir, DirPlus, Entry, LogicalIno, Open, Sleek, StatFs, Write, Xattr};
 
 
 
@@ -35,6 +35,8 @@ impl AsyncFileSystem for TiFs {
             .expect("kernel config failed to add cap_fuse FUSE_BIG_WRITES");
         config.add_capabilities(fuser::consts::FUSE_PARALLEL_DIROPS)
             .expect("kernel config failed to add cap_fuse FUSE_PARALLEL_DIROPS");
+        config.add_capabilities(fuser::consts::FUSE_DO_READDIRPLUS | fuser::consts::FUSE_READDIRPLUS_AUTO)
+            .expect("kernel config failed to add cap_fuse FUSE_DO_READDIRPLUS");
         let _ = config.set_max_write(self.fs_config.block_size as u32 * 128);
 
         if let Err(next_working) = config.set_max_readahead(self.fs_config.block_size as u32 * 100) {
@@ -53,10 +55,16 @@ impl AsyncFileSystem for TiFs {
 
         self.weak.upgrade().unwrap().check_metadata().await?;
         tokio::spawn(Self::heartbeat(self.weak.clone()));
+        tokio::spawn(Self::meta_cache_revalidation(self.weak.clone()));
+        tokio::spawn(Self::watch_changes_task(self.weak.clone()));
 
         Ok(())
     }
 
+    fn set_kernel_notifier(&self, notifier: fuser::Notifier) {
+        let _ = self.kernel_notifier.set(notifier);
+    }
+
     #[tracing::instrument]
     async fn lookup(&self, parent: u64, name: ByteString) -> Result<Entry> {
         let p_ino = LogicalIno::from_raw(parent);
@@ -79,35 +87,32 @@ impl AsyncFileSystem for TiFs {
                     }.to_raw();
                     Some(stat)
                 }
+                crate::fs::key::FS_STATS_FILE_NAME => {
+                    Some(self.get_all_file_attributes_storage_ino(ROOT_INODE.0, InoKind::Stats).await?)
+                }
                 _ => None,
             }
         } else { None };
 
-        let attrs = if let Some(attrs) = special {
-            attrs
+        let (attrs, kind) = if let Some(attrs) = special {
+            (attrs, InoKind::Regular)
         } else {
             let (filename, kind) = parse_filename(name);
 
             check_file_name(&filename)?;
             let stat = self.lookup_all_info_logical(
                 ParentStorageIno(p_ino.storage_ino()), filename, kind).await?;
-            stat
-        };
-
-        let entry = Entry {
-            generation: 0,
-            time: Duration::from_millis(0),
-            stat: attrs,
+            (stat, kind)
         };
 
-        Ok(entry)
+        Ok(Entry::new(self.entry_ttl(kind), attrs, 0))
     }
 
     #[tracing::instrument]
     async fn getattr(&self, ino: u64) -> Result<Attr> {
         let l_ino = LogicalIno::from_raw(ino);
         let stat = self.get_all_file_attributes(l_ino).await?;
-        Ok(Attr::new(stat))
+        Ok(Attr::new(self.attr_ttl(l_ino.kind), stat))
     }
 
     #[tracing::instrument]
@@ -128,8 +133,24 @@ impl AsyncFileSystem for TiFs {
         flags: Option<u32>,
     ) -> Result<Attr> {
         let l_ino = LogicalIno::from_raw(ino);
-        if l_ino.kind != InoKind::Regular {
-            return Err(FsError::InoKindNotSupported(l_ino.kind));
+        match (l_ino.kind, size) {
+            (InoKind::Regular, _) => {}
+            (InoKind::Hashes, Some(size)) => {
+                // truncating the hashes file truncates the file to the remaining blocks.
+                // Other attributes of the virtual file are ignored.
+                let hash_size = self.fs_config.hash_len as u64;
+                if size % hash_size != 0 {
+                    return Err(FsError::InvalidOffset { ino, offset: size as i64 });
+                }
+                let storage_ino = l_ino.storage_ino();
+                self.spin_no_delay(format!("setattr, truncate hashes"), move |_, txn| {
+                    txn.set_blocks_from_hashes(
+                        storage_ino, BlockIndex(size / hash_size), vec![], true).boxed()
+                }).await?;
+                let attr = self.get_all_file_attributes(l_ino).await?;
+                return Ok(Attr::new(self.attr_ttl(l_ino.kind), attr));
+            }
+            (other, _) => return Err(FsError::InoKindNotSupported(other)),
         }
 
         self.spin_no_delay(format!("setattr"), move |_, txn| {
@@ -155,24 +176,57 @@ impl AsyncFileSystem for TiFs {
 
         let attr = self.get_all_file_attributes(l_ino).await?;
 
-        Ok(Attr {
-            time: get_time(),
-            attr,
-        })
+        Ok(Attr::new(self.attr_ttl(l_ino.kind), attr))
     }
 
     #[tracing::instrument]
-    async fn readdir(&self, ino: u64, _fh: u64, offset: i64) -> Result<Dir> {
+    async fn opendir(&self, ino: u64, _flags: i32) -> Result<Open> {
         let l_ino = LogicalIno::from_raw(ino);
+        let handler = Arc::new(DirHandler::new(l_ino.storage_ino()));
+        let fh = self.with_mut_data(|d| {
+            let fh = d.get_free_fh();
+            d.dir_handlers.insert(fh, handler);
+            fh
+        }).await?;
+        Ok(Open::new(fh, 0))
+    }
+
+    #[tracing::instrument]
+    async fn readdir(&self, ino: u64, fh: u64, offset: i64) -> Result<Dir> {
+        let l_ino = LogicalIno::from_raw(ino);
+        let dir_handler = self.get_dir_handler_checked(fh).await?;
         let mut dir = Dir::offset(offset as usize);
-        let directory = self.read_dir1(l_ino.storage_ino()).await?;
-        for item in directory.into_iter().skip(offset as usize) {
+        let directory = self.read_dir_at_offset(
+            &dir_handler, offset as usize,
+            |start_after| self.read_dir_page(l_ino.storage_ino(), start_after)).await?;
+        for item in directory.into_iter() {
             dir.push(item)
         }
         debug!("read directory {:?}", &dir);
         Ok(dir)
     }
 
+    #[tracing::instrument]
+    async fn readdirplus(&self, ino: u64, fh: u64, offset: i64) -> Result<DirPlus> {
+        let l_ino = LogicalIno::from_raw(ino);
+        let dir_handler = self.get_dir_handler_checked(fh).await?;
+        let mut dir = DirPlus::offset(offset as usize);
+        let directory = self.read_dir_at_offset(
+            &dir_handler, offset as usize,
+            |start_after| self.read_dir_plus_page(l_ino.storage_ino(), start_after)).await?;
+        for (item, entry) in directory.into_iter() {
+            dir.push(item, entry)
+        }
+        debug!("read directory plus {:?}", &dir);
+        Ok(dir)
+    }
+
+    #[tracing::instrument]
+    async fn releasedir(&self, _ino: u64, fh: u64, _flags: i32) -> Result<()> {
+        self.with_mut_data(|d| d.release_dir_handler(fh)).await?;
+        Ok(())
+    }
+
     #[tracing::instrument]
     async fn open(&self, ino: u64, flags: i32) -> Result<Open> {
 
@@ -216,6 +270,10 @@ impl AsyncFileSystem for TiFs {
         }).await?;
 
         let mut open_flags = 0;
+        // the size of generated content isn't known in advance:
+        if l_ino.kind == InoKind::Stats {
+            open_flags |= FOPEN_DIRECT_IO;
+        }
         #[cfg(target_os = "linux")]
         if self.direct_io || flags & libc::O_DIRECT != 0 {
             open_flags |= FOPEN_DIRECT_IO;
@@ -257,7 +315,10 @@ impl AsyncFileSystem for TiFs {
         let data = match l_ino.kind {
             InoKind::Regular => self.read_kind_regular(l_ino.storage_ino(), file_handler, start as u64, size, flags, lock_owner).await,
             InoKind::Hash => self.read_kind_hash(l_ino.storage_ino(), start as u64, size).await,
+            InoKind::Merkle => self.read_kind_merkle(l_ino.storage_ino(), start as u64, size).await,
             InoKind::Hashes => self.read_file_kind_hashes(l_ino.storage_ino(), start as u64, size).await,
+            InoKind::Blocks => self.read_kind_blocks(l_ino.storage_ino(), start as u64, size).await,
+            InoKind::Stats => self.read_kind_stats(l_ino.storage_ino(), file_handler, start as u64, size).await,
         }?;
 
         trace!("read() result len: {}", data.data.len());
@@ -291,6 +352,15 @@ impl AsyncFileSystem for TiFs {
             return Err(FsError::InvalidOffset { ino, offset: start });
         }
 
+        match l_ino.kind {
+            InoKind::Regular => {}
+            InoKind::Hashes => {
+                self.write_kind_hashes(l_ino.storage_ino(), start as u64, data).await?;
+                return Ok(Write::new(size as u32));
+            }
+            other => return Err(FsError::InoKindNotSupported(other)),
+        }
+
         let mut write_cache = file_handler.write_cache.write().await;
         let fh_clone = file_handler.clone();
         let arc = self.weak.upgrade().unwrap();
@@ -352,6 +422,14 @@ impl AsyncFileSystem for TiFs {
         let p_ino = LogicalIno::from_raw(parent);
         let perm = StorageFilePermission(as_file_perm(mode));
         let typ = map_file_type_to_storage_dir_item_kind(as_file_kind(mode))?;
+        // creating a virtual file creates the regular file it belongs to.
+        // Only the hashes file can be written, all other kinds are rejected:
+        let (name, kind) = parse_filename(name);
+        match (kind, typ) {
+            (InoKind::Regular, _) => {}
+            (InoKind::Hashes, StorageDirItemKind::File) => {}
+            (other, _) => return Err(FsError::InoKindNotSupported(other)),
+        }
         check_file_name(&name)?;
         let name_clone = name.clone();
         let item: GotOrMade<StorageDirItem> = self
@@ -364,7 +442,7 @@ impl AsyncFileSystem for TiFs {
             return Err(FsError::FileExist { file: name.into() });
         }
 
-        self.get_all_file_attributes_storage_ino_entry_reply(item.value().ino, InoKind::Regular).await
+        self.get_all_file_attributes_storage_ino_entry_reply(item.value().ino, kind).await
     }
 
     #[tracing::instrument]
@@ -382,58 +460,53 @@ impl AsyncFileSystem for TiFs {
         umask: u32,
         flags: i32,
     ) -> Result<Create> {
-        check_file_name(&name)?;
         let entry = self.mknod(parent, name, mode, gid, uid, umask, 0).await?;
         let open = self.open(entry.stat.ino, flags).await?;
         Ok(Create::new(
+            entry.time,
             entry.stat,
             entry.generation,
             open.fh,
             open.flags,
         ))
     }
-/*
-    async fn lseek(&self, ino: u64, fh: u64, offset: i64, whence: i32) -> Result<Lseek> {
-        let l_ino = LogicalIno::from_raw(ino);
-        eprintln!("lseek-begin(ino:{ino},fh:{fh},offset:{offset},whence:{whence}");
-        let file_handler = self.get_file_handler_checked(fh).await?;
-        let file_handler_data = file_handler.mut_data.read().await;
-        let current_cursor = file_handler_data.cursor.get(&l_ino.kind).cloned().unwrap_or(0);
-        let result = self.spin_no_delay(format!("lseek"), move |_, txn| {
-            Box::pin(async move {
 
-                let target_cursor = match whence {
-                    SEEK_SET => offset,
-                    SEEK_CUR => current_cursor as i64 + offset,
-                    SEEK_END => {
-                        let inode = txn.read_ino_size(l_ino.storage_ino()).await?;
-                        inode.size() as i64 + offset
-                    }
-                    _ => return Err(FsError::UnknownWhence { whence }),
-                };
-
-                if target_cursor < 0 {
-                    return Err(FsError::InvalidOffset {
-                        ino,
-                        offset: target_cursor,
-                    });
+    async fn lseek(&self, ino: u64, fh: u64, offset: i64, whence: i32) -> Result<Sleek> {
+        let l_ino = LogicalIno::from_raw(ino);
+        // the file position itself is maintained by the kernel.
+        // Only make sure that the handle is valid:
+        let _file_handler = self.get_file_handler_checked(fh).await?;
+
+        let target = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_END => {
+                let attr = self.get_all_file_attributes(l_ino).await?;
+                attr.size as i64 + offset
+            }
+            libc::SEEK_DATA | libc::SEEK_HOLE if l_ino.kind == InoKind::Regular => {
+                self.flush_write_cache(fh).await?;
+                let seek_data = whence == libc::SEEK_DATA;
+                self.spin_no_delay(format!("lseek"), move |_, txn| {
+                    Box::pin(txn.seek_data_or_hole(l_ino.storage_ino(), offset, seek_data))
+                }).await?
+            }
+            libc::SEEK_DATA | libc::SEEK_HOLE => {
+                // virtual files have no holes
+                let size = self.get_all_file_attributes(l_ino).await?.size as i64;
+                if offset >= size {
+                    return Err(FsError::NoDataOrHoleAfterOffset { ino, offset });
                 }
-                Ok(Lseek::new(target_cursor))
-            })
-        })
-        .await?;
-
-        self.with_mut_data(|d| -> Result<()> {
-            let file_handler = d.file_handlers.get_mut(&fh).ok_or(FsError::FhNotFound { fh: fh })?;
-            file_handler.mut_data.blocking_write().cursor.insert(l_ino.kind, result.offset as u64);
-            Ok(())
-        }).await??;
-
-        eprintln!("lseek-ok(ino:{ino},fh:{fh},offset:{offset},whence:{whence},current_cursor:{current_cursor},new_cursor:{})", result.offset);
+                if whence == libc::SEEK_DATA { offset } else { size }
+            }
+            _ => return Err(FsError::UnknownWhence { whence }),
+        };
 
-        Ok(result)
+        if target < 0 {
+            return Err(FsError::InvalidOffset { ino, offset: target });
+        }
+        Ok(Sleek::new(target))
     }
-*/
+
     async fn release(
         &self,
         _ino: u64,
@@ -479,19 +552,22 @@ impl AsyncFileSystem for TiFs {
         raw_name: ByteString,
         new_parent: u64,
         new_raw_name: ByteString,
-        _flags: u32,
+        flags: u32,
     ) -> Result<()> {
+        let mode = RenameMode::from_flags(flags)
+            .ok_or(FsError::InvalidRenameFlags { flags })?;
         let p_ino = LogicalIno::from_raw(parent);
         let np_ino = LogicalIno::from_raw(new_parent);
         self.spin_no_delay(format!("rename"), move |_, txn| {
             let name = raw_name.clone();
             let new_name = new_raw_name.clone();
             Box::pin(async move {
-                txn.hash_fs.directory_rename_child(
+                txn.rename(
                     ParentStorageIno(p_ino.storage_ino()),
                     name,
                     ParentStorageIno(np_ino.storage_ino()),
-                    new_name).await.map_err(|e|e.into())
+                    new_name,
+                    mode).await
             })
         })
         .await
@@ -513,10 +589,8 @@ impl AsyncFileSystem for TiFs {
             self.spin_no_delay(format!("inode for symlink"), move |_, txn| {
             let name = name_clone1.clone();
             let link_data_vec = link.clone();
-            Box::pin(async move {
-                Ok(txn.hash_fs.directory_add_new_symlink(
-                    gid, uid, ParentStorageIno(p_ino.storage_ino()), name, link_data_vec.clone()).await?)
-            })
+            Box::pin(txn.symlink(
+                gid, uid, ParentStorageIno(p_ino.storage_ino()), name, link_data_vec))
         }).await?;
 
         self.get_all_file_attributes_storage_ino_entry_reply(item.ino, InoKind::Regular).await
@@ -538,17 +612,19 @@ impl AsyncFileSystem for TiFs {
         fh: u64,
         offset: i64,
         length: i64,
-        _mode: i32,
+        mode: i32,
     ) -> Result<()> {
         let l_ino: LogicalIno = LogicalIno::from_raw(ino);
         if l_ino.kind != InoKind::Regular {
             return Err(FsError::InoKindNotSupported(l_ino.kind));
         }
+        // pending writes need to be applied before the block assignments are modified:
+        self.flush_write_cache(fh).await?;
         let fh = self.get_file_handler_checked(fh).await?;
         self.spin_no_delay(format!("fallocate"), move |_, txn| {
             let fh = fh.clone();
             Box::pin(async move {
-                txn.f_allocate(fh.clone(), l_ino.storage_ino(), offset, length).await
+                txn.f_allocate(fh.clone(), l_ino.storage_ino(), offset, length, mode).await
             })
         })
         .await?;
@@ -719,3 +795,151 @@ impl AsyncFileSystem for TiFs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::{BTreeMap, HashMap, HashSet}, ops::Range, sync::{Arc, Mutex}, time::SystemTime};
+
+    use bytestring::ByteString;
+    use fuser::TimeOrNow;
+    use num_bigint::BigUint;
+    use uuid::Uuid;
+
+    use crate::fs::async_fs::AsyncFileSystem;
+    use crate::fs::error::FsError;
+    use crate::fs::fs_stats::{FileStats, FsStats};
+    use crate::fs::hash_fs_interface::*;
+    use crate::fs::inode::*;
+    use crate::fs::key::ROOT_INODE;
+    use crate::fs::meta::{MetaMutable, MetaStatic};
+    use crate::fs::quota::{QuotaLimits, QuotaReport, QuotaTarget};
+    use crate::fs::reply::{InoKind, LogicalIno};
+    use crate::fs::tikv_fs::TiFs;
+
+    /// Stores a single created file and records the hashes written to it.
+    #[derive(Default)]
+    struct SingleFileHashFs {
+        created: Mutex<Option<(ByteString, StorageDirItem)>>,
+        set_hashes: Mutex<Vec<(StorageIno, BlockIndex, Vec<TiFsHash>, bool)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HashFsInterface for SingleFileHashFs {
+        async fn init(&self, _gid: u32, _uid: u32) -> HashFsResult<StorageDirItem> { unimplemented!() }
+        async fn meta_static_read(&self) -> HashFsResult<MetaStatic> { unimplemented!() }
+        async fn meta_mutable_read(&self) -> HashFsResult<MetaMutable> { unimplemented!() }
+        async fn directory_read_children(&self, _dir_ino: StorageIno) -> HashFsResult<Vec<DirectoryItem>> { unimplemented!() }
+        async fn directory_read_children_page(&self, _dir_ino: StorageIno, _start_after: Option<ByteString>, _limit: u32,
+        ) -> HashFsResult<DirectoryPage<DirectoryItem>> { unimplemented!() }
+        async fn directory_read_children_with_attributes(&self, _dir_ino: StorageIno, _start_after: Option<ByteString>, _limit: u32,
+        ) -> HashFsResult<DirectoryPage<(DirectoryItem, Option<InoAllAttributes>)>> { unimplemented!() }
+        async fn directory_add_child_checked_new_inode(
+            &self,
+            _parent: ParentStorageIno,
+            name: ByteString,
+            typ: StorageDirItemKind,
+            _perm: StorageFilePermission,
+            _gid: u32,
+            _uid: u32,
+            _rdev: u32,
+            _inline_data: Option<Vec<u8>>,
+        ) -> HashFsResult<GotOrMade<StorageDirItem>> {
+            let item = StorageDirItem { ino: StorageIno(2), typ };
+            *self.created.lock().unwrap() = Some((name, item.clone()));
+            Ok(GotOrMade::NewlyCreated(item))
+        }
+        async fn directory_add_child_checked_existing_inode(&self, _parent: ParentStorageIno, _name: ByteString, _ino: StorageIno,
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn directory_remove_child_file(&self, _parent: ParentStorageIno, _name: ByteString) -> HashFsResult<()> { unimplemented!() }
+        async fn directory_remove_child_directory(&self, _parent: ParentStorageIno, _name: ByteString) -> HashFsResult<()> { unimplemented!() }
+        async fn directory_rename_child(&self, _parent: ParentStorageIno, _child_name: ByteString,
+            _new_parent: ParentStorageIno, _new_child_name: ByteString, _mode: RenameMode,
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn directory_child_get_all_attributes(&self, _parent: ParentStorageIno, _name: ByteString,
+        ) -> HashFsResult<InoAllAttributes> { unimplemented!() }
+        async fn directory_add_new_symlink(&self, _gid: u32, _uid: u32, _parent: ParentStorageIno, _name: ByteString, _link: ByteString,
+        ) -> HashFsResult<StorageDirItem> { unimplemented!() }
+        async fn inode_get_all_attributes(&self, ino: StorageIno) -> HashFsResult<InoAllAttributes> {
+            let now = SystemTime::now();
+            Ok((
+                Arc::new(InoDescription { ino, typ: StorageDirItemKind::File, creation_time: now }),
+                Arc::new(InoStorageFileAttr { perm: StorageFilePermission(0o644), uid: 0, gid: 0, rdev: 0, flags: 0, last_change: now }),
+                Arc::new(InoSize::new()),
+                InoAccessTime(now),
+            ))
+        }
+        async fn inode_get_change_iteration_ids(&self, inos: &[StorageIno]) -> HashFsResult<HashMap<StorageIno, InoChangeIterationId>> {
+            Ok(inos.iter().map(|ino| (*ino, InoChangeIterationId::random())).collect())
+        }
+        async fn inode_set_all_attributes(&self, _ino: StorageIno, _mode: Option<StorageFilePermission>, _uid: Option<u32>,
+            _gid: Option<u32>, _size: Option<u64>, _atime: Option<TimeOrNow>, _mtime: Option<TimeOrNow>,
+            _ctime: Option<SystemTime>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>,
+            _bkuptime: Option<SystemTime>, _flags: Option<u32>,
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_open(&self, _ino: StorageIno) -> HashFsResult<Uuid> { Ok(Uuid::new_v4()) }
+        async fn inode_close(&self, _ino: StorageIno, _use_id: Uuid) -> HashFsResult<()> { Ok(()) }
+        async fn inode_allocate_size(&self, _ino: StorageIno, _offset: i64, _length: i64) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_read_inline_data(&self, _ino: StorageIno) -> HashFsResult<Vec<u8>> { unimplemented!() }
+        async fn inode_read_block_hashes_data_range(&self, _ino: StorageIno, _start: u64, _read_size: u64,
+        ) -> HashFsResult<BTreeMap<BlockIndex, TiFsHash>> { unimplemented!() }
+        async fn inode_read_block_hashes_block_range(&self, _ino: StorageIno, _block_ranges: &[Range<BlockIndex>],
+        ) -> HashFsResult<BTreeMap<BlockIndex, TiFsHash>> { unimplemented!() }
+        async fn hb_get_reference_counts(&self, _hashes: &[TiFsHash], _source: &BlockDataSource,
+        ) -> HashFsResult<HashMap<TiFsHash, u64>> { unimplemented!() }
+        async fn inode_remove_block_range(&self, _ino: StorageIno, _block_range: Range<BlockIndex>, _collapse: bool,
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_seek_block_mapping(&self, _ino: StorageIno, _block_range: Range<BlockIndex>, _mapped: bool,
+        ) -> HashFsResult<Option<BlockIndex>> { unimplemented!() }
+        async fn hb_get_block_data_by_hashes(&self, _hashes: &HashSet<&TiFsHash>, _source: &BlockDataSource,
+        ) -> HashFsResult<HashMap<TiFsHash, Arc<Vec<u8>>>> { unimplemented!() }
+        async fn hb_get_block_data_by_hashes_stream(&self, _hashes: Vec<TiFsHash>, _source: BlockDataSource,
+        ) -> HashFsResult<BlockDataStream> { unimplemented!() }
+        async fn file_get_hash(&self, _ino: StorageIno) -> HashFsResult<Vec<u8>> { unimplemented!() }
+        async fn file_get_merkle_hash(&self, _ino: StorageIno) -> HashFsResult<TiFsHash> { unimplemented!() }
+        async fn file_read_block_hashes(&self, _ino: StorageIno, _block_range: Range<BlockIndex>) -> HashFsResult<Vec<u8>> { unimplemented!() }
+        async fn hb_increment_reference_count(&self, _blocks: &[(&TiFsHash, u64)]) -> HashFsResult<HashMap<TiFsHash, BigUint>> { unimplemented!() }
+        async fn hb_upload_new_block(&self, _blocks: &[(&TiFsHash, Arc<Vec<u8>>)]) -> HashFsResult<()> { unimplemented!() }
+        async fn hb_upload_new_block_stream(&self, _blocks: NewBlockStream) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_write_hash_block_to_addresses_update_ino_size_and_cleaning_previous_block_hashes(
+            &self, _ino: StorageIno, _blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_write_blocks(&self, _ino: StorageIno, _blocks: &[(&TiFsHash, u64, Vec<BlockIndex>)],
+            _missing_data: MissingBlocksProvider,
+        ) -> HashFsResult<()> { unimplemented!() }
+        async fn inode_set_blocks_from_hashes(&self, ino: StorageIno, first_block: BlockIndex, hashes: &[TiFsHash], truncate: bool,
+        ) -> HashFsResult<()> {
+            self.set_hashes.lock().unwrap().push((ino, first_block, hashes.to_vec(), truncate));
+            Ok(())
+        }
+        async fn snapshot_create(&self, _name: ByteString) -> HashFsResult<GotOrMade<StorageDirItem>> { unimplemented!() }
+        async fn quota_set(&self, _target: QuotaTarget, _limits: QuotaLimits) -> HashFsResult<()> { unimplemented!() }
+        async fn quota_list(&self) -> HashFsResult<Vec<QuotaReport>> { unimplemented!() }
+        async fn quota_report(&self, _target: QuotaTarget) -> HashFsResult<Option<QuotaReport>> { unimplemented!() }
+        async fn fs_stats(&self) -> HashFsResult<FsStats> { unimplemented!() }
+        async fn file_stats(&self, _ino: StorageIno) -> HashFsResult<FileStats> { unimplemented!() }
+        async fn watch_changes(&self) -> HashFsResult<ChangeEventStream> { unimplemented!() }
+    }
+
+    #[tokio::test]
+    async fn creating_hashes_file_creates_base_file_and_writes_hashes() {
+        let hash_fs = Arc::new(SingleFileHashFs::default());
+        let fs = TiFs::construct_hash_fs_client(Vec::<String>::new(), vec![], hash_fs.clone()).await.unwrap();
+        let root = LogicalIno { storage_ino: ROOT_INODE.0, kind: InoKind::Regular }.to_raw();
+        let mode = libc::S_IFREG as u32 | 0o644;
+
+        let created = fs.create(0, 0, root, ".a.@hashes".into(), mode, 0, libc::O_WRONLY).await.unwrap();
+        let (name, item) = hash_fs.created.lock().unwrap().clone().unwrap();
+        assert_eq!(name, ByteString::from("a"));
+        let l_ino = LogicalIno::from_raw(created.attr.ino);
+        assert_eq!(l_ino.kind, InoKind::Hashes);
+        assert_eq!(l_ino.storage_ino(), item.ino);
+
+        let hashes = [vec![1u8; 32], vec![2u8; 32]];
+        let written = fs.write(created.attr.ino, created.fh, 0, hashes.concat(), 0, 0, None).await.unwrap();
+        assert_eq!(written.size, 64);
+        assert_eq!(*hash_fs.set_hashes.lock().unwrap(), vec![(item.ino, BlockIndex(0), hashes.to_vec(), false)]);
+
+        let other = fs.create(0, 0, root, ".b.@hash".into(), mode, 0, libc::O_WRONLY).await;
+        assert_eq!(other.err(), Some(FsError::InoKindNotSupported(InoKind::Hash)));
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use super::quota::{QuotaState, QuotaTarget};
+
+/// Number of keys the changes of a usage counter are spread over.
+/// Transactions updating the same counter only conflict if they pick the same shard.
+pub const USAGE_SHARDS: u16 = 64;
+
+/// Counter whose changes are stored as deltas next to its base value.
+/// The base value is the usage in MetaMutable or in the QuotaState of the target.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum UsageCounter {
+    Fs,
+    Quota(QuotaTarget),
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UsageDelta {
+    pub logical_bytes: i64,
+    pub physical_blocks: i64,
+    pub inodes: i64,
+}
+
+impl UsageDelta {
+    pub fn new(logical_bytes: i64, physical_blocks: i64, inodes: i64) -> Self {
+        Self { logical_bytes, physical_blocks, inodes }
+    }
+
+    pub fn add(&mut self, other: &UsageDelta) {
+        self.logical_bytes = self.logical_bytes.saturating_add(other.logical_bytes);
+        self.physical_blocks = self.physical_blocks.saturating_add(other.physical_blocks);
+        self.inodes = self.inodes.saturating_add(other.inodes);
+    }
+
+    /// Quota with the deltas applied to its usage.
+    pub fn apply_to_quota(&self, mut state: QuotaState) -> QuotaState {
+        state.apply_usage_delta(self.logical_bytes, self.inodes);
+        state
+    }
+}
+
+/// Shard for the next update, such that concurrent writers pick different ones.
+pub fn random_usage_shard() -> u16 {
+    (uuid::Uuid::new_v4().as_u128() % USAGE_SHARDS as u128) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::quota::{QuotaLimits, QuotaUsage};
+
+    #[test]
+    fn deltas_are_summed_and_applied_to_the_base() {
+        let mut sum = UsageDelta::default();
+        sum.add(&UsageDelta::new(100, 2, 1));
+        sum.add(&UsageDelta::new(-30, -1, 1));
+        assert_eq!(sum, UsageDelta::new(70, 1, 2));
+
+        let base = QuotaState {
+            limits: QuotaLimits { max_bytes: Some(10), max_inodes: None },
+            usage: QuotaUsage { used_bytes: 5, used_inodes: 1 },
+        };
+        let state = sum.apply_to_quota(base);
+        assert_eq!(state.usage, QuotaUsage { used_bytes: 75, used_inodes: 3 });
+        assert!(random_usage_shard() < USAGE_SHARDS);
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fs::inode::TiFsHash;
+
+use super::hash_algorithm::{HashAlgorithm, StreamingHasher};
+
+/// How the hash of a whole file is derived from its block hashes.
+/// None of the variants needs to read block data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FullHashMode {
+    /// Hash of the concatenation of all block hashes.
+    /// Needs all block hashes of the file on every recomputation.
+    #[default]
+    Flat,
+    /// Binary hash tree over the block hashes, using the configured hash algorithm.
+    /// Left subtrees are complete (like the BLAKE3 tree layout) and
+    /// parent = hash(0x01 || left || right). This is a tifs specific hash, it is
+    /// not the BLAKE3 hash of the file content (e.g. b3sum) even with blake3 blocks.
+    /// The full hash of a single block file is its block hash.
+    /// Roots of full segments are stored, thus a recomputation only reads the
+    /// block hashes of changed segments and of the last partial segment.
+    MerkleTree,
+}
+
+impl FullHashMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(FullHashMode::Flat),
+            "merkle" => Some(FullHashMode::MerkleTree),
+            _ => None,
+        }
+    }
+}
+
+const PARENT_NODE_PREFIX: u8 = 0x01;
+
+/// Height of the subtree whose root is stored per segment in merkle tree mode.
+pub const FULL_HASH_SEGMENT_HEIGHT: u32 = 13;
+/// Blocks per stored subtree root. A power of two, such that each segment
+/// is a complete subtree of the file's tree.
+pub const FULL_HASH_SEGMENT_BLOCKS: u64 = 1 << FULL_HASH_SEGMENT_HEIGHT;
+
+/// First block of the segment that contains the block.
+pub fn full_hash_segment_start(block: u64) -> u64 {
+    block - block % FULL_HASH_SEGMENT_BLOCKS
+}
+
+/// Consumes the block hashes of a file in order, without keeping all of them in memory.
+pub struct FullHasher {
+    algorithm: HashAlgorithm,
+    state: FullHasherState,
+}
+
+enum FullHasherState {
+    Flat(StreamingHasher),
+    /// Roots of the complete subtrees that are not merged yet, with their height.
+    MerkleTree(Vec<(u32, TiFsHash)>),
+}
+
+impl FullHasher {
+    pub fn new(algorithm: HashAlgorithm, mode: FullHashMode) -> Self {
+        let state = match mode {
+            FullHashMode::Flat => FullHasherState::Flat(algorithm.streaming_hasher()),
+            FullHashMode::MerkleTree => FullHasherState::MerkleTree(Vec::new()),
+        };
+        Self { algorithm, state }
+    }
+
+    fn parent(algorithm: HashAlgorithm, left: &TiFsHash, right: &TiFsHash) -> TiFsHash {
+        let mut hasher = algorithm.streaming_hasher();
+        hasher.update(&[PARENT_NODE_PREFIX]);
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize()
+    }
+
+    pub fn push_block_hash(&mut self, block_hash: &[u8]) {
+        if let FullHasherState::Flat(hasher) = &mut self.state {
+            hasher.update(block_hash);
+        } else {
+            self.push_subtree_root(0, block_hash);
+        }
+    }
+
+    /// Adds the root of a complete subtree with 2^height blocks in merkle tree mode.
+    /// The blocks before it must be a multiple of 2^height.
+    pub fn push_subtree_root(&mut self, height: u32, root: &[u8]) {
+        match &mut self.state {
+            FullHasherState::Flat(_) => panic!("flat full hash has no subtrees"),
+            FullHasherState::MerkleTree(stack) => {
+                debug_assert!(stack.last().map(|(h, _)| *h >= height).unwrap_or(true));
+                stack.push((height, root.to_vec()));
+                while stack.len() >= 2 && stack[stack.len() - 1].0 == stack[stack.len() - 2].0 {
+                    let (height, right) = stack.pop().unwrap();
+                    let (_, left) = stack.pop().unwrap();
+                    stack.push((height + 1, Self::parent(self.algorithm, &left, &right)));
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> TiFsHash {
+        match self.state {
+            FullHasherState::Flat(hasher) => hasher.finalize(),
+            FullHasherState::MerkleTree(mut stack) => {
+                let Some((_, mut root)) = stack.pop() else {
+                    return self.algorithm.calculate_hash(&[]);
+                };
+                while let Some((_, left)) = stack.pop() {
+                    root = Self::parent(self.algorithm, &left, &root);
+                }
+                root
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::inode::InoFullHash;
+
+    #[test]
+    fn full_hash_of_flat_and_merkle_tree_mode() {
+        let algo = HashAlgorithm::Blake3;
+        let leaves = (0u8..3).map(|i| algo.calculate_hash(&[i])).collect::<Vec<_>>();
+
+        let mut flat = FullHasher::new(algo, FullHashMode::Flat);
+        leaves.iter().for_each(|l| flat.push_block_hash(l));
+        assert_eq!(flat.finish(), algo.calculate_hash(&leaves.concat()));
+
+        let mut tree = FullHasher::new(algo, FullHashMode::MerkleTree);
+        leaves.iter().for_each(|l| tree.push_block_hash(l));
+        let left = FullHasher::parent(algo, &leaves[0], &leaves[1]);
+        assert_eq!(tree.finish(), FullHasher::parent(algo, &left, &leaves[2]));
+
+        let mut single = FullHasher::new(algo, FullHashMode::MerkleTree);
+        single.push_block_hash(&leaves[0]);
+        assert_eq!(single.finish(), leaves[0]);
+
+        let mut pair = FullHasher::new(algo, FullHashMode::MerkleTree);
+        leaves[..2].iter().for_each(|l| pair.push_block_hash(l));
+        let mut segmented = FullHasher::new(algo, FullHashMode::MerkleTree);
+        segmented.push_subtree_root(1, &pair.finish());
+        segmented.push_block_hash(&leaves[2]);
+        assert_eq!(segmented.finish(), FullHasher::parent(algo, &left, &leaves[2]));
+    }
+
+    #[test]
+    fn stored_full_hash_of_older_versions_stays_readable() {
+        let legacy: InoFullHash = serde_json::from_slice(b"[1,2,3]").unwrap();
+        assert_eq!(legacy, InoFullHash { hash: vec![1, 2, 3], mode: FullHashMode::Flat, version: 0 });
+        assert!(legacy.is_valid_for(FullHashMode::Flat));
+        assert!(!legacy.is_valid_for(FullHashMode::MerkleTree));
+
+        let current = InoFullHash::new(vec![4], FullHashMode::MerkleTree);
+        let stored = serde_json::to_vec(&current).unwrap();
+        assert_eq!(serde_json::from_slice::<InoFullHash>(&stored).unwrap(), current);
+    }
+}
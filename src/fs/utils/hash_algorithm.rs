@@ -59,4 +59,37 @@ impl HashAlgorithm {
     pub fn to_string(&self) -> String {
         ALGO_NAME_MAP.get_by_right(self).unwrap().to_string()
     }
+
+    pub fn streaming_hasher(&self) -> StreamingHasher {
+        match self {
+            HashAlgorithm::Blake3 => StreamingHasher::Blake3(blake3::Hasher::new()),
+            HashAlgorithm::Sha256 => StreamingHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => StreamingHasher::Sha512(Sha512::new()),
+        }
+    }
+}
+
+/// Computes the same hash as calculate_hash for input that is provided in pieces.
+pub enum StreamingHasher {
+    Blake3(blake3::Hasher),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl StreamingHasher {
+    pub fn update(&mut self, input: &[u8]) {
+        match self {
+            StreamingHasher::Blake3(h) => { h.update(input); }
+            StreamingHasher::Sha256(h) => h.update(input),
+            StreamingHasher::Sha512(h) => h.update(input),
+        }
+    }
+
+    pub fn finalize(self) -> TiFsHash {
+        match self {
+            StreamingHasher::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            StreamingHasher::Sha256(h) => h.finalize().to_vec(),
+            StreamingHasher::Sha512(h) => h.finalize().to_vec(),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 pub mod stop_watch;
 pub mod hash_algorithm;
+pub mod full_hash;
 pub mod common_prints;
 pub mod auto_commit_txn;
 pub mod txn_data_cache;
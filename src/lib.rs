@@ -54,7 +54,9 @@ where
 
     make_daemon()?;
 
-    fuser::mount2(AsyncFs(fs_impl.clone()), mount_point, &fuse_options)?;
+    let mut session = fuser::Session::new(AsyncFs(fs_impl.clone()), mount_point, &fuse_options)?;
+    fs_impl.set_kernel_notifier(session.notifier());
+    session.run()?;
 
     Ok(())
 }